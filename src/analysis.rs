@@ -0,0 +1,341 @@
+//! Checking single packages on demand and explaining how each one was classified, for when
+//! someone wants to know about a package without adding it to `packages.txt`.
+
+use crate::{
+    check::{check_package, http_client},
+    Cache, Error, Package, PackageReport, PackageSpec, PackageStatus, Source,
+};
+use futures::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// The classification of a package along with what it was made from: the conditions each
+/// subpath of `exports` offers and the reasons for the status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    /// What was asked for, such as `chalk` or `chalk@4`.
+    pub requested: String,
+    pub status: PackageStatus,
+    pub package: Package,
+    /// The subpaths of `exports`, `.` alone when it maps the main entry point only.
+    pub subpaths: Vec<Subpath>,
+    pub reasons: Vec<String>,
+}
+
+/// One subpath of `exports`, such as `.` or `./package.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Subpath {
+    pub path: String,
+    /// Every condition used for the subpath, in the order they're declared, which is the order
+    /// Node tries them in.
+    pub conditions: Vec<String>,
+    pub targets: Vec<Target>,
+    /// Whether any target is behind a `require` condition.
+    pub require: bool,
+    /// Whether any target is behind an `import` condition.
+    pub import: bool,
+}
+
+/// A file `exports` resolves to, and the conditions leading to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Target {
+    pub conditions: Vec<String>,
+    /// The file, or `None` when the subpath is blocked with `null`.
+    pub path: Option<String>,
+}
+
+impl Analysis {
+    pub fn new(report: PackageReport) -> Self {
+        let package = report.package;
+        let subpaths = package
+            .manifest
+            .exports
+            .as_ref()
+            .map(subpaths)
+            .unwrap_or_default();
+
+        Analysis {
+            requested: report.spec.to_string(),
+            status: package.status(),
+            reasons: reasons(&package, &subpaths),
+            subpaths,
+            package,
+        }
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = self.package.version.as_deref().unwrap_or("?");
+        writeln!(f, "{}@{}: {}", self.package.name, version, self.status)?;
+        if let Some(message) = &self.package.deprecated {
+            writeln!(f, "  deprecated: {}", message)?;
+        }
+
+        if !self.subpaths.is_empty() {
+            writeln!(f, "  exports:")?;
+        }
+        for subpath in &self.subpaths {
+            writeln!(f, "    {}", subpath.path)?;
+            for target in &subpath.targets {
+                let conditions = match target.conditions.is_empty() {
+                    true => String::from("(any)"),
+                    false => target.conditions.join(" > "),
+                };
+                let path = target.path.as_deref().unwrap_or("null (blocked)");
+                writeln!(f, "      {} -> {}", conditions, path)?;
+            }
+        }
+
+        writeln!(f, "  reasons:")?;
+        for reason in &self.reasons {
+            writeln!(f, "    - {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches, classifies and explains each of `specs` from `source`, in the same order.
+pub async fn analyze_packages(
+    specs: &[PackageSpec],
+    source: &Source,
+) -> Vec<Result<Analysis, Error>> {
+    let client = http_client();
+    let checks = specs.iter().map(|spec| {
+        check_package(
+            client.clone(),
+            source.clone(),
+            Cache::Disabled,
+            spec.clone(),
+        )
+    });
+
+    join_all(checks)
+        .await
+        .into_iter()
+        .map(|report| report.map(Analysis::new))
+        .collect()
+}
+
+/// Splits `exports` into its subpaths. Without any key starting with `.`, the whole field
+/// describes the main entry point.
+fn subpaths(exports: &Value) -> Vec<Subpath> {
+    match exports {
+        Value::Object(map) if map.keys().any(|key| key.starts_with('.')) => map
+            .iter()
+            .map(|(path, value)| Subpath::new(path, value))
+            .collect(),
+        value => vec![Subpath::new(".", value)],
+    }
+}
+
+impl Subpath {
+    fn new(path: &str, value: &Value) -> Self {
+        let mut targets = vec![];
+        collect_targets(value, &mut vec![], &mut targets);
+
+        let mut conditions: Vec<String> = vec![];
+        for condition in targets.iter().flat_map(|t| &t.conditions) {
+            if !conditions.contains(condition) {
+                conditions.push(condition.clone());
+            }
+        }
+        let has = |name: &str| conditions.iter().any(|c| c == name);
+
+        Subpath {
+            path: path.to_owned(),
+            require: has("require"),
+            import: has("import"),
+            conditions,
+            targets,
+        }
+    }
+}
+
+/// Walks nested conditions and fallback arrays down to the files they resolve to.
+fn collect_targets(value: &Value, conditions: &mut Vec<String>, targets: &mut Vec<Target>) {
+    match value {
+        Value::String(path) => targets.push(Target {
+            conditions: conditions.clone(),
+            path: Some(path.clone()),
+        }),
+        Value::Null => targets.push(Target {
+            conditions: conditions.clone(),
+            path: None,
+        }),
+        Value::Array(fallbacks) => {
+            for fallback in fallbacks {
+                collect_targets(fallback, conditions, targets);
+            }
+        }
+        Value::Object(map) => {
+            for (condition, value) in map {
+                conditions.push(condition.clone());
+                collect_targets(value, conditions, targets);
+                conditions.pop();
+            }
+        }
+        // Not valid in `exports`, and Node ignores them
+        Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Why `package` got its status, one field at a time and then the conclusion.
+fn reasons(package: &Package, subpaths: &[Subpath]) -> Vec<String> {
+    let manifest = &package.manifest;
+    let mut reasons = vec![];
+
+    reasons.push(match &manifest.package_type {
+        _ if package.type_module => {
+            String::from("`type` is `module`, so its `.js` files are ES modules")
+        }
+        Some(package_type) => format!(
+            "`type` is {}, so its `.js` files are CommonJS",
+            package_type
+        ),
+        None => String::from("there's no `type` field, so its `.js` files are CommonJS"),
+    });
+
+    let requirable: Vec<String> = subpaths
+        .iter()
+        .filter(|s| s.require)
+        .map(|s| format!("`{}`", s.path))
+        .collect();
+    match &manifest.exports {
+        None => {
+            let main = manifest
+                .main
+                .as_ref()
+                .map_or_else(|| String::from("\"index.js\""), Value::to_string);
+            reasons.push(format!(
+                "there's no `exports` field, so Node loads `main` ({})",
+                main
+            ));
+            if let Some(module) = &manifest.module {
+                reasons.push(format!(
+                    "`module` ({}) is only read by bundlers, so it doesn't count towards ESM support",
+                    module
+                ));
+            }
+        }
+        Some(_) if !requirable.is_empty() => reasons.push(format!(
+            "`exports` has a `require` condition for {}",
+            requirable.join(", ")
+        )),
+        // The classification looks for the text `require` anywhere in `exports`
+        Some(_) if package.exports_require => reasons.push(String::from(
+            "`require` appears in `exports` without being a condition, which still counts as one",
+        )),
+        Some(_) => reasons.push(String::from("`exports` has no `require` condition")),
+    }
+
+    reasons.push(match package.status() {
+        PackageStatus::Dual => String::from(
+            "so it's dual: `exports` offers a `require` condition alongside its ESM entry points",
+        ),
+        PackageStatus::EsmOnly if package.exports_no_require => {
+            String::from("so it's ESM only: `exports` doesn't offer a `require` condition")
+        }
+        PackageStatus::EsmOnly => {
+            String::from("so it's ESM only: `type` is `module` and there's no `exports` field")
+        }
+        PackageStatus::Cjs => {
+            String::from("so it's CommonJS: it has neither `exports` nor `type: module`")
+        }
+    });
+
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManifestFields;
+    use serde_json::json;
+
+    fn analyze(manifest: Value) -> Analysis {
+        let package = Package {
+            name: String::from("pkg"),
+            exports_require: manifest["exports"].to_string().contains("require"),
+            exports_no_require: manifest.get("exports").is_some()
+                && !manifest["exports"].to_string().contains("require"),
+            type_module: manifest["type"] == "module",
+            manifest: ManifestFields::from_manifest(&manifest),
+            ..Package::default()
+        };
+        Analysis::new(PackageReport {
+            spec: PackageSpec::from("pkg"),
+            version: None,
+            package,
+        })
+    }
+
+    #[test]
+    fn test_subpaths() {
+        let analysis = analyze(json!({
+            "exports": {
+                ".": {
+                    "node": { "import": "./index.mjs", "require": "./index.cjs" },
+                    "default": "./index.mjs"
+                },
+                "./internal/*": null,
+                "./package.json": "./package.json"
+            }
+        }));
+
+        assert_eq!(analysis.status, PackageStatus::Dual);
+        let main = &analysis.subpaths[0];
+        // `default` comes last, as declared, since Node only falls back to it
+        assert_eq!(
+            main.conditions,
+            vec!["node", "import", "require", "default"]
+        );
+        assert!(main.require && main.import);
+        assert_eq!(main.targets[2].conditions, vec!["default"]);
+        assert_eq!(
+            main.targets[1],
+            Target {
+                conditions: vec![String::from("node"), String::from("require")],
+                path: Some(String::from("./index.cjs")),
+            }
+        );
+        assert_eq!(analysis.subpaths[1].targets[0].path, None);
+        assert!(!analysis.subpaths[2].require);
+        assert_eq!(
+            analysis.reasons[1],
+            "`exports` has a `require` condition for `.`"
+        );
+    }
+
+    #[test]
+    fn test_reasons() {
+        let sugar = analyze(json!({ "type": "module", "exports": "./index.js" }));
+        assert_eq!(sugar.subpaths[0].path, ".");
+        assert_eq!(
+            sugar.subpaths[0].targets[0].conditions,
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            sugar.reasons,
+            vec![
+                "`type` is `module`, so its `.js` files are ES modules",
+                "`exports` has no `require` condition",
+                "so it's ESM only: `exports` doesn't offer a `require` condition",
+            ]
+        );
+
+        let cjs = analyze(json!({ "main": "./lib.js", "module": "./lib.mjs" }));
+        assert!(cjs.subpaths.is_empty());
+        assert_eq!(cjs.status, PackageStatus::Cjs);
+        assert_eq!(
+            cjs.reasons[1],
+            "there's no `exports` field, so Node loads `main` (\"./lib.js\")"
+        );
+        assert_eq!(cjs.reasons.len(), 4);
+
+        let misleading = analyze(json!({ "exports": "./require.js" }));
+        assert_eq!(misleading.status, PackageStatus::Dual);
+        assert!(misleading.reasons[1].contains("without being a condition"));
+    }
+}
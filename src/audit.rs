@@ -0,0 +1,537 @@
+use crate::{check::publish_time, Error, Inactive, Package, PackageStatus, Source};
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// A change to one of the classification fields of a package, or to whether it is deprecated or
+/// still checked at all (see [`LIFECYCLE_CHANGES`]).
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub package_name: String,
+    pub timestamp: String,
+    pub change: String,
+    pub old_value: bool,
+    pub new_value: bool,
+    /// The registry changes feed sequence of the publish which caused the change, when it was
+    /// picked up by following the feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// The key of the entry in the audit table, the package name followed by a random id. Entries
+    /// are given one when they're first written to DynamoDB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_name_id: Option<String>,
+    /// The version which was stored before the change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_version: Option<String>,
+    /// The version which caused the change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_version: Option<String>,
+    /// When `new_version` was published, if the registry document said.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// The `type`, `exports`, `main` and `module` fields which differ between the two versions.
+    /// Empty when the old manifest wasn't recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manifest_diff: Vec<FieldChange>,
+    /// The status of the package before the change. Entries recorded before statuses were
+    /// tracked don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_status: Option<PackageStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_status: Option<PackageStatus>,
+    /// The npm deprecation message, on `deprecated` entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Which way a change moved a package, judged by who can still consume it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transition {
+    /// The package started shipping ESM.
+    Adoption,
+    /// The package stopped shipping ESM, or dropped its `require` condition and so broke CommonJS
+    /// consumers.
+    Regression,
+    /// Neither, such as an ESM only package adding a `require` condition back.
+    Neutral,
+}
+
+impl Transition {
+    pub fn classify(old: PackageStatus, new: PackageStatus) -> Self {
+        use PackageStatus::*;
+
+        match (old, new) {
+            (Cjs, Dual) | (Cjs, EsmOnly) => Transition::Adoption,
+            (Dual, Cjs) | (EsmOnly, Cjs) | (Dual, EsmOnly) => Transition::Regression,
+            _ => Transition::Neutral,
+        }
+    }
+}
+
+impl FromStr for Transition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adoption" => Ok(Transition::Adoption),
+            "regression" => Ok(Transition::Regression),
+            "neutral" => Ok(Transition::Neutral),
+            _ => Err(Error::Config(format!(
+                "unknown transition `{}`, expected adoption, regression or neutral",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transition::Adoption => write!(f, "adoption"),
+            Transition::Regression => write!(f, "regression"),
+            Transition::Neutral => write!(f, "neutral"),
+        }
+    }
+}
+
+/// A `package.json` field which changed between two versions, `None` where it was absent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+impl AuditEntry {
+    /// How the change moved the package as a whole, when both statuses were recorded.
+    pub fn transition(&self) -> Option<Transition> {
+        Some(Transition::classify(self.old_status?, self.new_status?))
+    }
+
+    /// The existing `package_name_id` of the entry, or a newly generated one.
+    pub fn key(&self) -> String {
+        self.package_name_id
+            .clone()
+            .unwrap_or_else(|| format!("{}{}", self.package_name, uuid::Uuid::new_v4().to_simple()))
+    }
+}
+
+impl From<HashMap<String, AttributeValue>> for AuditEntry {
+    fn from(map: HashMap<String, AttributeValue>) -> Self {
+        let string = |key: &str| map.get(key).and_then(|v| v.as_s().ok()).cloned();
+        let boolean = |key: &str| map.get(key).and_then(|v| v.as_bool().ok()).copied();
+
+        AuditEntry {
+            package_name: string("package_name").unwrap_or_default(),
+            timestamp: string("timestamp").unwrap_or_default(),
+            change: string("change").unwrap_or_default(),
+            old_value: boolean("old_value").unwrap_or_default(),
+            new_value: boolean("new_value").unwrap_or_default(),
+            seq: map
+                .get("seq")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok()),
+            package_name_id: string("package_name_id"),
+            old_version: string("old_version"),
+            new_version: string("new_version"),
+            published: string("published"),
+            manifest_diff: string("manifest_diff")
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            old_status: string("old_status").and_then(|s| s.parse().ok()),
+            new_status: string("new_status").and_then(|s| s.parse().ok()),
+            message: string("message"),
+        }
+    }
+}
+
+/// The classification fields audit entries can record a change to.
+pub const AUDITED_FIELDS: [&str; 3] = ["type_module", "exports_require", "exports_no_require"];
+
+/// The other changes audit entries record: `deprecated` when the npm deprecation flag of the
+/// package is set or cleared, and `removed` or `unpublished` when it stops (or starts again)
+/// being checked for that reason.
+pub const LIFECYCLE_CHANGES: [&str; 3] = ["deprecated", "removed", "unpublished"];
+
+/// Which audit entries to look up. Every criterion which is set has to match.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditQuery {
+    pub package: Option<String>,
+    /// The first day to include (`%Y-%m-%d`).
+    pub from: Option<String>,
+    /// The last day to include (`%Y-%m-%d`).
+    pub to: Option<String>,
+    /// One of [`AUDITED_FIELDS`] or [`LIFECYCLE_CHANGES`].
+    pub change: Option<String>,
+    pub transition: Option<Transition>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, audit: &AuditEntry) -> bool {
+        self.package
+            .as_ref()
+            .is_none_or(|p| *p == audit.package_name)
+            && self
+                .from
+                .as_ref()
+                .is_none_or(|from| audit.timestamp >= *from)
+            && self.to.as_ref().is_none_or(|to| audit.timestamp <= *to)
+            && self.change.as_ref().is_none_or(|c| *c == audit.change)
+            && self
+                .transition
+                .is_none_or(|t| audit.transition() == Some(t))
+    }
+}
+
+fn manifest_diff(old_pkg: &Package, pkg: &Package) -> Vec<FieldChange> {
+    // Rows stored before versions were recorded don't have a manifest to compare with either
+    if old_pkg.version.is_none() {
+        return vec![];
+    }
+
+    old_pkg
+        .manifest
+        .diff(&pkg.manifest)
+        .into_iter()
+        .map(|(field, old, new)| FieldChange {
+            field: field.to_owned(),
+            old,
+            new,
+        })
+        .collect()
+}
+
+/// Compares the stored state of a package with a fresh classification, returning whether the
+/// stored state needs updating along with an audit entry for every field which changed.
+pub fn diff_packages(old_pkg: &Package, pkg: &Package, date: &str) -> (bool, Vec<AuditEntry>) {
+    let mut should_update = old_pkg.version != pkg.version
+        || old_pkg.published != pkg.published
+        || old_pkg.manifest != pkg.manifest
+        || old_pkg.deprecated != pkg.deprecated
+        || old_pkg.inactive != pkg.inactive;
+    let mut audits: Vec<AuditEntry> = Vec::new();
+
+    // Every entry from this comparison shares the versions and manifest diff
+    let base = AuditEntry {
+        package_name: pkg.name.clone(),
+        timestamp: date.to_owned(),
+        old_version: old_pkg.version.clone(),
+        new_version: pkg.version.clone(),
+        published: pkg.published.clone(),
+        manifest_diff: manifest_diff(old_pkg, pkg),
+        old_status: Some(old_pkg.status()),
+        new_status: Some(pkg.status()),
+        ..AuditEntry::default()
+    };
+
+    if old_pkg.type_module != pkg.type_module {
+        should_update = true;
+
+        let audit_entry = AuditEntry {
+            change: String::from("type_module"),
+            old_value: old_pkg.type_module,
+            new_value: pkg.type_module,
+            ..base.clone()
+        };
+
+        audits.push(audit_entry);
+    }
+    if old_pkg.exports_require != pkg.exports_require {
+        should_update = true;
+
+        let audit_entry = AuditEntry {
+            change: String::from("exports_require"),
+            old_value: old_pkg.exports_require,
+            new_value: pkg.exports_require,
+            ..base.clone()
+        };
+        audits.push(audit_entry);
+    }
+    if old_pkg.exports_no_require != pkg.exports_no_require {
+        should_update = true;
+
+        let audit_entry = AuditEntry {
+            change: String::from("exports_no_require"),
+            old_value: old_pkg.exports_no_require,
+            new_value: pkg.exports_no_require,
+            ..base.clone()
+        };
+        audits.push(audit_entry);
+    }
+    if old_pkg.deprecated.is_some() != pkg.deprecated.is_some() {
+        audits.push(AuditEntry {
+            change: String::from("deprecated"),
+            old_value: old_pkg.deprecated.is_some(),
+            new_value: pkg.deprecated.is_some(),
+            message: pkg.deprecated.clone(),
+            ..base.clone()
+        });
+    }
+    // A package which was checked again is back, such as one republished after an unpublish
+    if let (Some(reason), None) = (old_pkg.inactive, pkg.inactive) {
+        audits.push(AuditEntry {
+            change: reason.to_string(),
+            old_value: true,
+            new_value: false,
+            ..base
+        });
+    }
+
+    (should_update, audits)
+}
+
+/// Fills in when the new version of `pkg` was published, on it and on its `audits`. Sources which
+/// serve single version manifests don't say, so it's looked up in the registry document of
+/// `source` once a package has changes worth recording.
+pub async fn add_publish_time(source: &Source, pkg: &mut Package, audits: &mut [AuditEntry]) {
+    if audits.is_empty() || pkg.published.is_some() {
+        return;
+    }
+
+    match publish_time(source, pkg).await {
+        Ok(published) => pkg.published = published,
+        Err(e) => eprintln!("Failed to look up when {} was published: {}", pkg.name, e),
+    }
+    for audit in audits {
+        audit.published = pkg.published.clone();
+    }
+}
+
+/// Marks a stored package as inactive for `reason`, returning the row to store along with an
+/// audit entry recording it. Returns `None` when the package was already inactive for `reason`.
+pub fn deactivate_package(
+    old_pkg: &Package,
+    reason: Inactive,
+    date: &str,
+) -> Option<(Package, AuditEntry)> {
+    if old_pkg.inactive == Some(reason) {
+        return None;
+    }
+
+    let pkg = Package {
+        inactive: Some(reason),
+        ..old_pkg.clone()
+    };
+    let audit = AuditEntry {
+        package_name: pkg.name.clone(),
+        timestamp: date.to_owned(),
+        change: reason.to_string(),
+        old_value: false,
+        new_value: true,
+        old_version: old_pkg.version.clone(),
+        new_version: old_pkg.version.clone(),
+        old_status: Some(old_pkg.status()),
+        new_status: Some(old_pkg.status()),
+        ..AuditEntry::default()
+    };
+
+    Some((pkg, audit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ManifestFields;
+
+    #[test]
+    fn test_update_type_module() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: false,
+            type_module: false,
+            ..Package::default()
+        };
+        let new_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: false,
+            type_module: true,
+            ..Package::default()
+        };
+
+        let result = diff_packages(&old_pkg, &new_pkg, "2022-01-01");
+
+        assert!(result.0);
+        assert_eq!(result.1.len(), 1);
+        assert_eq!(result.1[0].change, String::from("type_module"));
+    }
+
+    #[test]
+    fn test_multiple() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: false,
+            type_module: false,
+            ..Package::default()
+        };
+        let new_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: true,
+            type_module: true,
+            ..Package::default()
+        };
+
+        let result = diff_packages(&old_pkg, &new_pkg, "2022-01-01");
+
+        assert!(result.0);
+        assert_eq!(result.1.len(), 2);
+    }
+
+    #[test]
+    fn test_no_change() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: false,
+            type_module: false,
+            ..Package::default()
+        };
+        let new_pkg = Package {
+            name: String::from("test-package"),
+            exports_no_require: false,
+            exports_require: false,
+            type_module: false,
+            ..Package::default()
+        };
+
+        let result = diff_packages(&old_pkg, &new_pkg, "2022-01-01");
+
+        assert!(!result.0);
+        assert_eq!(result.1.len(), 0);
+    }
+
+    #[test]
+    fn test_audit_query_matches() {
+        let audit = AuditEntry {
+            package_name: String::from("chalk"),
+            timestamp: String::from("2022-01-15"),
+            change: String::from("exports_no_require"),
+            ..AuditEntry::default()
+        };
+        let query = |package: Option<&str>, from: Option<&str>, to: Option<&str>| AuditQuery {
+            package: package.map(String::from),
+            from: from.map(String::from),
+            to: to.map(String::from),
+            ..AuditQuery::default()
+        };
+
+        assert!(AuditQuery::default().matches(&audit));
+        assert!(query(Some("chalk"), Some("2022-01-15"), Some("2022-01-15")).matches(&audit));
+        assert!(!query(Some("chalk-template"), None, None).matches(&audit));
+        assert!(!query(None, Some("2022-01-16"), None).matches(&audit));
+        assert!(!query(None, None, Some("2022-01-14")).matches(&audit));
+        assert!(!AuditQuery {
+            change: Some(String::from("type_module")),
+            ..AuditQuery::default()
+        }
+        .matches(&audit));
+    }
+
+    #[test]
+    fn test_versions_and_manifest_diff() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            version: Some(String::from("1.0.0")),
+            manifest: ManifestFields {
+                main: Some(serde_json::json!("index.js")),
+                ..ManifestFields::default()
+            },
+            ..Package::default()
+        };
+        let new_pkg = Package {
+            name: String::from("test-package"),
+            type_module: true,
+            version: Some(String::from("2.0.0")),
+            published: Some(String::from("2022-01-10T12:00:00.000Z")),
+            manifest: ManifestFields {
+                package_type: Some(serde_json::json!("module")),
+                main: Some(serde_json::json!("index.js")),
+                ..ManifestFields::default()
+            },
+            ..Package::default()
+        };
+
+        let (_, audits) = diff_packages(&old_pkg, &new_pkg, "2022-01-11");
+
+        assert_eq!(audits[0].old_version.as_deref(), Some("1.0.0"));
+        assert_eq!(audits[0].new_version.as_deref(), Some("2.0.0"));
+        assert_eq!(
+            audits[0].published.as_deref(),
+            Some("2022-01-10T12:00:00.000Z")
+        );
+        assert_eq!(
+            audits[0].manifest_diff,
+            vec![FieldChange {
+                field: String::from("type"),
+                old: None,
+                new: Some(serde_json::json!("module")),
+            }]
+        );
+
+        // A new version without a classification change still needs storing
+        let bumped = Package {
+            version: Some(String::from("1.0.1")),
+            ..old_pkg.clone()
+        };
+        assert_eq!(
+            diff_packages(&old_pkg, &bumped, "2022-01-11"),
+            (true, vec![])
+        );
+    }
+
+    #[test]
+    fn test_deprecation_and_reactivation() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            inactive: Some(Inactive::Unpublished),
+            ..Package::default()
+        };
+        let new_pkg = Package {
+            name: String::from("test-package"),
+            deprecated: Some(String::from("use other-package instead")),
+            ..Package::default()
+        };
+
+        let (should_update, audits) = diff_packages(&old_pkg, &new_pkg, "2022-01-11");
+
+        assert!(should_update);
+        assert_eq!(audits.len(), 2);
+        assert_eq!(audits[0].change, "deprecated");
+        assert!(audits[0].new_value);
+        assert_eq!(
+            audits[0].message.as_deref(),
+            Some("use other-package instead")
+        );
+        assert_eq!(audits[1].change, "unpublished");
+        assert!(audits[1].old_value && !audits[1].new_value);
+        assert_eq!(audits[1].transition(), Some(Transition::Neutral));
+    }
+
+    #[test]
+    fn test_deactivate_package() {
+        let old_pkg = Package {
+            name: String::from("test-package"),
+            exports_require: true,
+            version: Some(String::from("1.0.0")),
+            ..Package::default()
+        };
+
+        let (pkg, audit) = deactivate_package(&old_pkg, Inactive::Removed, "2022-01-11").unwrap();
+
+        assert_eq!(pkg.inactive, Some(Inactive::Removed));
+        assert!(pkg.exports_require);
+        assert_eq!(audit.change, "removed");
+        assert!(!audit.old_value && audit.new_value);
+        assert_eq!(audit.old_version.as_deref(), Some("1.0.0"));
+        assert_eq!(audit.transition(), Some(Transition::Neutral));
+
+        // Already removed, so there's nothing more to record
+        assert!(deactivate_package(&pkg, Inactive::Removed, "2022-01-12").is_none());
+        assert!(deactivate_package(&pkg, Inactive::Unpublished, "2022-01-12").is_some());
+    }
+}
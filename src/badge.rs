@@ -0,0 +1,147 @@
+//! Shields style SVG badges showing how a package can be imported, such as `ESM | dual`.
+
+use crate::{notify::escape_html, Package, PackageStatus};
+
+const LABEL_COLOR: &str = "#555";
+/// Space on either side of each part's text.
+const PADDING: f64 = 5.0;
+
+/// A badge with an optional grey label on the left and a colored message on the right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    pub label: Option<String>,
+    pub message: String,
+    pub color: String,
+}
+
+impl Badge {
+    pub fn new(label: Option<&str>, message: impl Into<String>, color: impl Into<String>) -> Self {
+        Badge {
+            label: label.map(str::to_owned),
+            message: message.into(),
+            color: color.into(),
+        }
+    }
+
+    /// `ESM | esm-only`, `ESM | dual` or `CJS` from the stored classification, or the reason the
+    /// package is no longer checked.
+    pub fn for_package(pkg: &Package) -> Self {
+        if let Some(inactive) = pkg.inactive {
+            return Badge::new(Some("ESM"), inactive.to_string(), "#9f9f9f");
+        }
+
+        match pkg.status() {
+            PackageStatus::EsmOnly => Badge::new(Some("ESM"), "esm-only", "#4c1"),
+            PackageStatus::Dual => Badge::new(Some("ESM"), "dual", "#97ca00"),
+            PackageStatus::Cjs => Badge::new(None, "CJS", "#007ec6"),
+        }
+    }
+
+    /// For packages which haven't been checked.
+    pub fn unknown() -> Self {
+        Badge::new(Some("ESM"), "unknown", "#9f9f9f")
+    }
+
+    /// The badge in the flat style, 20 pixels high.
+    pub fn svg(&self) -> String {
+        let label_width = match &self.label {
+            Some(label) => text_width(label) + 2.0 * PADDING,
+            None => 0.0,
+        };
+        let message_width = text_width(&self.message) + 2.0 * PADDING;
+        let width = label_width + message_width;
+        let aria = match &self.label {
+            Some(label) => format!("{}: {}", label, self.message),
+            None => self.message.clone(),
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"20\" role=\"img\" aria-label=\"{aria}\"><title>{aria}</title><linearGradient id=\"s\" x2=\"0\" y2=\"100%\"><stop offset=\"0\" stop-color=\"#bbb\" stop-opacity=\".1\"/><stop offset=\"1\" stop-opacity=\".1\"/></linearGradient><clipPath id=\"r\"><rect width=\"{w}\" height=\"20\" rx=\"3\" fill=\"#fff\"/></clipPath><g clip-path=\"url(#r)\"><rect width=\"{lw}\" height=\"20\" fill=\"{LABEL_COLOR}\"/><rect x=\"{lw}\" width=\"{mw}\" height=\"20\" fill=\"{color}\"/><rect width=\"{w}\" height=\"20\" fill=\"url(#s)\"/></g><g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">",
+            w = width,
+            lw = label_width,
+            mw = message_width,
+            aria = escape_html(&aria),
+            color = escape_html(&self.color),
+        );
+        if let Some(label) = &self.label {
+            svg.push_str(&shadowed_text(label_width / 2.0, label));
+        }
+        svg.push_str(&shadowed_text(
+            label_width + message_width / 2.0,
+            &self.message,
+        ));
+        svg.push_str("</g></svg>\n");
+        svg
+    }
+}
+
+/// Text with a faint shadow a pixel below it, as shields draws it.
+fn shadowed_text(x: f64, text: &str) -> String {
+    let text = escape_html(text);
+    format!(
+        "<text x=\"{x}\" y=\"15\" fill=\"#010101\" fill-opacity=\".3\">{text}</text><text x=\"{x}\" y=\"14\">{text}</text>"
+    )
+}
+
+/// The width of `text` in 11px Verdana, rounded up to a whole pixel. There's no font to measure
+/// with, so characters are grouped into a few typical widths.
+fn text_width(text: &str) -> f64 {
+    let width: f64 = text
+        .chars()
+        .map(|c| match c {
+            'i' | 'j' | 'l' | 'I' | '.' | ',' | ':' | ';' | '|' | '!' | '\'' => 3.9,
+            'f' | 'r' | 't' | ' ' | '-' | '(' | ')' | '/' => 4.9,
+            'm' | 'w' | 'M' | 'W' => 10.5,
+            c if c.is_ascii_uppercase() => 7.5,
+            c if c.is_ascii_digit() => 7.0,
+            _ => 6.9,
+        })
+        .sum();
+    width.ceil()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Inactive;
+
+    #[test]
+    fn test_for_package() {
+        let pkg = |exports_require: bool, exports_no_require: bool| Package {
+            exports_require,
+            exports_no_require,
+            ..Package::default()
+        };
+
+        assert_eq!(
+            Badge::for_package(&pkg(true, false)),
+            Badge::new(Some("ESM"), "dual", "#97ca00")
+        );
+        assert_eq!(Badge::for_package(&pkg(false, true)).message, "esm-only");
+        assert_eq!(Badge::for_package(&pkg(false, false)).label, None);
+        assert_eq!(
+            Badge::for_package(&Package {
+                inactive: Some(Inactive::Unpublished),
+                ..pkg(true, false)
+            })
+            .message,
+            "unpublished"
+        );
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = Badge::new(Some("ESM"), "dual", "#97ca00").svg();
+
+        // 26px and 25px of text with 10px of padding each
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"71\" height=\"20\" role=\"img\" aria-label=\"ESM: dual\">"
+        ));
+        assert!(svg.contains("<rect x=\"36\" width=\"35\" height=\"20\" fill=\"#97ca00\"/>"));
+        assert!(svg.contains("<text x=\"18\" y=\"14\">ESM</text>"));
+
+        let cjs = Badge::new(None, "CJS", "#007ec6").svg();
+        assert!(cjs.contains("aria-label=\"CJS\""));
+        assert_eq!(cjs.matches("<text ").count(), 2);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs};
+use std::{cmp::Reverse, collections::HashMap, fs};
 
 fn main() {
     let registry_str = fs::read_to_string("registry.txt").unwrap();
@@ -18,11 +18,10 @@ fn main() {
 
     let mut prefixes: Vec<(&str, u32)> = hash.iter().map(|(&k, &v)| (k, v)).collect();
 
-    prefixes.sort_by(|a, b| b.1.cmp(&a.1));
+    prefixes.sort_by_key(|p| Reverse(p.1));
 
     println!("Top 10 prefixes with length {}", prefix_size);
-    for i in 0..10 {
-        let (prefix, prefix_count) = prefixes[i];
+    for (prefix, prefix_count) in prefixes.iter().take(10) {
         println!("prefixes[{}]: {}", prefix, prefix_count);
     }
 }
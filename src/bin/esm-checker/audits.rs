@@ -0,0 +1,96 @@
+use chrono::NaiveDate;
+use clap::Args;
+use esm_checker::{store, AuditQuery, Transition, AUDITED_FIELDS, LIFECYCLE_CHANGES};
+
+#[derive(Args, Debug)]
+pub struct AuditsArgs {
+    /// Only show changes to this package
+    #[clap(long)]
+    package: Option<String>,
+
+    /// First day to include (YYYY-MM-DD)
+    #[clap(long)]
+    from: Option<NaiveDate>,
+
+    /// Last day to include (YYYY-MM-DD)
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Only show changes to this field, or deprecations, removals and unpublishes
+    #[clap(long, possible_values = [AUDITED_FIELDS, LIFECYCLE_CHANGES].concat())]
+    change: Option<String>,
+
+    /// Only show adoptions, regressions or neutral changes
+    #[clap(long)]
+    transition: Option<Transition>,
+
+    /// Print the entries as JSON instead of a table
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(args: AuditsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let query = AuditQuery {
+        package: args.package,
+        from: args.from.map(|d| d.format("%F").to_string()),
+        to: args.to.map(|d| d.format("%F").to_string()),
+        change: args.change,
+        transition: args.transition,
+    };
+
+    let store = store::from_env().await?;
+    let audits = store.query_audits(&query).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&audits)?);
+        return Ok(());
+    }
+
+    if audits.is_empty() {
+        println!("No matching audit entries");
+        return Ok(());
+    }
+
+    let name_width = audits
+        .iter()
+        .map(|a| a.package_name.len())
+        .max()
+        .unwrap_or_default()
+        .max("package".len());
+    let version = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("?"));
+
+    println!(
+        "{:<10}  {:<name_width$}  {:<18}  {:<13}  {:<25}  fields",
+        "date",
+        "package",
+        "change",
+        "value",
+        "version",
+        name_width = name_width
+    );
+    for audit in &audits {
+        let fields: Vec<&str> = audit
+            .manifest_diff
+            .iter()
+            .map(|c| c.field.as_str())
+            .collect();
+
+        let line = format!(
+            "{:<10}  {:<name_width$}  {:<18}  {:<13}  {:<25}  {}",
+            audit.timestamp,
+            audit.package_name,
+            audit.change,
+            format!("{} -> {}", audit.old_value, audit.new_value),
+            format!(
+                "{} -> {}",
+                version(&audit.old_version),
+                version(&audit.new_version)
+            ),
+            fields.join(", "),
+            name_width = name_width
+        );
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
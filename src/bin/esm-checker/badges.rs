@@ -0,0 +1,48 @@
+use clap::Args;
+use esm_checker::{read_package_list, store, Badge};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct BadgesArgs {
+    /// Packages to write badges for
+    names: Vec<String>,
+
+    /// File listing more packages, like packages.txt. Every tracked package is used when no
+    /// names are given
+    #[clap(long)]
+    packages: Option<String>,
+
+    /// Directory the badges are written to, as `<name>.svg`
+    #[clap(long, default_value = "badges")]
+    out: PathBuf,
+}
+
+pub async fn run(args: BadgesArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut names = args.names;
+    if let Some(path) = &args.packages {
+        names.extend(read_package_list(path)?);
+    }
+
+    let store = store::from_env().await?;
+    let mut packages = store.packages().await?;
+    if names.is_empty() {
+        names = packages.keys().cloned().collect();
+        names.sort();
+    }
+
+    for name in &names {
+        let badge = match packages.remove(name) {
+            Some(pkg) => Badge::for_package(&pkg),
+            None => Badge::unknown(),
+        };
+        // Scoped packages end up in a directory per scope
+        let path = args.out.join(format!("{}.svg", name));
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, badge.svg())?;
+    }
+
+    println!("Wrote {} badges to {}", names.len(), args.out.display());
+    Ok(())
+}
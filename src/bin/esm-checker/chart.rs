@@ -0,0 +1,91 @@
+use chrono::{NaiveDate, Utc};
+use clap::Args;
+use esm_checker::{
+    stats_series, store, Chart, ChartKind, Error, GapPolicy, Metric, FIRST_STATS_DATE,
+};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ChartArgs {
+    /// The stats to plot
+    #[clap(
+        long = "metric",
+        default_values = &["type_module", "exports_require", "exports_no_require"]
+    )]
+    metrics: Vec<Metric>,
+
+    /// Draw a line per metric, or stack them as areas
+    #[clap(long, default_value = "line")]
+    kind: ChartKind,
+
+    /// First day to plot (YYYY-MM-DD)
+    #[clap(long, default_value = FIRST_STATS_DATE)]
+    from: NaiveDate,
+
+    /// Last day to plot (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    #[clap(long, default_value = "720")]
+    width: u32,
+
+    #[clap(long, default_value = "320")]
+    height: u32,
+
+    /// Heading of the chart, defaults to the metrics
+    #[clap(long)]
+    title: Option<String>,
+
+    /// Carry the previous day's stats into days without any
+    #[clap(long)]
+    fill: bool,
+
+    /// File the SVG is written to, instead of printing it
+    #[clap(long)]
+    out: Option<PathBuf>,
+}
+
+pub async fn run(args: ChartArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let percentages = args
+        .metrics
+        .iter()
+        .filter(|m| **m == Metric::Adoption)
+        .count();
+    if percentages > 0 && percentages < args.metrics.len() {
+        return Err(Error::Config(String::from(
+            "adoption is a percentage and can't share a chart with counts",
+        ))
+        .into());
+    }
+
+    let to = args.to.unwrap_or_else(|| Utc::now().naive_utc().date());
+    let gaps = if args.fill {
+        GapPolicy::Fill
+    } else {
+        GapPolicy::Flag
+    };
+
+    let store = store::from_env().await?;
+    let series = stats_series(store.as_ref(), args.from, to, gaps).await?;
+
+    let title = args.title.unwrap_or_else(|| {
+        let names: Vec<String> = args.metrics.iter().map(|m| m.to_string()).collect();
+        names.join(", ")
+    });
+    let mut chart = Chart::new(args.kind, title)
+        .size(args.width, args.height)
+        .metrics(&series, &args.metrics);
+    if percentages > 0 {
+        chart = chart.unit("%");
+    }
+
+    match args.out {
+        Some(path) => {
+            std::fs::write(&path, chart.svg())?;
+            println!("Wrote {}", path.display());
+        }
+        None => print!("{}", chart.svg()),
+    }
+
+    Ok(())
+}
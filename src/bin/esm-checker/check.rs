@@ -0,0 +1,46 @@
+use clap::Args;
+use esm_checker::{analyze_packages, PackageSpec, Source, NPM_REGISTRY_URL};
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Packages to check, as `name` or `name@version`
+    #[clap(required = true)]
+    packages: Vec<PackageSpec>,
+
+    /// The registry manifests are fetched from
+    #[clap(long, default_value = NPM_REGISTRY_URL)]
+    registry_url: String,
+
+    /// Print the classifications as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(args: CheckArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let results = analyze_packages(&args.packages, &Source::Registry(args.registry_url)).await;
+
+    let mut analyses = vec![];
+    let mut failures = vec![];
+    for (spec, result) in args.packages.iter().zip(results) {
+        match result {
+            Ok(analysis) => analyses.push(analysis),
+            Err(e) => {
+                eprintln!("failed to check {}: {}", spec, e);
+                failures.push(e);
+            }
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&analyses)?);
+    } else {
+        let printed: Vec<String> = analyses.iter().map(|a| a.to_string()).collect();
+        print!("{}", printed.join("\n"));
+    }
+
+    // Exit with an error when any package couldn't be checked, after printing the others
+    match failures.into_iter().next() {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
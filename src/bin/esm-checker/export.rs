@@ -0,0 +1,34 @@
+use clap::Args;
+use esm_checker::{store, Format, Snapshot};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Directory to write the files into
+    #[clap(long, default_value = "export")]
+    out: PathBuf,
+
+    /// Format to write, may be given more than once
+    #[clap(long = "format", default_values = &["csv", "jsonl", "parquet"])]
+    formats: Vec<Format>,
+}
+
+pub async fn run(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let store = store::from_env().await?;
+    let snapshot = Snapshot::from_store(store.as_ref()).await?;
+
+    println!(
+        "Exporting {} stats entries, {} packages and {} audit entries",
+        snapshot.stats.len(),
+        snapshot.packages.len(),
+        snapshot.audits.len()
+    );
+
+    for format in args.formats {
+        for path in snapshot.write(&args.out, format)? {
+            println!("Wrote {}", path.display());
+        }
+    }
+
+    Ok(())
+}
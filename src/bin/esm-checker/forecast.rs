@@ -0,0 +1,38 @@
+use chrono::{NaiveDate, Utc};
+use clap::Args;
+use esm_checker::{forecast_adoption, stats_series, store, GapPolicy, FIRST_STATS_DATE};
+
+#[derive(Args, Debug)]
+pub struct ForecastArgs {
+    /// First day of the history to fit (YYYY-MM-DD)
+    #[clap(long, default_value = FIRST_STATS_DATE)]
+    from: NaiveDate,
+
+    /// Last day of the history to fit (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Adoption percentages to project dates for
+    #[clap(long = "milestone", default_values = &["25", "50", "75"])]
+    milestones: Vec<f64>,
+
+    /// Print the fits and milestones as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(args: ForecastArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let to = args.to.unwrap_or_else(|| Utc::now().naive_utc().date());
+
+    let store = store::from_env().await?;
+    let series = stats_series(store.as_ref(), args.from, to, GapPolicy::Flag).await?;
+    let forecast = forecast_adoption(&series, &args.milestones);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&forecast)?);
+    } else {
+        println!("{}", forecast);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use clap::Args;
+use esm_checker::{import_snapshot, store, Snapshot};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Directory holding the `.jsonl` files written by `export`
+    #[clap(long, default_value = "export")]
+    from: PathBuf,
+
+    /// Validate the snapshot and report what would be written without writing anything
+    #[clap(long)]
+    dry_run: bool,
+}
+
+pub async fn run(args: ImportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = Snapshot::read(&args.from)?;
+
+    let store = store::from_env().await?;
+    let summary = import_snapshot(store.as_ref(), &snapshot, args.dry_run).await?;
+
+    if args.dry_run {
+        println!("Dry run, nothing was written");
+    }
+    println!("{}", summary);
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use clap::{Parser, Subcommand};
+
+mod audits;
+mod badges;
+mod chart;
+mod check;
+mod export;
+mod forecast;
+mod import;
+mod regressions;
+mod report;
+mod serve;
+mod trends;
+
+/// Query and report on the data collected by the other esm-checker tools.
+#[derive(Parser, Debug)]
+#[clap(name = "esm-checker", version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Fetch and classify packages now, explaining how each was classified
+    Check(check::CheckArgs),
+    /// Show how the stats moved over a range of days
+    Trends(trends::TrendsArgs),
+    /// Project when ESM adoption will reach milestone percentages
+    Forecast(forecast::ForecastArgs),
+    /// Plot the stats over a range of days as an SVG chart
+    Chart(chart::ChartArgs),
+    /// Dump the stats, packages and audit log to CSV, JSON Lines or Parquet files
+    Export(export::ExportArgs),
+    /// Load a JSON Lines export into the configured store
+    Import(import::ImportArgs),
+    /// Look up changes in the audit log by package, day or field
+    Audits(audits::AuditsArgs),
+    /// List packages which went back from ESM to CommonJS or dropped their require condition
+    Regressions(regressions::RegressionsArgs),
+    /// Compare the stats over a day, week, month, quarter or any range, and print or send it
+    Report(report::ReportArgs),
+    /// Write an SVG badge showing how each package can be imported
+    Badges(badges::BadgesArgs),
+    /// Serve the badges and a JSON API over the stats, packages and audit log
+    Serve(serve::ServeArgs),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv::dotenv().ok();
+
+    match Cli::parse().command {
+        Command::Check(args) => check::run(args).await,
+        Command::Trends(args) => trends::run(args).await,
+        Command::Forecast(args) => forecast::run(args).await,
+        Command::Chart(args) => chart::run(args).await,
+        Command::Export(args) => export::run(args).await,
+        Command::Import(args) => import::run(args).await,
+        Command::Audits(args) => audits::run(args).await,
+        Command::Regressions(args) => regressions::run(args).await,
+        Command::Report(args) => report::run(args).await,
+        Command::Badges(args) => badges::run(args).await,
+        Command::Serve(args) => serve::run(args).await,
+    }
+}
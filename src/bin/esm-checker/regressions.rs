@@ -0,0 +1,52 @@
+use chrono::NaiveDate;
+use clap::Args;
+use esm_checker::{find_regressions, store, AuditQuery, Transition};
+
+#[derive(Args, Debug)]
+pub struct RegressionsArgs {
+    /// First day to include (YYYY-MM-DD)
+    #[clap(long)]
+    from: Option<NaiveDate>,
+
+    /// Last day to include (YYYY-MM-DD)
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Print the regressions as JSON instead of a list
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(args: RegressionsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let query = AuditQuery {
+        from: args.from.map(|d| d.format("%F").to_string()),
+        to: args.to.map(|d| d.format("%F").to_string()),
+        transition: Some(Transition::Regression),
+        ..AuditQuery::default()
+    };
+
+    let store = store::from_env().await?;
+    let regressions = find_regressions(&store.query_audits(&query).await?);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&regressions)?);
+        return Ok(());
+    }
+
+    if regressions.is_empty() {
+        println!("No regressions");
+        return Ok(());
+    }
+
+    for regression in &regressions {
+        println!("{}", regression);
+    }
+    let breaking = regressions.iter().filter(|r| r.breaks_require()).count();
+    println!(
+        "{} regression(s), {} of which dropped the require condition",
+        regressions.len(),
+        breaking
+    );
+
+    Ok(())
+}
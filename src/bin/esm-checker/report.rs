@@ -0,0 +1,167 @@
+use chrono::{NaiveDate, Utc};
+use clap::{AppSettings, Args, Subcommand};
+use esm_checker::{
+    forecast_adoption, notify, stats_series, store, AuditQuery, ChannelTemplates, Error, GapPolicy,
+    HtmlReport, Period, ReportContext, StatsReport, FIRST_STATS_DATE,
+};
+use std::path::PathBuf;
+
+#[derive(Args, Debug)]
+#[clap(setting = AppSettings::ArgsNegateSubcommands)]
+pub struct ReportArgs {
+    #[clap(subcommand)]
+    format: Option<ReportFormat>,
+
+    /// How far back to compare: day, week, month, quarter or custom
+    #[clap(long, default_value = "week")]
+    period: Period,
+
+    /// First day of a custom period (YYYY-MM-DD)
+    #[clap(long, required_if_eq("period", "custom"))]
+    from: Option<NaiveDate>,
+
+    /// Last day of the period (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Include projected dates for adoption milestones
+    #[clap(long)]
+    forecast: bool,
+
+    /// First day of the history the forecast is fitted to (YYYY-MM-DD)
+    #[clap(long, default_value = FIRST_STATS_DATE)]
+    forecast_from: NaiveDate,
+
+    /// Adoption percentages to project dates for
+    #[clap(long = "milestone", default_values = &["25", "50", "75"])]
+    milestones: Vec<f64>,
+
+    /// Handlebars template to write the report with, as `<kind>=<file>` for one kind of channel
+    /// or `<file>` for every other channel and the printed report
+    #[clap(long = "template")]
+    templates: Vec<String>,
+
+    /// Send the report to the channels in ESM_CHECKER_NOTIFY instead of printing it
+    #[clap(long)]
+    send: bool,
+
+    /// Print the context templates are rendered with as JSON
+    #[clap(long, conflicts_with = "send")]
+    json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum ReportFormat {
+    /// Write a self-contained HTML page with charts, the package table and the audit timeline
+    Html(HtmlArgs),
+}
+
+#[derive(Args, Debug)]
+struct HtmlArgs {
+    /// File the page is written to
+    #[clap(long, default_value = "report.html")]
+    out: PathBuf,
+
+    /// First day of the charts and timeline (YYYY-MM-DD)
+    #[clap(long, default_value = FIRST_STATS_DATE)]
+    from: NaiveDate,
+
+    /// Last day of the charts and timeline (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Heading of the page
+    #[clap(long, default_value = "ESM Checker report")]
+    title: String,
+
+    /// Number of the most recent audit entries listed in the timeline
+    #[clap(long, default_value = "500")]
+    timeline_limit: usize,
+}
+
+pub async fn run(args: ReportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(ReportFormat::Html(html)) = args.format {
+        return run_html(html).await;
+    }
+
+    let to = args.to.unwrap_or_else(|| Utc::now().naive_utc().date());
+    let from = match (args.period.start(to), args.from) {
+        (Some(from), None) => from,
+        (None, Some(from)) => from,
+        _ => {
+            return Err(
+                Error::Config(String::from("--from can only be given for a custom period")).into(),
+            )
+        }
+    };
+    let templates = ChannelTemplates::from_args(&args.templates)?;
+
+    let store = store::from_env().await?;
+    let mut report = StatsReport::between(store.as_ref(), from, to)
+        .await?
+        .period(args.period);
+
+    if args.forecast {
+        let history = stats_series(store.as_ref(), args.forecast_from, to, GapPolicy::Flag).await?;
+        report = report.forecast(forecast_adoption(&history, &args.milestones));
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&ReportContext::new(&report))?
+        );
+        return Ok(());
+    }
+
+    if !args.send {
+        let message = match templates.fallback() {
+            Some(template) => template.message(&report)?,
+            None => report.message(),
+        };
+        println!("{}", message.text);
+        return Ok(());
+    }
+
+    let notifiers = notify::from_env()?;
+    let messages = templates.messages(&notifiers, &report)?;
+    let mut failures = notify::notify_each(&notifiers, &messages).await;
+    // Only fail the run when nobody got the report
+    if failures.len() == notifiers.len() {
+        return Err(failures.remove(0).1.into());
+    }
+    for (kind, e) in &failures {
+        eprintln!("failed to notify {}: {}", kind, e);
+    }
+
+    Ok(())
+}
+
+async fn run_html(args: HtmlArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    let to = args.to.unwrap_or_else(|| now.naive_utc().date());
+
+    let store = store::from_env().await?;
+    let series = stats_series(store.as_ref(), args.from, to, GapPolicy::Flag).await?;
+    let packages = store.packages().await?.into_values().collect();
+    let audits = store
+        .query_audits(&AuditQuery {
+            from: Some(args.from.format("%F").to_string()),
+            to: Some(to.format("%F").to_string()),
+            ..AuditQuery::default()
+        })
+        .await?;
+
+    let report = HtmlReport::new(
+        series,
+        packages,
+        audits,
+        now.format("%F %H:%M UTC").to_string(),
+    )
+    .title(args.title)
+    .timeline_limit(args.timeline_limit);
+    std::fs::write(&args.out, report.render())?;
+
+    println!("Wrote {}", args.out.display());
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use clap::Args;
+use esm_checker::{store, Server, Source, NPM_REGISTRY_URL};
+use std::{net::TcpListener, sync::Arc};
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+
+    /// The registry `/api/check` fetches manifests from
+    #[clap(long, default_value = NPM_REGISTRY_URL)]
+    registry_url: String,
+}
+
+pub async fn run(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let store = store::from_env().await?;
+    let listener = TcpListener::bind(&args.addr)?;
+
+    println!("Listening on http://{}", listener.local_addr()?);
+    Server::new(Arc::from(store))
+        .source(Source::Registry(args.registry_url))
+        .run(listener, async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use chrono::{NaiveDate, Utc};
+use clap::Args;
+use esm_checker::{stats_series, store, GapPolicy, Interval, Metric};
+use std::collections::HashMap;
+
+#[derive(Args, Debug)]
+pub struct TrendsArgs {
+    /// First day of the range (YYYY-MM-DD)
+    #[clap(long)]
+    from: NaiveDate,
+
+    /// Last day of the range (YYYY-MM-DD), defaults to today
+    #[clap(long)]
+    to: Option<NaiveDate>,
+
+    /// Compare each day with the day, week or month before it
+    #[clap(long, default_value = "day")]
+    interval: Interval,
+
+    /// The stat to compute a moving average of
+    #[clap(long, default_value = "adoption")]
+    metric: Metric,
+
+    /// Number of days in the moving average, which averages whichever of them have stats
+    #[clap(long, default_value = "7")]
+    window: usize,
+
+    /// Carry the previous day's stats into days without any
+    #[clap(long)]
+    fill: bool,
+
+    /// Print the series as JSON instead of a table
+    #[clap(long)]
+    json: bool,
+}
+
+pub async fn run(args: TrendsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let to = args.to.unwrap_or_else(|| Utc::now().naive_utc().date());
+    let gaps = if args.fill {
+        GapPolicy::Fill
+    } else {
+        GapPolicy::Flag
+    };
+
+    let store = store::from_env().await?;
+    let series = stats_series(store.as_ref(), args.from, to, gaps).await?;
+
+    let deltas = series.deltas(args.interval);
+    let moving_average = series.moving_average(args.metric, args.window);
+
+    if args.json {
+        let output = serde_json::json!({
+            "from": series.from,
+            "to": series.to,
+            "points": series.points,
+            "gaps": series.gaps,
+            "deltas": deltas,
+            "moving_average": {
+                "metric": args.metric.to_string(),
+                "window": args.window,
+                "values": moving_average
+                    .iter()
+                    .map(|(date, value)| serde_json::json!({ "date": date, "value": value }))
+                    .collect::<Vec<_>>(),
+            },
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    let deltas: HashMap<&str, _> = deltas.iter().map(|d| (d.timestamp.as_str(), d)).collect();
+    let averages: HashMap<NaiveDate, f64> = moving_average.into_iter().collect();
+
+    println!(
+        "{:<10}  {:>6}  {:>11}  {:>15}  {:>18}  {:>6}  {:>6}  {:>8}  {:>8}  {:>9}  {:>10}",
+        "date",
+        "total",
+        "type_module",
+        "exports_require",
+        "exports_no_require",
+        "dual",
+        "esm",
+        "adoption",
+        "Δ pp",
+        "Δ dual",
+        format!("avg {}", args.window),
+    );
+
+    for point in &series.points {
+        let entry = &point.entry;
+        let delta = deltas.get(entry.timestamp.as_str());
+        println!(
+            "{:<10}  {:>6}  {:>11}  {:>15}  {:>18}  {:>6}  {:>6}  {:>7.2}%  {:>8}  {:>9}  {:>10}{}",
+            entry.timestamp,
+            entry.total_packages,
+            entry.type_module,
+            entry.exports_require,
+            entry.exports_no_require,
+            entry.dual,
+            entry.esm_only,
+            entry.adoption(),
+            delta.map_or(String::from("-"), |d| format!("{:+.2}", d.adoption_pp)),
+            delta.map_or(String::from("-"), |d| format!("{:+}", d.dual)),
+            averages
+                .get(&point.date)
+                .map_or(String::from("-"), |v| format!("{:.2}", v)),
+            match (point.filled, entry.estimated) {
+                (true, _) => "  (filled)",
+                (false, true) => "  (estimated)",
+                (false, false) => "",
+            },
+        );
+    }
+
+    if !series.gaps.is_empty() {
+        println!();
+        println!(
+            "Missing stats for {} day(s): {}",
+            series.gaps.len(),
+            series
+                .gaps
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
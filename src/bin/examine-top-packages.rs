@@ -1,369 +1,177 @@
-use aws_sdk_dynamodb::model::AttributeValue;
-use aws_types::region::Region;
 use chrono::Utc;
-use clap::StructOpt;
-use esm_checker::{generate_packages, AuditEntry, Package};
-use std::collections::HashMap;
-
-#[derive(StructOpt, Debug)]
-#[structopt(name = "examine-top-packages")]
+use clap::Parser;
+use esm_checker::{
+    add_publish_time, deactivate_package, diff_packages, find_regressions, post_regressions,
+    read_package_list, send_watch_alerts, store, stream_packages, watchlist_notifiers,
+    CheckOptions, Error, Inactive, Package, Source, StatsEntry, Watchlist, NPM_REGISTRY_URL,
+};
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Parser, Debug)]
+#[clap(name = "examine-top-packages")]
 struct Opt {
     /// Use this flag to reduce the requests to the first 100
-    #[structopt(long)]
+    #[clap(long)]
     short: bool,
 
-    /// Publish stats to dynamo
-    #[structopt(long)]
+    /// Publish stats, packages and audits to the configured store (DynamoDB by default)
+    #[clap(long)]
     dynamo: bool,
+
+    /// Whitespace separated list of packages to send an alert for as soon as their status changes
+    #[clap(long)]
+    watchlist: Option<String>,
+
+    /// npm compatible registry to fetch manifests from. Unlike the `package.json` on a CDN, its
+    /// manifests include the deprecation message
+    #[clap(long, default_value = NPM_REGISTRY_URL)]
+    registry_url: String,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Opt::parse();
 
-    let mut packages = generate_packages(args.short).await?;
-    let all_packages = packages.clone();
-
-    let total_packages = packages.len();
-
-    packages = packages.into_iter().filter(|p| p.has_values()).collect();
-
-    let type_module_count = packages.iter().filter(|&p| p.type_module).count();
-
-    let require_count = packages.iter().filter(|&p| p.exports_require).count();
+    let watchlist = match &args.watchlist {
+        Some(path) => Watchlist::from_file(path)?,
+        None => Watchlist::default(),
+    };
+
+    let top_list = read_package_list("packages.txt")?;
+    let listed: HashSet<String> = top_list.iter().cloned().collect();
+    // Watched packages outside the top packages are checked too, but left out of the stats
+    let watched: Vec<String> = watchlist
+        .iter()
+        .filter(|p| !listed.contains(*p))
+        .cloned()
+        .collect();
+    let tracked: HashSet<String> = listed.iter().chain(&watched).cloned().collect();
+
+    // Shortened runs only check fewer top packages, watched ones are always checked
+    let mut package_list = top_list;
+    if args.short {
+        package_list.truncate(100);
+    }
+    let top_checked = package_list.len();
+    package_list.extend(watched);
+    let source = Source::Registry(args.registry_url.clone());
+    let options = CheckOptions::new(package_list).source(source.clone());
+
+    let mut all_packages: Vec<Package> = Vec::new();
+    let mut top_packages: Vec<Package> = Vec::new();
+    let mut unpublished: Vec<String> = Vec::new();
+
+    let mut reports = stream_packages(&options);
+    while let Some(report) = reports.next().await {
+        match report {
+            Ok(report) => {
+                if listed.contains(&report.spec.name) {
+                    top_packages.push(report.package.clone());
+                }
+                all_packages.push(report.package);
+            }
+            Err(Error::Unpublished { package }) => {
+                eprintln!("{} has been unpublished", package);
+                unpublished.push(package);
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
 
-    let exports_no_require = packages.iter().filter(|&p| p.exports_no_require).count();
+    let date = Utc::now().format("%F").to_string();
 
-    let date_time = Utc::now();
-    let date = date_time.format("%F").to_string();
-    let month_year_date = date_time.format("%Y-%m").to_string();
+    // Errors don't always say which package they were for, so the top packages which failed are
+    // the ones without a report. Failed watched packages aren't part of the stats.
+    let failures = top_checked - top_packages.len();
+    let stats = StatsEntry::from_packages(&date, &top_packages, failures);
 
-    println!("Total packages: {}", total_packages);
-    println!(
-        "Packages with a `type: module` field: {}",
-        type_module_count
-    );
-    println!("Packages with a `exports.require` field: {}", require_count);
-    println!(
-        "Packages without an explicit `exports.require` that may be ESM only: {}",
-        exports_no_require
-    );
+    println!("{}", stats);
 
     if args.dynamo {
-        let stats_table_name = std::env::var("DYNAMO_STATS_TABLE_NAME")
-            .expect("There should be a stats table name defined as an environment variable");
-        let package_table_name = std::env::var("DYNAMO_PACKAGE_TABLE_NAME")
-            .expect("There should be a pacakge table name defined as an environment variable");
-        let audit_table_name = std::env::var("DYNAMO_AUDIT_TABLE_NAME")
-            .expect("There should be an audit table name defined as an environment variable");
+        let store = store::from_env().await?;
 
-        let aws_region = std::env::var("AWS_REGION").ok().map(Region::new).unwrap();
-        let config = aws_config::from_env().region(aws_region).load().await;
-        let dynamo_client = aws_sdk_dynamodb::Client::new(&config);
-
-        put_stats(
-            &dynamo_client,
-            stats_table_name,
-            &month_year_date,
-            &date,
-            StatsObject {
-                total_packages,
-                type_module_count,
-                require_count,
-                exports_no_require,
-            },
-        )
-        .await;
+        if let Err(e) = store.put_stats(&stats).await {
+            eprintln!("err: {}", e);
+        }
 
-        let mut new_packages: Vec<Package> = Vec::new();
-        let package_table_map = grab_remote_packages(&dynamo_client, &package_table_name).await;
+        let package_table_map = store.packages().await?;
+        let mut written_audits = vec![];
+        let classified: HashMap<String, Package> = all_packages
+            .iter()
+            .map(|p| (p.name.clone(), p.clone()))
+            .collect();
 
         // Diff packages with their state in dynamo and update entries & create audit points if there are changes
-        for pkg in all_packages {
-            if package_table_map.contains_key(&pkg.name) {
-                let old_pkg = package_table_map.get(&pkg.name).unwrap();
-                let (should_update, mut local_audits) = diff_packages(old_pkg, &pkg, &date);
-
-                if should_update {
-                    update_package(&dynamo_client, &package_table_name, &pkg).await;
+        for mut pkg in all_packages {
+            let (should_update, mut audits) = match package_table_map.get(&pkg.name) {
+                Some(old_pkg) => diff_packages(old_pkg, &pkg, &date),
+                // Brand new packages get an entry in the package table without any audits
+                None => (true, vec![]),
+            };
+            add_publish_time(&source, &mut pkg, &mut audits).await;
+
+            if should_update {
+                if let Err(e) = store.put_package(&pkg).await {
+                    println!("err: {}", e);
                 }
-                while local_audits.len() > 0 {
-                    let audit = local_audits.pop().unwrap();
-                    put_audit(&dynamo_client, &audit_table_name, audit).await;
+            }
+            for audit in audits {
+                println!(
+                    "{}: change in {} {} to {}",
+                    audit.package_name, audit.change, audit.old_value, audit.new_value
+                );
+                if let Err(e) = store.put_audit(&audit).await {
+                    println!("err: {}", e);
                 }
-            } else {
-                new_packages.push(pkg);
+                written_audits.push(audit);
             }
         }
 
-        // Create entries for brand new packages in package table
-        for pkg in new_packages {
-            put_package(&dynamo_client, &package_table_name, pkg).await;
-        }
-    }
-
-    Ok(())
-}
-
-struct StatsObject {
-    total_packages: usize,
-    type_module_count: usize,
-    require_count: usize,
-    exports_no_require: usize,
-}
-
-async fn put_stats(
-    dynamo_client: &aws_sdk_dynamodb::Client,
-    stats_table_name: String,
-    month_year_date: &String,
-    date: &String,
-    stats: StatsObject,
-) {
-    let request = dynamo_client
-        .put_item()
-        .table_name(stats_table_name)
-        .item("year_month", AttributeValue::S(month_year_date.clone()))
-        .item("timestamp", AttributeValue::S(date.clone()))
-        .item(
-            "total_packages",
-            AttributeValue::N(stats.total_packages.to_string()),
-        )
-        .item(
-            "type_module",
-            AttributeValue::N(stats.type_module_count.to_string()),
-        )
-        .item(
-            "exports_require",
-            AttributeValue::N(stats.require_count.to_string()),
-        )
-        .item(
-            "exports_no_require",
-            AttributeValue::N(stats.exports_no_require.to_string()),
-        );
-    let r = request.send().await;
-    if r.is_err() {
-        eprintln!("err: {}", r.unwrap_err());
-    }
-}
-
-async fn grab_remote_packages(
-    dynamo_client: &aws_sdk_dynamodb::Client,
-    package_table_name: &String,
-) -> HashMap<String, Package> {
-    let scan_result = dynamo_client
-        .scan()
-        .table_name(package_table_name.clone())
-        .send()
-        .await
-        .unwrap();
-    let items = scan_result.items.unwrap();
-
-    let mut package_table_map: HashMap<String, Package> = HashMap::new();
-
-    for pkg_hash in items {
-        let pkg = Package::from(pkg_hash);
-        package_table_map.insert(pkg.name.clone(), pkg);
-    }
-    package_table_map
-}
-
-async fn put_package(
-    dynamo_client: &aws_sdk_dynamodb::Client,
-    package_table_name: &String,
-    pkg: Package,
-) {
-    let r = dynamo_client
-        .put_item()
-        .table_name(package_table_name.clone())
-        .item("package_name", AttributeValue::S(pkg.name))
-        .item("exports_require", AttributeValue::Bool(pkg.exports_require))
-        .item(
-            "exports_no_require",
-            AttributeValue::Bool(pkg.exports_no_require),
-        )
-        .item("type_module", AttributeValue::Bool(pkg.type_module))
-        .send()
-        .await;
-    if r.is_err() {
-        println!("err: {}", r.unwrap_err());
-    }
-}
-
-async fn update_package(
-    dynamo_client: &aws_sdk_dynamodb::Client,
-    package_table_name: &String,
-    pkg: &Package,
-) {
-    let r = dynamo_client
-        .update_item()
-        .table_name(package_table_name.clone())
-        .key("package_name", AttributeValue::S(pkg.name.clone()))
-        .update_expression("SET exports_require=:exports_require, exports_no_require=:exports_no_require, type_module=:type_module")
-        .expression_attribute_values(":exports_require", AttributeValue::Bool(pkg.exports_require))
-        .expression_attribute_values(":exports_no_require", AttributeValue::Bool(pkg.exports_no_require))
-        .expression_attribute_values(":type_module", AttributeValue::Bool(pkg.type_module))
-        .send()
-        .await;
-    if r.is_err() {
-        println!("err: {}", r.unwrap_err());
-    }
-}
-
-async fn put_audit(
-    dynamo_client: &aws_sdk_dynamodb::Client,
-    audit_table_name: &String,
-    audit: AuditEntry,
-) {
-    let uuid = uuid::Uuid::new_v4();
-
-    let r = dynamo_client
-        .put_item()
-        .table_name(audit_table_name.clone())
-        .item("timestamp", AttributeValue::S(audit.timestamp))
-        .item(
-            "package_name_id",
-            AttributeValue::S(format!(
-                "{}{}",
-                audit.package_name.clone(),
-                &uuid.to_simple().to_string()
-            )),
-        )
-        .item("package_name", AttributeValue::S(audit.package_name))
-        .item("change", AttributeValue::S(audit.change))
-        .item("old_value", AttributeValue::Bool(audit.old_value))
-        .item("new_value", AttributeValue::Bool(audit.new_value))
-        .send()
-        .await;
-    if r.is_err() {
-        println!("err: {}", r.unwrap_err());
-    }
-}
-
-fn diff_packages(old_pkg: &Package, pkg: &Package, date: &String) -> (bool, Vec<AuditEntry>) {
-    let mut should_update = false;
-    let mut audits: Vec<AuditEntry> = Vec::new();
-
-    if old_pkg.type_module != pkg.type_module {
-        println!(
-            "change in type module {} to {}",
-            old_pkg.type_module, pkg.type_module
-        );
-
-        should_update = true;
-
-        let audit_entry = AuditEntry {
-            package_name: pkg.name.clone(),
-            timestamp: date.clone(),
-            change: String::from("type_module"),
-            old_value: old_pkg.type_module,
-            new_value: pkg.type_module,
-        };
-
-        audits.push(audit_entry);
-    }
-    if old_pkg.exports_require != pkg.exports_require {
-        println!(
-            "change in exports.require {} to {}",
-            old_pkg.exports_require, pkg.exports_require
-        );
-
-        should_update = true;
-
-        let audit_entry = AuditEntry {
-            package_name: pkg.name.clone(),
-            timestamp: date.clone(),
-            change: String::from("exports_require"),
-            old_value: old_pkg.exports_require,
-            new_value: pkg.exports_require,
-        };
-        audits.push(audit_entry);
-    }
-    if old_pkg.exports_no_require != pkg.exports_no_require {
-        println!(
-            "change in no exports.require {} to {}",
-            old_pkg.exports_no_require, pkg.exports_no_require
-        );
-
-        should_update = true;
-
-        let audit_entry = AuditEntry {
-            package_name: pkg.name.clone(),
-            timestamp: date.clone(),
-            change: String::from("exports_no_require"),
-            old_value: old_pkg.exports_no_require,
-            new_value: pkg.exports_no_require,
-        };
-        audits.push(audit_entry);
-    }
-
-    (should_update, audits)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_update_type_module() {
-        let old_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: false,
-            type_module: false,
-        };
-        let new_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: false,
-            type_module: true,
-        };
-
-        let result = diff_packages(&old_pkg, &new_pkg, &String::from("2022-01-01"));
-
-        assert_eq!(result.0, true);
-        assert_eq!(result.1.len(), 1);
-        assert_eq!(result.1[0].change, String::from("type_module"));
-    }
+        // Rather than leaving stale rows behind, mark packages which are no longer listed or no
+        // longer on the registry as inactive
+        let gone = package_table_map.values().filter_map(|old_pkg| {
+            if !tracked.contains(&old_pkg.name) {
+                deactivate_package(old_pkg, Inactive::Removed, &date)
+            } else if unpublished.contains(&old_pkg.name) {
+                deactivate_package(old_pkg, Inactive::Unpublished, &date)
+            } else {
+                None
+            }
+        });
+        for (pkg, audit) in gone {
+            println!("{} is now inactive: {}", pkg.name, audit.change);
 
-    #[test]
-    fn test_multiple() {
-        let old_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: false,
-            type_module: false,
-        };
-        let new_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: true,
-            type_module: true,
-        };
+            if let Err(e) = store.put_package(&pkg).await {
+                println!("err: {}", e);
+            }
+            if let Err(e) = store.put_audit(&audit).await {
+                println!("err: {}", e);
+            }
+            written_audits.push(audit);
+        }
 
-        let result = diff_packages(&old_pkg, &new_pkg, &String::from("2022-01-01"));
+        let regressions = find_regressions(&written_audits);
+        for regression in &regressions {
+            println!("regression: {}", regression);
+        }
+        if let Ok(webhook) = std::env::var("REGRESSION_WEBHOOK") {
+            if let Err(e) = post_regressions(&webhook, &regressions).await {
+                eprintln!("err: {}", e);
+            }
+        }
 
-        assert_eq!(result.0, true);
-        assert_eq!(result.1.len(), 2);
+        let alerts = watchlist.alerts(&written_audits, &classified);
+        if !alerts.is_empty() {
+            for alert in &alerts {
+                println!("watched: {}", alert);
+            }
+            let notifiers = watchlist_notifiers()?;
+            for (kind, e) in send_watch_alerts(&notifiers, &alerts).await {
+                eprintln!("failed to notify {}: {}", kind, e);
+            }
+        }
     }
 
-    #[test]
-    fn test_no_change() {
-        let old_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: false,
-            type_module: false,
-        };
-        let new_pkg = Package {
-            name: String::from("test-package"),
-            exports_no_require: false,
-            exports_require: false,
-            type_module: false,
-        };
-
-        let result = diff_packages(&old_pkg, &new_pkg, &String::from("2022-01-01"));
-
-        assert_eq!(result.0, false);
-        assert_eq!(result.1.len(), 0);
-    }
+    Ok(())
 }
@@ -0,0 +1,126 @@
+use clap::Parser;
+use esm_checker::{
+    find_regressions, follow_changes, post_regressions, read_package_list, send_watch_alerts,
+    store, watchlist_notifiers, Feed, FollowCheckpoint, FollowOptions, Source, Watchlist,
+    NPM_REGISTRY_URL, NPM_REPLICATE_URL,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// Re-classifies tracked packages which published since the last run, using the registry's
+/// `_changes` feed.
+#[derive(Parser, Debug)]
+#[clap(name = "follow-changes")]
+struct Opt {
+    /// Whitespace separated list of the packages to track
+    #[clap(long, default_value = "packages.txt")]
+    packages: String,
+
+    /// File the last processed sequence is saved to
+    #[clap(long, default_value = "follow-checkpoint.json")]
+    checkpoint: PathBuf,
+
+    /// Start following from this sequence instead of the checkpoint. Without either, following
+    /// starts from the feed's current sequence
+    #[clap(long)]
+    since: Option<u64>,
+
+    /// The CouchDB database whose `_changes` feed is followed
+    #[clap(long, default_value = NPM_REPLICATE_URL)]
+    changes_url: String,
+
+    /// Replay a `_changes` response recorded to a file instead of following `changes_url`
+    #[clap(long)]
+    feed_file: Option<PathBuf>,
+
+    /// The registry changed packages are re-classified from
+    #[clap(long, default_value = NPM_REGISTRY_URL)]
+    registry_url: String,
+
+    /// Number of changes requested from the feed at a time
+    #[clap(long, default_value = "1000")]
+    batch_size: usize,
+
+    /// Whitespace separated list of packages to send an alert for as soon as their status
+    /// changes. They're followed even when they aren't in `packages`
+    #[clap(long)]
+    watchlist: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv::dotenv().ok();
+
+    let args = Opt::parse();
+
+    let watchlist = match &args.watchlist {
+        Some(path) => Watchlist::from_file(path)?,
+        None => Watchlist::default(),
+    };
+    let mut tracked: HashSet<String> = read_package_list(&args.packages)?.into_iter().collect();
+    tracked.extend(watchlist.iter().cloned());
+
+    if let Some(since) = args.since {
+        FollowCheckpoint {
+            since,
+            ..FollowCheckpoint::default()
+        }
+        .save(&args.checkpoint)?;
+    }
+
+    let feed = match args.feed_file {
+        Some(path) => Feed::File(path),
+        None => Feed::Http(args.changes_url),
+    };
+
+    let options = FollowOptions::new(feed, &args.checkpoint)
+        .source(Source::Registry(args.registry_url))
+        .batch_size(args.batch_size);
+
+    let store = store::from_env().await?;
+    let summary = follow_changes(store.as_ref(), &tracked, &options).await?;
+
+    println!("Changes read: {}", summary.changes);
+    println!("Tracked packages re-checked: {}", summary.rechecked);
+    for audit in &summary.audits {
+        println!(
+            "{} (seq {}): {} {} -> {}",
+            audit.package_name,
+            audit.seq.unwrap_or_default(),
+            audit.change,
+            audit.old_value,
+            audit.new_value
+        );
+    }
+    println!("Caught up to sequence {}", summary.since);
+    if !summary.retry.is_empty() {
+        println!("Retrying next run: {}", summary.retry.join(", "));
+    }
+
+    let regressions = find_regressions(&summary.audits);
+    for regression in &regressions {
+        println!("regression: {}", regression);
+    }
+    if let Ok(webhook) = std::env::var("REGRESSION_WEBHOOK") {
+        post_regressions(&webhook, &regressions).await?;
+    }
+
+    // Checking for alerts first saves reading every stored package on most runs
+    if !watchlist
+        .alerts(&summary.audits, &HashMap::new())
+        .is_empty()
+    {
+        let alerts = watchlist.alerts(&summary.audits, &store.packages().await?);
+        for alert in &alerts {
+            println!("watched: {}", alert);
+        }
+        let notifiers = watchlist_notifiers()?;
+        for (kind, e) in send_watch_alerts(&notifiers, &alerts).await {
+            eprintln!("failed to notify {}: {}", kind, e);
+        }
+    }
+
+    Ok(())
+}
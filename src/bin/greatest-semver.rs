@@ -96,7 +96,7 @@ fn find_semver(val: &Value, pkg_name: &String) -> String {
     let versions = match val["versions"].as_object() {
         Some(s) => s,
         None => {
-            println!("{}: {}", pkg_name, val.to_string());
+            println!("{}: {}", pkg_name, val);
             panic!("Failed to find `versions` field within JSON for package");
         }
     };
@@ -1,93 +1,91 @@
-use aws_sdk_dynamodb::model::AttributeValue;
-use aws_types::region::Region;
-use chrono::{Duration, Utc};
-use esm_checker::StatsEntry;
+use chrono::{NaiveDate, Utc};
+use clap::Parser;
+use esm_checker::{
+    forecast_adoption, notify, stats_series, store, ChannelTemplates, GapPolicy, Period,
+    StatsReport, FIRST_STATS_DATE,
+};
+
+/// Sends the weekly report to the notification channels. `esm-checker report --send` does the same
+/// for any period.
+#[derive(Parser, Debug)]
+#[clap(name = "msg-weekly-stats")]
+struct Opt {
+    /// Compare with yesterday's run rather than last week's, for a daily report
+    #[clap(long)]
+    daily: bool,
+
+    /// Include projected dates for adoption milestones in the message
+    #[clap(long)]
+    forecast: bool,
+
+    /// First day of the history the forecast is fitted to (YYYY-MM-DD)
+    #[clap(long, default_value = FIRST_STATS_DATE)]
+    forecast_from: NaiveDate,
+
+    /// Adoption percentages to project dates for
+    #[clap(long = "milestone", default_values = &["25", "50", "75"])]
+    milestones: Vec<f64>,
+
+    /// Handlebars template to write the message with instead of the built in report, as
+    /// `<kind>=<file>` for one kind of channel or `<file>` for every other channel
+    #[clap(long = "template")]
+    templates: Vec<String>,
+
+    /// Print the message each channel would get instead of sending it
+    #[clap(long)]
+    dry_run: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
 
-    let discord_webhook = std::env::var("DISCORD_WEBHOOK").unwrap();
-
-    let stats_table_name = std::env::var("DYNAMO_STATS_TABLE_NAME")
-        .expect("There should be a stats table name defined as an environment variable");
-
-    let aws_region = std::env::var("AWS_REGION").ok().map(Region::new).unwrap();
-    let config = aws_config::from_env().region(aws_region).load().await;
-    let dynamo_client = aws_sdk_dynamodb::Client::new(&config);
-
-    let today = Utc::now();
-    let last_week = Utc::now().checked_sub_signed(Duration::weeks(1)).unwrap();
-
-    let today_pk_str = today.format("%Y-%m").to_string();
-    let last_week_pk_str = last_week.format("%Y-%m").to_string();
-
-    let today_sk_str = today.format("%Y-%m-%d").to_string();
-    let last_week_sk_str = last_week.format("%Y-%m-%d").to_string();
-
-    let today_item = fetch_stats_entry(
-        &dynamo_client,
-        &stats_table_name,
-        today_pk_str,
-        today_sk_str,
-    )
-    .await?;
-
-    let last_week_item = fetch_stats_entry(
-        &dynamo_client,
-        &stats_table_name,
-        last_week_pk_str,
-        last_week_sk_str,
-    )
-    .await?;
-
-    let diff = today_item - last_week_item;
-
-    let diff_str = format!(
-        "[ESM Checker]\nStats for {}\ntype_module: {}\nexports_require: {}\nexports_no_require: {}",
-        diff.timestamp, diff.type_module, diff.exports_require, diff.exports_no_require
-    );
-
-    let body = serde_json::json!({ "content": format!("{}", diff_str) }).to_string();
-
-    let reqwest_client = reqwest::Client::builder()
-        .user_agent("esm-checker-discord-webhook/0.3.1")
-        .build()
-        .unwrap();
-
-    reqwest_client
-        .post(discord_webhook)
-        .header("Content-Type", "application/json")
-        .body(body)
-        .send()
-        .await?;
+    let args = Opt::parse();
+
+    let notifiers = notify::from_env()?;
+    let templates = ChannelTemplates::from_args(&args.templates)?;
+
+    let store = store::from_env().await?;
+
+    let period = if args.daily {
+        Period::Day
+    } else {
+        Period::Week
+    };
+    let today = Utc::now().naive_utc().date();
+    let previous = period.start(today).unwrap();
+
+    let mut report = StatsReport::between(store.as_ref(), previous, today)
+        .await?
+        .period(period);
+
+    if args.forecast {
+        let history =
+            stats_series(store.as_ref(), args.forecast_from, today, GapPolicy::Flag).await?;
+        report = report.forecast(forecast_adoption(&history, &args.milestones));
+    }
+
+    let messages = templates.messages(&notifiers, &report)?;
+    if args.dry_run {
+        for (notifier, message) in notifiers.iter().zip(&messages) {
+            println!(
+                "[{}] {}\n{}\n",
+                notifier.kind(),
+                message.title,
+                message.text
+            );
+        }
+        return Ok(());
+    }
+
+    let mut failures = notify::notify_each(&notifiers, &messages).await;
+    // Only fail the run when nobody got the report
+    if failures.len() == notifiers.len() {
+        return Err(failures.remove(0).1.into());
+    }
+    for (kind, e) in &failures {
+        eprintln!("failed to notify {}: {}", kind, e);
+    }
 
     Ok(())
 }
-
-async fn fetch_stats_entry(
-    client: &aws_sdk_dynamodb::Client,
-    table_name: &String,
-    pk: String,
-    sk: String,
-) -> Result<StatsEntry, Box<dyn std::error::Error>> {
-    let query_output = client
-        .query()
-        .table_name(table_name)
-        .key_condition_expression("year_month = :ym and #timestamp = :t")
-        .expression_attribute_names("#timestamp", "timestamp")
-        .expression_attribute_values(":ym", AttributeValue::S(pk))
-        .expression_attribute_values(":t", AttributeValue::S(sk))
-        .send()
-        .await?;
-
-    let res = query_output
-        .items
-        .unwrap()
-        .first()
-        .unwrap()
-        .to_owned()
-        .into();
-
-    Ok(res)
-}
@@ -0,0 +1,89 @@
+use clap::Parser;
+use esm_checker::{read_registry_list, scan_registry, ScanOptions, Source, NPM_REPLICATE_URL};
+use std::path::PathBuf;
+
+/// Classifies every package on npm, checkpointing progress so an interrupted scan can be resumed.
+#[derive(Parser, Debug)]
+#[clap(name = "scan-registry")]
+struct Opt {
+    /// Comma separated list of every package in the registry
+    #[clap(long, default_value = "registry.txt")]
+    registry: String,
+
+    /// File progress is saved to. An existing checkpoint is resumed from
+    #[clap(long, default_value = "scan-checkpoint.json")]
+    checkpoint: PathBuf,
+
+    /// Discard any existing checkpoint and scan from the beginning
+    #[clap(long)]
+    restart: bool,
+
+    /// Read registry documents from a local mirror directory instead of the CouchDB replica
+    #[clap(long)]
+    mirror: Option<PathBuf>,
+
+    /// The CouchDB replica of the registry to read documents from
+    #[clap(long, default_value = NPM_REPLICATE_URL)]
+    replicate_url: String,
+
+    /// Number of packages checked between checkpoints
+    #[clap(long, default_value = "500")]
+    batch_size: usize,
+
+    /// Maximum number of requests in flight at once
+    #[clap(long, default_value = "30")]
+    concurrency: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Opt::parse();
+
+    let names = read_registry_list(&args.registry)?;
+
+    if args.restart && args.checkpoint.exists() {
+        std::fs::remove_file(&args.checkpoint)?;
+    }
+
+    let source = match args.mirror {
+        Some(path) => Source::Mirror(path),
+        None => Source::Replicate(args.replicate_url),
+    };
+
+    let options = ScanOptions::new(source, &args.checkpoint)
+        .batch_size(args.batch_size)
+        .concurrency(args.concurrency);
+
+    let checkpoint = scan_registry(&names, &options, |checkpoint| {
+        println!(
+            "Checked {}/{} packages ({} failed)",
+            checkpoint.position,
+            names.len(),
+            checkpoint.stats.failed
+        );
+    })
+    .await?;
+
+    let stats = checkpoint.stats;
+
+    println!("Total packages: {}", stats.total());
+    println!("Packages classified: {}", stats.scanned);
+    println!("Packages which failed to fetch: {}", stats.failed);
+    println!(
+        "Packages with a `type: module` field: {} ({:.2}%)",
+        stats.type_module,
+        stats.percent(stats.type_module)
+    );
+    println!(
+        "Packages with a `exports.require` field: {} ({:.2}%)",
+        stats.exports_require,
+        stats.percent(stats.exports_require)
+    );
+    println!(
+        "Packages without an explicit `exports.require` that may be ESM only: {} ({:.2}%)",
+        stats.exports_no_require,
+        stats.percent(stats.exports_no_require)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,428 @@
+//! Charts of the stats as SVG, with no scripts or external styles so they can be embedded in a
+//! page or opened on their own.
+
+use crate::{notify::escape_html, Error, Metric, StatsSeries};
+use chrono::NaiveDate;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Series colors, assigned in order.
+const PALETTE: [&str; 6] = [
+    "#2e86c1", "#e67e22", "#27ae60", "#8e44ad", "#c0392b", "#7f8c8d",
+];
+
+const MARGIN_LEFT: f64 = 56.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 32.0;
+const Y_TICKS: usize = 5;
+const X_TICKS: usize = 5;
+
+/// A named line of a chart, one value per day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartSeries {
+    pub name: String,
+    pub points: Vec<(NaiveDate, f64)>,
+}
+
+impl ChartSeries {
+    pub fn new(name: impl Into<String>, points: Vec<(NaiveDate, f64)>) -> Self {
+        ChartSeries {
+            name: name.into(),
+            points,
+        }
+    }
+
+    /// `metric` for every point of `series`.
+    pub fn of_metric(series: &StatsSeries, metric: Metric) -> Self {
+        ChartSeries::new(
+            metric.to_string(),
+            series
+                .points
+                .iter()
+                .map(|p| (p.date, metric.value(&p.entry)))
+                .collect(),
+        )
+    }
+}
+
+/// How the series of a chart are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    /// A line per series.
+    Line,
+    /// Each series filled in on top of the ones before it, so the top edge is their total.
+    StackedArea,
+}
+
+impl FromStr for ChartKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(ChartKind::Line),
+            "stacked-area" | "area" => Ok(ChartKind::StackedArea),
+            _ => Err(Error::Config(format!(
+                "unknown chart `{}`, expected line or stacked-area",
+                s
+            ))),
+        }
+    }
+}
+
+/// Series over time, with a legend and labelled axes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    pub title: String,
+    pub kind: ChartKind,
+    pub width: u32,
+    pub height: u32,
+    /// Appended to the labels of the y axis, such as `%`.
+    pub unit: String,
+    /// The first and last day of the x axis. Defaults to the days of the first and last points.
+    pub range: Option<(NaiveDate, NaiveDate)>,
+    pub series: Vec<ChartSeries>,
+}
+
+impl Chart {
+    pub fn new(kind: ChartKind, title: impl Into<String>) -> Self {
+        Chart {
+            title: title.into(),
+            kind,
+            width: 720,
+            height: 320,
+            unit: String::new(),
+            range: None,
+            series: vec![],
+        }
+    }
+
+    pub fn line(title: impl Into<String>) -> Self {
+        Chart::new(ChartKind::Line, title)
+    }
+
+    pub fn stacked_area(title: impl Into<String>) -> Self {
+        Chart::new(ChartKind::StackedArea, title)
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = unit.into();
+        self
+    }
+
+    /// Only plot the days from `from` to `to`, inclusive.
+    pub fn range(mut self, from: NaiveDate, to: NaiveDate) -> Self {
+        self.range = Some((from, to));
+        self
+    }
+
+    pub fn series(mut self, series: ChartSeries) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    /// A series per metric of `series`, over its whole range.
+    pub fn metrics(mut self, series: &StatsSeries, metrics: &[Metric]) -> Self {
+        for &metric in metrics {
+            self.series.push(ChartSeries::of_metric(series, metric));
+        }
+        self.range(series.from, series.to)
+    }
+
+    pub fn svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"11\">\n<title>{title}</title>\n<rect width=\"{w}\" height=\"{h}\" fill=\"#ffffff\"/>\n<text x=\"{MARGIN_LEFT}\" y=\"20\" font-size=\"14\" font-weight=\"bold\">{title}</text>\n",
+            w = self.width,
+            h = self.height,
+            title = escape_html(&self.title),
+        );
+
+        let layers = self.layers();
+        let dates = layers.iter().flat_map(|l| l.iter().map(|p| p.0));
+        let extent = match (dates.clone().min(), dates.max()) {
+            (Some(first), Some(last)) => Some((first, last)),
+            _ => None,
+        };
+        let (first, last) = match (self.range, extent) {
+            (Some(range), Some(_)) => range,
+            (None, Some(extent)) => extent,
+            (_, None) => {
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"#7f8c8d\">No data</text>\n</svg>\n",
+                    self.width / 2,
+                    self.height / 2
+                ));
+                return svg;
+            }
+        };
+        let max = layers
+            .iter()
+            .flat_map(|l| l.iter().map(|p| p.2))
+            .fold(0.0, f64::max);
+        let scale = Scale::new(self.width, self.height, first, last, nice_ceiling(max));
+
+        svg.push_str(&scale.axes(&self.unit));
+        for (i, (series, layer)) in self.series.iter().zip(&layers).enumerate() {
+            let color = PALETTE[i % PALETTE.len()];
+            let top: Vec<String> = layer
+                .iter()
+                .map(|&(date, _, top)| format!("{:.1},{:.1}", scale.x(date), scale.y(top)))
+                .collect();
+
+            match self.kind {
+                ChartKind::Line => svg.push_str(&format!(
+                    "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"><title>{}</title></polyline>\n",
+                    color,
+                    top.join(" "),
+                    escape_html(&series.name)
+                )),
+                ChartKind::StackedArea => {
+                    // Along the top of the layer, then back along the layer below it
+                    let base = layer
+                        .iter()
+                        .rev()
+                        .map(|&(date, base, _)| format!("{:.1},{:.1}", scale.x(date), scale.y(base)));
+                    let outline: Vec<String> = top.iter().cloned().chain(base).collect();
+                    svg.push_str(&format!(
+                        "<polygon fill=\"{c}\" fill-opacity=\"0.75\" stroke=\"{c}\" points=\"{}\"><title>{}</title></polygon>\n",
+                        outline.join(" "),
+                        escape_html(&series.name),
+                        c = color,
+                    ))
+                }
+            }
+        }
+        let names: Vec<&str> = self.series.iter().map(|s| s.name.as_str()).collect();
+        svg.push_str(&legend(self.width, &names));
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The points of each series inside the range as `(day, bottom, top)`. Lines sit on zero,
+    /// while stacked layers start from the top of the layers before them on the same day.
+    fn layers(&self) -> Vec<Vec<(NaiveDate, f64, f64)>> {
+        let mut totals: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+
+        self.series
+            .iter()
+            .map(|series| {
+                let mut points: Vec<(NaiveDate, f64)> = series
+                    .points
+                    .iter()
+                    .copied()
+                    .filter(|(date, _)| {
+                        self.range
+                            .is_none_or(|(from, to)| *date >= from && *date <= to)
+                    })
+                    .collect();
+                points.sort_by_key(|p| p.0);
+
+                points
+                    .into_iter()
+                    .map(|(date, value)| match self.kind {
+                        ChartKind::Line => (date, 0.0, value),
+                        ChartKind::StackedArea => {
+                            let total = totals.entry(date).or_insert(0.0);
+                            let base = *total;
+                            *total += value;
+                            (date, base, *total)
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Maps days and values to coordinates inside the margins.
+struct Scale {
+    width: f64,
+    height: f64,
+    first: NaiveDate,
+    days: i64,
+    max: f64,
+}
+
+impl Scale {
+    fn new(width: u32, height: u32, first: NaiveDate, last: NaiveDate, max: f64) -> Self {
+        Scale {
+            width: width as f64,
+            height: height as f64,
+            first,
+            days: (last - first).num_days(),
+            max,
+        }
+    }
+
+    fn x(&self, date: NaiveDate) -> f64 {
+        let plot = self.width - MARGIN_LEFT - MARGIN_RIGHT;
+        // A single day is drawn at the left edge rather than dividing by zero
+        MARGIN_LEFT + (date - self.first).num_days() as f64 / self.days.max(1) as f64 * plot
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        let plot = self.height - MARGIN_TOP - MARGIN_BOTTOM;
+        self.height - MARGIN_BOTTOM - value / self.max * plot
+    }
+
+    /// Horizontal grid lines labelled with their value, and date labels along the bottom.
+    fn axes(&self, unit: &str) -> String {
+        let mut svg = String::new();
+        let right = self.width - MARGIN_RIGHT;
+
+        for i in 0..=Y_TICKS {
+            let value = self.max * i as f64 / Y_TICKS as f64;
+            let y = self.y(value);
+            svg.push_str(&format!(
+                "<line x1=\"{MARGIN_LEFT}\" y1=\"{y:.1}\" x2=\"{right}\" y2=\"{y:.1}\" stroke=\"#e5e8e8\"/>\n<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"#566573\">{}{}</text>\n",
+                MARGIN_LEFT - 6.0,
+                y + 4.0,
+                format_tick(value),
+                escape_html(unit)
+            ));
+        }
+
+        let ticks = (self.days as usize).min(X_TICKS);
+        for i in 0..=ticks {
+            let offset = match ticks {
+                0 => 0,
+                _ => (self.days as f64 * i as f64 / ticks as f64).round() as i64,
+            };
+            let date = self.first + chrono::Duration::days(offset);
+            // Keep the labels at either end inside the chart
+            let anchor = match i {
+                0 => "start",
+                i if i == ticks => "end",
+                _ => "middle",
+            };
+            svg.push_str(&format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" fill=\"#566573\">{}</text>\n",
+                self.x(date),
+                self.height - MARGIN_BOTTOM + 16.0,
+                anchor,
+                date.format("%F")
+            ));
+        }
+
+        svg
+    }
+}
+
+/// A colored swatch and name per series along the top right.
+fn legend(width: u32, names: &[&str]) -> String {
+    let mut svg = String::new();
+    let mut x = width as f64 - MARGIN_RIGHT;
+
+    for (i, name) in names.iter().enumerate().rev() {
+        // Roughly the width of an 11px sans-serif character
+        x -= name.chars().count() as f64 * 6.5 + 22.0;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"12\" width=\"10\" height=\"10\" fill=\"{}\"/>\n<text x=\"{:.1}\" y=\"21\">{}</text>\n",
+            x,
+            PALETTE[i % PALETTE.len()],
+            x + 14.0,
+            escape_html(name)
+        ));
+    }
+
+    svg
+}
+
+/// The smallest of 1, 2, 2.5 or 5 times a power of ten which is at least `value`, so the axis
+/// ends on a round number.
+fn nice_ceiling(value: f64) -> f64 {
+    if value <= 0.0 {
+        return 1.0;
+    }
+
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 2.5, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&v| v >= value)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn format_tick(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    #[test]
+    fn test_nice_ceiling() {
+        assert_eq!(nice_ceiling(0.0), 1.0);
+        assert_eq!(nice_ceiling(7.3), 10.0);
+        assert_eq!(nice_ceiling(21.0), 25.0);
+        assert_eq!(nice_ceiling(1234.0), 2000.0);
+    }
+
+    #[test]
+    fn test_line_chart() {
+        let chart = Chart::line("Adoption <%>")
+            .size(400, 200)
+            .unit("%")
+            .series(ChartSeries::new(
+                "adoption",
+                vec![(date("2022-01-01"), 10.0), (date("2022-01-05"), 20.0)],
+            ));
+
+        let svg = chart.svg();
+
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"400\" height=\"200\""));
+        assert!(svg.contains("<title>Adoption &lt;%&gt;</title>"));
+        // The axis ends at 20, so the last point is at the top of the plot
+        assert!(svg.contains("points=\"56.0,104.0 384.0,40.0\""));
+        assert!(svg.contains(">2022-01-05</text>"));
+        assert!(Chart::line("Empty").svg().contains("No data"));
+    }
+
+    #[test]
+    fn test_stacked_area_chart() {
+        let days = |values: [f64; 3]| -> Vec<(NaiveDate, f64)> {
+            ["2022-01-01", "2022-01-02", "2022-01-03"]
+                .iter()
+                .map(|d| date(d))
+                .zip(values)
+                .collect()
+        };
+        let chart = Chart::stacked_area("Statuses")
+            .size(400, 200)
+            .range(date("2022-01-02"), date("2022-01-03"))
+            .series(ChartSeries::new("cjs", days([60.0, 50.0, 40.0])))
+            .series(ChartSeries::new("esm_only", days([40.0, 50.0, 60.0])));
+
+        let layers = chart.layers();
+        assert_eq!(
+            layers[1],
+            vec![
+                (date("2022-01-02"), 50.0, 100.0),
+                (date("2022-01-03"), 40.0, 100.0)
+            ]
+        );
+
+        let svg = chart.svg();
+        assert_eq!(svg.matches("<polygon ").count(), 2);
+        // The top layer ends at the top of the plot, over the top of the cjs layer
+        assert!(svg.contains("points=\"56.0,40.0 384.0,40.0 384.0,116.8 56.0,104.0\""));
+        assert!(!svg.contains(">2022-01-01</text>"));
+        assert!("area".parse::<ChartKind>().unwrap() == ChartKind::StackedArea);
+    }
+}
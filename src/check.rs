@@ -0,0 +1,578 @@
+use crate::{Error, ManifestFields, Package};
+use futures::{stream, Stream, StreamExt};
+use serde_json::Value;
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// The public CDN used to fetch the `package.json` of a release of a package.
+pub const UNPKG_URL: &str = "https://unpkg.com";
+
+/// The public npm registry, which serves the manifest of any published version.
+pub const NPM_REGISTRY_URL: &str = "https://registry.npmjs.org";
+
+/// The public CouchDB replica of the npm registry.
+pub const NPM_REPLICATE_URL: &str = "https://replicate.npmjs.com";
+
+/// A package to check, optionally pinned to a version or dist-tag (`name` or `name@version`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl PackageSpec {
+    /// The version or dist-tag to request, falling back to `latest`.
+    pub fn version_or_latest(&self) -> &str {
+        self.version.as_deref().unwrap_or("latest")
+    }
+}
+
+impl FromStr for PackageSpec {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(PackageSpec::from(s))
+    }
+}
+
+impl From<&str> for PackageSpec {
+    fn from(spec: &str) -> Self {
+        // Skip the first character so the `@` of a scope isn't mistaken for a version separator
+        let split_at = spec
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c == '@')
+            .map(|(i, _)| i);
+
+        match split_at {
+            Some(i) if i + 1 < spec.len() => PackageSpec {
+                name: spec[..i].to_owned(),
+                version: Some(spec[i + 1..].to_owned()),
+            },
+            Some(i) => PackageSpec {
+                name: spec[..i].to_owned(),
+                version: None,
+            },
+            None => PackageSpec {
+                name: spec.to_owned(),
+                version: None,
+            },
+        }
+    }
+}
+
+impl From<String> for PackageSpec {
+    fn from(spec: String) -> Self {
+        PackageSpec::from(spec.as_str())
+    }
+}
+
+impl From<&String> for PackageSpec {
+    fn from(spec: &String) -> Self {
+        PackageSpec::from(spec.as_str())
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Where manifests are downloaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A CDN laid out like unpkg: `{base_url}/{name}@{version}/package.json`.
+    Unpkg(String),
+    /// An npm compatible registry: `{base_url}/{name}/{version}`.
+    Registry(String),
+    /// A CouchDB replica of the registry, such as replicate.npmjs.com, where `{base_url}/{name}` is
+    /// the document listing every version of the package.
+    Replicate(String),
+    /// A local mirror holding either a manifest or a registry document per package at
+    /// `{path}/{name}.json`.
+    Mirror(PathBuf),
+}
+
+/// Where a [`Source`] keeps the manifest of a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Location {
+    Url(String),
+    File(PathBuf),
+}
+
+impl Source {
+    fn manifest_location(&self, spec: &PackageSpec) -> Location {
+        match self {
+            Source::Unpkg(base_url) => Location::Url(format!(
+                "{}/{}@{}/package.json",
+                base_url.trim_end_matches('/'),
+                spec.name,
+                spec.version_or_latest()
+            )),
+            Source::Registry(base_url) => Location::Url(format!(
+                "{}/{}/{}",
+                base_url.trim_end_matches('/'),
+                spec.name.replace('/', "%2f"),
+                spec.version_or_latest()
+            )),
+            Source::Replicate(base_url) => Location::Url(format!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                spec.name.replace('/', "%2f")
+            )),
+            Source::Mirror(path) => Location::File(path.join(format!("{}.json", spec.name))),
+        }
+    }
+
+    /// The registry document listing every version of `name` along with when each was published.
+    /// CDNs and local mirrors don't have one.
+    fn document_url(&self, name: &str) -> Option<String> {
+        match self {
+            Source::Registry(base_url) | Source::Replicate(base_url) => Some(format!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                name.replace('/', "%2f")
+            )),
+            Source::Unpkg(_) | Source::Mirror(_) => None,
+        }
+    }
+
+    async fn fetch(&self, client: &reqwest::Client, spec: &PackageSpec) -> Result<String, Error> {
+        match self.manifest_location(spec) {
+            Location::Url(url) => fetch_package_json(client, &url).await,
+            Location::File(path) => Ok(tokio::fs::read_to_string(path).await?),
+        }
+    }
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Unpkg(UNPKG_URL.to_owned())
+    }
+}
+
+/// Local caching of downloaded manifests.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Cache {
+    #[default]
+    Disabled,
+    /// Store every manifest in `path`, reusing it until it is older than `max_age` (forever when
+    /// `max_age` is `None`).
+    Directory {
+        path: PathBuf,
+        max_age: Option<Duration>,
+    },
+}
+
+impl Cache {
+    fn entry_path(&self, spec: &PackageSpec) -> Option<PathBuf> {
+        match self {
+            Cache::Disabled => None,
+            Cache::Directory { path, .. } => Some(path.join(format!(
+                "{}@{}.json",
+                spec.name.replace('/', "+"),
+                spec.version_or_latest()
+            ))),
+        }
+    }
+
+    fn read(&self, spec: &PackageSpec) -> Option<String> {
+        let entry = self.entry_path(spec)?;
+
+        if let Cache::Directory {
+            max_age: Some(max_age),
+            ..
+        } = self
+        {
+            let modified = fs::metadata(&entry).and_then(|m| m.modified()).ok()?;
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            if age > *max_age {
+                return None;
+            }
+        }
+
+        fs::read_to_string(entry).ok()
+    }
+
+    fn write(&self, spec: &PackageSpec, json_str: &str) {
+        if let Some(entry) = self.entry_path(spec) {
+            let written = entry
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&entry, json_str));
+            if let Err(e) = written {
+                eprintln!("Failed to cache {}: {}", entry.display(), e);
+            }
+        }
+    }
+}
+
+/// Which packages to check and how to fetch them.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use esm_checker::{generate_packages, CheckOptions, Source, NPM_REGISTRY_URL};
+///
+/// let options = CheckOptions::new(["chalk", "react@17.0.2"])
+///     .concurrency(10)
+///     .source(Source::Registry(NPM_REGISTRY_URL.to_owned()));
+/// let packages = generate_packages(&options).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    packages: Vec<PackageSpec>,
+    limit: Option<usize>,
+    concurrency: usize,
+    source: Source,
+    cache: Cache,
+}
+
+impl CheckOptions {
+    pub fn new<I, S>(packages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<PackageSpec>,
+    {
+        CheckOptions {
+            packages: packages.into_iter().map(Into::into).collect(),
+            limit: None,
+            concurrency: 30,
+            source: Source::default(),
+            cache: Cache::default(),
+        }
+    }
+
+    /// Only check the first `limit` packages.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The maximum number of requests in flight at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// The packages which will be checked, after applying the limit.
+    pub fn packages(&self) -> &[PackageSpec] {
+        let len = self.limit.unwrap_or(usize::MAX).min(self.packages.len());
+        &self.packages[..len]
+    }
+}
+
+/// Reads a whitespace separated list of package names, such as `packages.txt`.
+pub fn read_package_list(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(fs::read_to_string(path)?
+        .split_whitespace()
+        .map(|c| c.to_owned())
+        .collect())
+}
+
+/// The classification of a single package, along with what was asked for and what was found.
+#[derive(Debug, Clone)]
+pub struct PackageReport {
+    pub spec: PackageSpec,
+    /// The `version` field of the manifest which was classified.
+    pub version: Option<String>,
+    pub package: Package,
+}
+
+/// Fetches and classifies every package described by `options`, yielding each one as soon as it
+/// has been classified.
+///
+/// Up to `concurrency` requests are in flight at once, so results arrive in completion order
+/// rather than the order of the package list.
+pub fn stream_packages(
+    options: &CheckOptions,
+) -> impl Stream<Item = Result<PackageReport, Error>> + Send + 'static {
+    let client = http_client();
+    let source = options.source.clone();
+    let cache = options.cache.clone();
+
+    stream::iter(options.packages().to_vec())
+        .map(move |spec| check_package(client.clone(), source.clone(), cache.clone(), spec))
+        .buffer_unordered(options.concurrency)
+}
+
+/// Fetches and classifies every package described by `options`.
+///
+/// Packages which fail to download, respond with a non-success status or don't contain a valid
+/// `package.json` are logged and left out of the result.
+pub async fn generate_packages(options: &CheckOptions) -> Result<Vec<Package>, Error> {
+    let mut reports = stream_packages(options);
+
+    let mut pkgs: Vec<Package> = vec![];
+
+    while let Some(report) = reports.next().await {
+        match report {
+            Ok(report) => pkgs.push(report.package),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    Ok(pkgs)
+}
+
+pub(crate) fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .user_agent("esm-checker/0.3.0 (+https://github.com/lannonbr/esm-checker)")
+        .build()
+        .expect("Failed to build the HTTP client")
+}
+
+pub(crate) async fn check_package(
+    client: reqwest::Client,
+    source: Source,
+    cache: Cache,
+    spec: PackageSpec,
+) -> Result<PackageReport, Error> {
+    let json_str = match cache.read(&spec) {
+        Some(cached) => cached,
+        None => {
+            let json_str = match source.fetch(&client, &spec).await {
+                // Without a pinned version a 404 means the whole package is gone, rather than
+                // just the version which was asked for
+                Err(e) if e.status() == Some(404) && spec.version.is_none() => {
+                    return Err(Error::Unpublished {
+                        package: spec.name.clone(),
+                    })
+                }
+                result => result?,
+            };
+            cache.write(&spec, &json_str);
+            json_str
+        }
+    };
+
+    let document: Value = serde_json::from_str(&json_str).map_err(|e| Error::InvalidManifest {
+        package: spec.to_string(),
+        reason: e.to_string(),
+    })?;
+    // Only full registry documents have publish times, keyed by version. Unpublished packages
+    // keep a document recording when they were unpublished, but no versions.
+    let times = document.get("time").cloned();
+    if times.as_ref().and_then(|t| t.get("unpublished")).is_some() {
+        return Err(Error::Unpublished {
+            package: spec.name.clone(),
+        });
+    }
+    let package_json = resolve_manifest(document, &spec)?;
+
+    let mut package = generate_pkg(&package_json).ok_or_else(|| Error::InvalidManifest {
+        package: spec.to_string(),
+        reason: String::from("missing a name"),
+    })?;
+    package.published = package
+        .version
+        .as_ref()
+        .and_then(|version| times?.get(version)?.as_str().map(|t| t.to_owned()));
+
+    Ok(PackageReport {
+        version: package.version.clone(),
+        spec,
+        package,
+    })
+}
+
+/// When the version of `package` was published, from the registry document of `source`. Single
+/// version manifests don't say, so this costs a request for the whole document.
+pub(crate) async fn publish_time(
+    source: &Source,
+    package: &Package,
+) -> Result<Option<String>, Error> {
+    let (url, version) = match (source.document_url(&package.name), &package.version) {
+        (Some(url), Some(version)) => (url, version),
+        _ => return Ok(None),
+    };
+
+    let document: Value = serde_json::from_str(&fetch_package_json(&http_client(), &url).await?)?;
+    Ok(document
+        .get("time")
+        .and_then(|times| times.get(version))
+        .and_then(|time| time.as_str())
+        .map(|time| time.to_owned()))
+}
+
+/// Registry documents list the manifest of every version, so pick out the one `spec` asks for.
+/// Plain manifests are returned untouched.
+fn resolve_manifest(document: Value, spec: &PackageSpec) -> Result<Value, Error> {
+    let versions = match document.get("versions").and_then(|v| v.as_object()) {
+        Some(versions) => versions,
+        None => return Ok(document),
+    };
+
+    let wanted = spec.version_or_latest();
+    let version = document
+        .get("dist-tags")
+        .and_then(|tags| tags.get(wanted))
+        .and_then(|v| v.as_str())
+        .unwrap_or(wanted);
+
+    versions
+        .get(version)
+        .cloned()
+        .ok_or_else(|| Error::InvalidManifest {
+            package: spec.to_string(),
+            reason: format!("no published version {}", version),
+        })
+}
+
+pub(crate) async fn fetch_package_json(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<String, Error> {
+    let http_err = |source| Error::Http {
+        url: url.to_owned(),
+        source,
+    };
+
+    let resp = client.get(url).send().await.map_err(http_err)?;
+
+    if !resp.status().is_success() {
+        return Err(Error::Status {
+            url: url.to_owned(),
+            status: resp.status().as_u16(),
+        });
+    }
+
+    resp.text().await.map_err(http_err)
+}
+
+fn generate_pkg(package_json: &Value) -> Option<Package> {
+    let name = package_json.get("name")?.as_str()?;
+
+    let mut new_package = Package {
+        name: name.to_string(),
+        version: package_json
+            .get("version")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_owned()),
+        manifest: ManifestFields::from_manifest(package_json),
+        deprecated: package_json
+            .get("deprecated")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_owned()),
+        ..Package::default()
+    };
+
+    if let Some(package_type) = package_json.get("type") {
+        new_package.type_module = package_type.as_str() == Some("module");
+    } else {
+        new_package.type_module = false;
+    }
+    if let Some(exports) = package_json.get("exports") {
+        let exports_str = exports.to_string();
+        if exports_str.contains("require") {
+            new_package.exports_require = true;
+            new_package.exports_no_require = false;
+        } else {
+            new_package.exports_no_require = true;
+            new_package.exports_require = false;
+        }
+    } else {
+        new_package.exports_no_require = false;
+        new_package.exports_require = false;
+    }
+
+    Some(new_package)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_spec() {
+        assert_eq!(
+            PackageSpec::from("chalk"),
+            PackageSpec {
+                name: String::from("chalk"),
+                version: None
+            }
+        );
+        assert_eq!(
+            PackageSpec::from("chalk@5.0.0"),
+            PackageSpec {
+                name: String::from("chalk"),
+                version: Some(String::from("5.0.0"))
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_scoped_package_spec() {
+        let spec = PackageSpec::from("@babel/core@next");
+        assert_eq!(spec.name, "@babel/core");
+        assert_eq!(spec.version_or_latest(), "next");
+
+        let spec = PackageSpec::from("@babel/core");
+        assert_eq!(spec.name, "@babel/core");
+        assert_eq!(spec.version_or_latest(), "latest");
+    }
+
+    #[test]
+    fn test_resolve_manifest_from_registry_document() {
+        let document = serde_json::json!({
+            "name": "pkg",
+            "dist-tags": { "latest": "1.0.0", "next": "2.0.0" },
+            "versions": {
+                "1.0.0": { "name": "pkg", "version": "1.0.0" },
+                "2.0.0": { "name": "pkg", "version": "2.0.0", "type": "module" }
+            }
+        });
+
+        let latest = resolve_manifest(document.clone(), &PackageSpec::from("pkg")).unwrap();
+        assert_eq!(latest["version"], "1.0.0");
+
+        let next = resolve_manifest(document.clone(), &PackageSpec::from("pkg@next")).unwrap();
+        assert_eq!(next["type"], "module");
+
+        assert!(resolve_manifest(document, &PackageSpec::from("pkg@9.9.9")).is_err());
+    }
+
+    #[test]
+    fn test_manifest_locations() {
+        let spec = PackageSpec::from("@babel/core");
+        let url = |url: &str| Location::Url(url.to_owned());
+
+        assert_eq!(
+            Source::default().manifest_location(&spec),
+            url("https://unpkg.com/@babel/core@latest/package.json")
+        );
+        assert_eq!(
+            Source::Registry(String::from("https://registry.npmjs.org/")).manifest_location(&spec),
+            url("https://registry.npmjs.org/@babel%2fcore/latest")
+        );
+        assert_eq!(
+            Source::Replicate(String::from(NPM_REPLICATE_URL)).manifest_location(&spec),
+            url("https://replicate.npmjs.com/@babel%2fcore")
+        );
+        assert_eq!(
+            Source::Mirror(PathBuf::from("mirror")).manifest_location(&spec),
+            Location::File(PathBuf::from("mirror/@babel/core.json"))
+        );
+    }
+}
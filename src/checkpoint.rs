@@ -0,0 +1,23 @@
+//! Reading and writing the JSON files long running commands save their progress to.
+
+use crate::Error;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::Path};
+
+/// Loads the checkpoint at `path`, or `None` if nothing has been saved there yet.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Error> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the checkpoint next to `path` before moving it into place, so a crash mid write never
+/// leaves a truncated checkpoint behind.
+pub(crate) fn save<T: Serialize>(value: &T, path: &Path) -> Result<(), Error> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(value)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request for `url` couldn't be completed.
+    Http {
+        url: String,
+        source: reqwest::Error,
+    },
+    /// `url` responded with a non-success status code.
+    Status {
+        url: String,
+        status: u16,
+    },
+    /// The manifest downloaded for `package` isn't a usable `package.json`.
+    InvalidManifest {
+        package: String,
+        reason: String,
+    },
+    /// The registry no longer has `package`, either because it responded with a 404 or because
+    /// the document records the package as unpublished.
+    Unpublished {
+        package: String,
+    },
+    /// Line `line` of the snapshot file at `path` doesn't match the export schema.
+    InvalidRecord {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    /// Reading from or writing to the configured store failed.
+    Store(String),
+    /// A required setting, such as an environment variable, is missing or invalid.
+    Config(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Parquet(parquet::errors::ParquetError),
+    /// Sending an email through the SMTP server failed.
+    Smtp(lettre::transport::smtp::Error),
+    /// A report template couldn't be parsed or rendered.
+    Template(String),
+    /// The HTTP server couldn't bind or stopped accepting connections.
+    Server(hyper::Error),
+}
+
+impl Error {
+    /// The HTTP status code of the response which caused this error, if there was one.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http { url, source } => write!(f, "failed to fetch {}: {}", url, source),
+            Error::Status { url, status } => {
+                write!(f, "failed to fetch {}: status {}", url, status)
+            }
+            Error::InvalidManifest { package, reason } => {
+                write!(f, "invalid package.json for {}: {}", package, reason)
+            }
+            Error::Unpublished { package } => write!(f, "{} has been unpublished", package),
+            Error::InvalidRecord { path, line, reason } => {
+                write!(f, "invalid record on line {} of {}: {}", line, path, reason)
+            }
+            Error::Store(message) => write!(f, "store error: {}", message),
+            Error::Config(message) => write!(f, "configuration error: {}", message),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "{}", e),
+            Error::Parquet(e) => write!(f, "{}", e),
+            Error::Smtp(e) => write!(f, "failed to send email: {}", e),
+            Error::Template(message) => write!(f, "template error: {}", message),
+            Error::Server(e) => write!(f, "server error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Parquet(e) => Some(e),
+            Error::Smtp(e) => Some(e),
+            Error::Server(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(e)
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        Error::Smtp(e)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Server(e)
+    }
+}
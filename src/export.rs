@@ -0,0 +1,472 @@
+//! Flat file dumps of everything in a store, for analysis in tools which can't talk to DynamoDB.
+//!
+//! Each table is written as `<table>.<format>` with a fixed set of columns, see [`STATS_COLUMNS`],
+//! [`PACKAGE_COLUMNS`] and [`AUDIT_COLUMNS`]. Columns are only ever added to the end, so existing
+//! notebooks and dashboards keep working as the export grows.
+
+use crate::{store::Store, AuditEntry, Error, Package, StatsEntry};
+use parquet::{
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    file::{
+        properties::WriterProperties,
+        writer::{FileWriter, SerializedFileWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Csv, Format::Jsonl, Format::Parquet];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(Error::Config(format!(
+                "unknown format `{}`, expected csv, jsonl or parquet",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Utf8,
+    Int64,
+    Boolean,
+    /// Nested JSON in JSON Lines files, serialized to a string in CSV and Parquet files.
+    Json,
+}
+
+/// A column of an exported table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnType,
+    pub nullable: bool,
+}
+
+const fn column(name: &'static str, kind: ColumnType) -> Column {
+    Column {
+        name,
+        kind,
+        nullable: false,
+    }
+}
+
+const fn nullable(name: &'static str, kind: ColumnType) -> Column {
+    Column {
+        name,
+        kind,
+        nullable: true,
+    }
+}
+
+pub const STATS_COLUMNS: &[Column] = &[
+    column("timestamp", ColumnType::Utf8),
+    column("total_packages", ColumnType::Int64),
+    column("failures", ColumnType::Int64),
+    column("type_module", ColumnType::Int64),
+    column("exports_require", ColumnType::Int64),
+    column("exports_no_require", ColumnType::Int64),
+    column("cjs", ColumnType::Int64),
+    column("dual", ColumnType::Int64),
+    column("esm_only", ColumnType::Int64),
+    // Missing from exports made before estimates were flagged
+    nullable("estimated", ColumnType::Boolean),
+];
+
+pub const PACKAGE_COLUMNS: &[Column] = &[
+    column("package_name", ColumnType::Utf8),
+    column("exports_require", ColumnType::Boolean),
+    column("exports_no_require", ColumnType::Boolean),
+    column("type_module", ColumnType::Boolean),
+    column("status", ColumnType::Utf8),
+    nullable("version", ColumnType::Utf8),
+    nullable("published", ColumnType::Utf8),
+    nullable("manifest", ColumnType::Json),
+    nullable("deprecated", ColumnType::Utf8),
+    nullable("inactive", ColumnType::Utf8),
+];
+
+pub const AUDIT_COLUMNS: &[Column] = &[
+    nullable("package_name_id", ColumnType::Utf8),
+    column("package_name", ColumnType::Utf8),
+    column("timestamp", ColumnType::Utf8),
+    column("change", ColumnType::Utf8),
+    column("old_value", ColumnType::Boolean),
+    column("new_value", ColumnType::Boolean),
+    nullable("seq", ColumnType::Int64),
+    nullable("old_version", ColumnType::Utf8),
+    nullable("new_version", ColumnType::Utf8),
+    nullable("published", ColumnType::Utf8),
+    nullable("manifest_diff", ColumnType::Json),
+    nullable("old_status", ColumnType::Utf8),
+    nullable("new_status", ColumnType::Utf8),
+    nullable("transition", ColumnType::Utf8),
+    nullable("message", ColumnType::Utf8),
+];
+
+/// A single cell, `None` when the column is null for the row.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Utf8(Option<String>),
+    Int64(Option<i64>),
+    Boolean(Option<bool>),
+    Json(Option<serde_json::Value>),
+}
+
+impl Value {
+    fn is_null(&self) -> bool {
+        matches!(
+            self,
+            Value::Utf8(None) | Value::Int64(None) | Value::Boolean(None) | Value::Json(None)
+        )
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Utf8(v) => serde_json::json!(v),
+            Value::Int64(v) => serde_json::json!(v),
+            Value::Boolean(v) => serde_json::json!(v),
+            Value::Json(v) => serde_json::json!(v),
+        }
+    }
+
+    /// The value as a string column, with JSON serialized.
+    fn to_text(&self) -> Option<String> {
+        match self {
+            Value::Utf8(v) => v.clone(),
+            Value::Json(v) => v.as_ref().map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Value::Json(Some(_)) => Value::Utf8(self.to_text()).to_csv(),
+            Value::Utf8(Some(v)) if v.contains(&[',', '"', '\n', '\r'][..]) => {
+                format!("\"{}\"", v.replace('"', "\"\""))
+            }
+            Value::Utf8(Some(v)) => v.clone(),
+            Value::Int64(Some(v)) => v.to_string(),
+            Value::Boolean(Some(v)) => v.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// A row of one of the exported tables.
+trait Record {
+    const TABLE: &'static str;
+    const COLUMNS: &'static [Column];
+
+    /// The cells of the row, in the same order as `COLUMNS`.
+    fn values(&self) -> Vec<Value>;
+}
+
+impl Record for StatsEntry {
+    const TABLE: &'static str = "stats";
+    const COLUMNS: &'static [Column] = STATS_COLUMNS;
+
+    fn values(&self) -> Vec<Value> {
+        let n = |v: isize| Value::Int64(Some(v as i64));
+
+        vec![
+            Value::Utf8(Some(self.timestamp.clone())),
+            n(self.total_packages),
+            n(self.failures),
+            n(self.type_module),
+            n(self.exports_require),
+            n(self.exports_no_require),
+            n(self.cjs),
+            n(self.dual),
+            n(self.esm_only),
+            Value::Boolean(Some(self.estimated)),
+        ]
+    }
+}
+
+impl Record for Package {
+    const TABLE: &'static str = "packages";
+    const COLUMNS: &'static [Column] = PACKAGE_COLUMNS;
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Utf8(Some(self.name.clone())),
+            Value::Boolean(Some(self.exports_require)),
+            Value::Boolean(Some(self.exports_no_require)),
+            Value::Boolean(Some(self.type_module)),
+            Value::Utf8(Some(self.status().to_string())),
+            Value::Utf8(self.version.clone()),
+            Value::Utf8(self.published.clone()),
+            Value::Json(Some(serde_json::json!(self.manifest))),
+            Value::Utf8(self.deprecated.clone()),
+            Value::Utf8(self.inactive.map(|i| i.to_string())),
+        ]
+    }
+}
+
+impl Record for AuditEntry {
+    const TABLE: &'static str = "audits";
+    const COLUMNS: &'static [Column] = AUDIT_COLUMNS;
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::Utf8(self.package_name_id.clone()),
+            Value::Utf8(Some(self.package_name.clone())),
+            Value::Utf8(Some(self.timestamp.clone())),
+            Value::Utf8(Some(self.change.clone())),
+            Value::Boolean(Some(self.old_value)),
+            Value::Boolean(Some(self.new_value)),
+            Value::Int64(self.seq.map(|seq| seq as i64)),
+            Value::Utf8(self.old_version.clone()),
+            Value::Utf8(self.new_version.clone()),
+            Value::Utf8(self.published.clone()),
+            Value::Json(Some(serde_json::json!(self.manifest_diff))),
+            Value::Utf8(self.old_status.map(|s| s.to_string())),
+            Value::Utf8(self.new_status.map(|s| s.to_string())),
+            Value::Utf8(self.transition().map(|t| t.to_string())),
+            Value::Utf8(self.message.clone()),
+        ]
+    }
+}
+
+/// Everything in a store at one point in time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Oldest first.
+    pub stats: Vec<StatsEntry>,
+    /// Sorted by name.
+    pub packages: Vec<Package>,
+    /// Oldest first.
+    pub audits: Vec<AuditEntry>,
+}
+
+impl Snapshot {
+    pub async fn from_store(store: &dyn Store) -> Result<Self, Error> {
+        let mut packages: Vec<Package> = store.packages().await?.into_values().collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Snapshot {
+            stats: store.stats().await?,
+            packages,
+            audits: store.audits().await?,
+        })
+    }
+
+    /// Writes `stats`, `packages` and `audits` files in `format` into `dir`, creating it if
+    /// needed. Returns the paths which were written.
+    pub fn write(&self, dir: &Path, format: Format) -> Result<Vec<PathBuf>, Error> {
+        fs::create_dir_all(dir)?;
+
+        Ok(vec![
+            write_table(dir, format, &self.stats)?,
+            write_table(dir, format, &self.packages)?,
+            write_table(dir, format, &self.audits)?,
+        ])
+    }
+}
+
+fn write_table<R: Record>(dir: &Path, format: Format, rows: &[R]) -> Result<PathBuf, Error> {
+    let path = dir.join(format!("{}.{}", R::TABLE, format.extension()));
+    let rows: Vec<Vec<Value>> = rows.iter().map(Record::values).collect();
+
+    match format {
+        Format::Csv => write_csv(&path, R::COLUMNS, &rows)?,
+        Format::Jsonl => write_jsonl(&path, R::COLUMNS, &rows)?,
+        Format::Parquet => write_parquet(&path, R::TABLE, R::COLUMNS, &rows)?,
+    }
+
+    Ok(path)
+}
+
+fn write_csv(path: &Path, columns: &[Column], rows: &[Vec<Value>]) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    let header: Vec<&str> = columns.iter().map(|c| c.name).collect();
+    writeln!(out, "{}", header.join(","))?;
+
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(Value::to_csv).collect();
+        writeln!(out, "{}", cells.join(","))?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_jsonl(path: &Path, columns: &[Column], rows: &[Vec<Value>]) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    for row in rows {
+        let object: serde_json::Map<String, serde_json::Value> = columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| (column.name.to_owned(), value.to_json()))
+            .collect();
+        writeln!(out, "{}", serde_json::Value::Object(object))?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn parquet_schema(table: &str, columns: &[Column]) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|c| {
+            let repetition = if c.nullable { "OPTIONAL" } else { "REQUIRED" };
+            let kind = match c.kind {
+                ColumnType::Utf8 | ColumnType::Json => "BYTE_ARRAY",
+                ColumnType::Int64 => "INT64",
+                ColumnType::Boolean => "BOOLEAN",
+            };
+            let logical = match c.kind {
+                ColumnType::Utf8 => " (UTF8)",
+                ColumnType::Json => " (JSON)",
+                _ => "",
+            };
+            format!("{} {} {}{};", repetition, kind, c.name, logical)
+        })
+        .collect();
+
+    format!("message {} {{ {} }}", table, fields.join(" "))
+}
+
+fn write_parquet(
+    path: &Path,
+    table: &str,
+    columns: &[Column],
+    rows: &[Vec<Value>],
+) -> Result<(), Error> {
+    let schema = Arc::new(parse_message_type(&parquet_schema(table, columns))?);
+    let props = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, props)?;
+
+    let mut row_group = writer.next_row_group()?;
+    for (i, column) in columns.iter().enumerate() {
+        let cells: Vec<&Value> = rows.iter().map(|row| &row[i]).collect();
+        // Definition levels mark which rows have a value, only needed for nullable columns
+        let levels: Vec<i16> = cells.iter().map(|v| (!v.is_null()) as i16).collect();
+        let levels = if column.nullable {
+            Some(&levels[..])
+        } else {
+            None
+        };
+
+        let mut column_writer = row_group
+            .next_column()?
+            .expect("the schema has a column for every value");
+
+        match &mut column_writer {
+            ColumnWriter::ByteArrayColumnWriter(w) => {
+                let values: Vec<ByteArray> = cells
+                    .iter()
+                    .filter_map(|v| v.to_text())
+                    .map(|s| ByteArray::from(s.as_str()))
+                    .collect();
+                w.write_batch(&values, levels, None)?;
+            }
+            ColumnWriter::Int64ColumnWriter(w) => {
+                let values: Vec<i64> = cells
+                    .iter()
+                    .filter_map(|v| match v {
+                        Value::Int64(v) => *v,
+                        _ => None,
+                    })
+                    .collect();
+                w.write_batch(&values, levels, None)?;
+            }
+            ColumnWriter::BoolColumnWriter(w) => {
+                let values: Vec<bool> = cells
+                    .iter()
+                    .filter_map(|v| match v {
+                        Value::Boolean(v) => *v,
+                        _ => None,
+                    })
+                    .collect();
+                w.write_batch(&values, levels, None)?;
+            }
+            _ => unreachable!("exports only use string, int64 and boolean columns"),
+        }
+
+        row_group.close_column(column_writer)?;
+    }
+
+    writer.close_row_group(row_group)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_quoting() {
+        assert_eq!(
+            Value::Utf8(Some(String::from("left-pad"))).to_csv(),
+            "left-pad"
+        );
+        assert_eq!(
+            Value::Utf8(Some(String::from("a,\"b\""))).to_csv(),
+            "\"a,\"\"b\"\"\""
+        );
+        assert_eq!(Value::Int64(None).to_csv(), "");
+    }
+
+    #[test]
+    fn test_parquet_schema() {
+        assert_eq!(
+            parquet_schema("audits", &AUDIT_COLUMNS[5..7]),
+            "message audits { REQUIRED BOOLEAN new_value; OPTIONAL INT64 seq; }"
+        );
+        assert_eq!(
+            parquet_schema("packages", &PACKAGE_COLUMNS[7..8]),
+            "message packages { OPTIONAL BYTE_ARRAY manifest (JSON); }"
+        );
+        for (table, columns) in [
+            ("stats", STATS_COLUMNS),
+            ("packages", PACKAGE_COLUMNS),
+            ("audits", AUDIT_COLUMNS),
+        ] {
+            assert!(parse_message_type(&parquet_schema(table, columns)).is_ok());
+        }
+    }
+}
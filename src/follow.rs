@@ -0,0 +1,357 @@
+//! Keeping tracked packages up to date by following the registry `_changes` feed, rather than
+//! re-fetching every package each day.
+
+use crate::{
+    add_publish_time,
+    check::{check_package, fetch_package_json, http_client},
+    checkpoint, deactivate_package, diff_packages,
+    store::Store,
+    AuditEntry, Cache, Error, Inactive, Package, PackageSpec, Source, NPM_REGISTRY_URL,
+};
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// A row of a CouchDB `_changes` response: package `id` was published or updated at `seq`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Change {
+    pub seq: u64,
+    pub id: String,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangesResponse {
+    results: Vec<Change>,
+    last_seq: u64,
+}
+
+/// The database info CouchDB serves at its root.
+#[derive(Debug, Deserialize)]
+struct DatabaseInfo {
+    update_seq: Value,
+}
+
+/// Where changes are read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Feed {
+    /// A CouchDB database such as replicate.npmjs.com, read through `{base_url}/_changes`.
+    Http(String),
+    /// A `_changes` response recorded to a file, for replaying a known set of changes.
+    File(PathBuf),
+}
+
+impl Feed {
+    /// Reads up to `limit` changes after `since`, along with the sequence to continue from.
+    async fn changes(
+        &self,
+        client: &reqwest::Client,
+        since: u64,
+        limit: usize,
+    ) -> Result<(Vec<Change>, u64), Error> {
+        match self {
+            Feed::Http(base_url) => {
+                let url = format!(
+                    "{}/_changes?since={}&limit={}",
+                    base_url.trim_end_matches('/'),
+                    since,
+                    limit
+                );
+                let changes: ChangesResponse =
+                    serde_json::from_str(&fetch_package_json(client, &url).await?)?;
+                Ok((changes.results, changes.last_seq.max(since)))
+            }
+            Feed::File(path) => {
+                let changes: ChangesResponse =
+                    serde_json::from_str(&tokio::fs::read_to_string(path).await?)?;
+                let results: Vec<Change> = changes
+                    .results
+                    .into_iter()
+                    .filter(|c| c.seq > since)
+                    .take(limit)
+                    .collect();
+                let last_seq = results.last().map_or(since, |c| c.seq);
+                Ok((results, last_seq))
+            }
+        }
+    }
+}
+
+impl Feed {
+    /// The sequence of the most recent change, where following starts without a checkpoint. A
+    /// recorded feed is replayed from its first change instead.
+    async fn current_seq(&self, client: &reqwest::Client) -> Result<u64, Error> {
+        match self {
+            Feed::Http(base_url) => {
+                let url = format!("{}/", base_url.trim_end_matches('/'));
+                let info: DatabaseInfo =
+                    serde_json::from_str(&fetch_package_json(client, &url).await?)?;
+                // CouchDB 2 and later use opaque strings starting with the number
+                let seq = match &info.update_seq {
+                    Value::String(seq) => seq.split('-').next().and_then(|n| n.parse().ok()),
+                    seq => seq.as_u64(),
+                };
+                seq.ok_or_else(|| {
+                    Error::Json(serde::de::Error::custom(format!(
+                        "unexpected update_seq {} from {}",
+                        info.update_seq, url
+                    )))
+                })
+            }
+            Feed::File(_) => Ok(0),
+        }
+    }
+}
+
+/// The last feed sequence which has been processed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FollowCheckpoint {
+    pub since: u64,
+    /// Packages whose re-check failed with a network error or an error status, along with the
+    /// sequence of the change which is still to be applied. They are retried with the next batch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub retry: BTreeMap<String, u64>,
+}
+
+impl FollowCheckpoint {
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        checkpoint::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        checkpoint::save(self, path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FollowOptions {
+    feed: Feed,
+    source: Source,
+    checkpoint: PathBuf,
+    batch_size: usize,
+    concurrency: usize,
+}
+
+impl FollowOptions {
+    pub fn new(feed: Feed, checkpoint: impl Into<PathBuf>) -> Self {
+        FollowOptions {
+            feed,
+            source: Source::Registry(NPM_REGISTRY_URL.to_owned()),
+            checkpoint: checkpoint.into(),
+            batch_size: 1000,
+            concurrency: 30,
+        }
+    }
+
+    /// Where changed packages are re-classified from.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// How many changes to request from the feed at a time.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The maximum number of requests in flight at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FollowSummary {
+    /// The sequence the next run will continue from.
+    pub since: u64,
+    /// Changes read from the feed, tracked or not.
+    pub changes: usize,
+    /// Tracked packages which were re-classified.
+    pub rechecked: usize,
+    /// Tracked packages which couldn't be fetched and will be retried by the next run.
+    pub retry: Vec<String>,
+    pub audits: Vec<AuditEntry>,
+}
+
+/// Reads the feed from the checkpoint in `options` until it is caught up, re-classifying every
+/// package in `tracked` which published in the meantime. Without a checkpoint, an HTTP feed is
+/// followed from its current sequence, so only changes from then on are applied.
+///
+/// Classification changes are written to `store` along with audit entries carrying the sequence
+/// of the publish which caused them. Tracked packages which were deleted from the registry are
+/// marked inactive instead. The checkpoint is saved after every batch of changes, along with the
+/// packages which couldn't be fetched so they are retried rather than skipped for good.
+pub async fn follow_changes(
+    store: &dyn Store,
+    tracked: &HashSet<String>,
+    options: &FollowOptions,
+) -> Result<FollowSummary, Error> {
+    let client = http_client();
+    let date = Utc::now().format("%F").to_string();
+
+    // A first run starts from the latest change rather than the whole history of the registry
+    let mut checkpoint = match FollowCheckpoint::load(&options.checkpoint)? {
+        Some(checkpoint) => checkpoint,
+        None => {
+            let checkpoint = FollowCheckpoint {
+                since: options.feed.current_seq(&client).await?,
+                ..FollowCheckpoint::default()
+            };
+            checkpoint.save(&options.checkpoint)?;
+            checkpoint
+        }
+    };
+    let mut stored = store.packages().await?;
+    let mut summary = FollowSummary::default();
+
+    let mut caught_up = false;
+    while !caught_up {
+        let (changes, last_seq) = options
+            .feed
+            .changes(&client, checkpoint.since, options.batch_size)
+            .await?;
+        // Once caught up, packages which failed earlier still get one more try
+        caught_up = changes.is_empty();
+        if caught_up && checkpoint.retry.is_empty() {
+            break;
+        }
+        summary.changes += changes.len();
+
+        let retries = std::mem::take(&mut checkpoint.retry)
+            .into_iter()
+            .filter(|(id, _)| tracked.contains(id))
+            .map(|(id, seq)| Change {
+                seq,
+                id,
+                deleted: false,
+            });
+
+        // A package may publish several times in one batch, only its latest change matters
+        let mut latest: HashMap<String, Change> = HashMap::new();
+        for change in retries.chain(changes.into_iter().filter(|c| tracked.contains(&c.id))) {
+            match latest.get(&change.id) {
+                Some(entry) if entry.seq >= change.seq => {}
+                _ => {
+                    latest.insert(change.id.clone(), change);
+                }
+            }
+        }
+        let changed: HashMap<&str, u64> =
+            latest.iter().map(|(id, c)| (id.as_str(), c.seq)).collect();
+
+        for change in latest.values().filter(|c| c.deleted) {
+            deactivate(
+                store,
+                &mut stored,
+                &change.id,
+                Some(change.seq),
+                &date,
+                &mut summary,
+            )
+            .await?;
+        }
+
+        let specs: Vec<PackageSpec> = latest
+            .values()
+            .filter(|c| !c.deleted)
+            .map(|c| PackageSpec::from(&c.id))
+            .collect();
+        let mut reports = stream::iter(specs)
+            .map(|spec| {
+                let name = spec.name.clone();
+                let check = check_package(
+                    client.clone(),
+                    options.source.clone(),
+                    Cache::Disabled,
+                    spec,
+                );
+                async move { (name, check.await) }
+            })
+            .buffer_unordered(options.concurrency);
+
+        while let Some((name, report)) = reports.next().await {
+            let seq = changed.get(name.as_str()).copied();
+            let report = match report {
+                Ok(report) => report,
+                Err(Error::Unpublished { .. }) => {
+                    deactivate(store, &mut stored, &name, seq, &date, &mut summary).await?;
+                    continue;
+                }
+                // Timeouts and server errors are worth another try, while a broken manifest
+                // stays broken until the next publish
+                Err(e @ (Error::Http { .. } | Error::Status { .. })) => {
+                    eprintln!("{}, retrying with the next batch", e);
+                    checkpoint.retry.insert(name, seq.unwrap_or_default());
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            summary.rechecked += 1;
+
+            let mut pkg = report.package;
+            pkg.name = name.clone();
+
+            let should_update = match stored.get(&name) {
+                Some(old_pkg) => {
+                    let (should_update, mut audits) = diff_packages(old_pkg, &pkg, &date);
+                    add_publish_time(&options.source, &mut pkg, &mut audits).await;
+                    for mut audit in audits {
+                        audit.seq = seq;
+                        store.put_audit(&audit).await?;
+                        summary.audits.push(audit);
+                    }
+                    should_update
+                }
+                None => true,
+            };
+
+            if should_update {
+                store.put_package(&pkg).await?;
+                stored.insert(name, pkg);
+            }
+        }
+
+        checkpoint.since = last_seq;
+        checkpoint.save(&options.checkpoint)?;
+    }
+
+    summary.since = checkpoint.since;
+    summary.retry = checkpoint.retry.keys().cloned().collect();
+    Ok(summary)
+}
+
+/// Marks a stored package as unpublished. Packages which were never stored have nothing to mark.
+async fn deactivate(
+    store: &dyn Store,
+    stored: &mut HashMap<String, Package>,
+    name: &str,
+    seq: Option<u64>,
+    date: &str,
+    summary: &mut FollowSummary,
+) -> Result<(), Error> {
+    let (pkg, mut audit) = match stored
+        .get(name)
+        .and_then(|old_pkg| deactivate_package(old_pkg, Inactive::Unpublished, date))
+    {
+        Some(deactivated) => deactivated,
+        None => return Ok(()),
+    };
+
+    audit.seq = seq;
+    store.put_audit(&audit).await?;
+    summary.audits.push(audit);
+    store.put_package(&pkg).await?;
+    stored.insert(pkg.name.clone(), pkg);
+    Ok(())
+}
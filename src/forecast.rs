@@ -0,0 +1,403 @@
+//! Projections of ESM adoption, fitted to the historical stats series.
+
+use crate::{Error, Metric, SeriesPoint, StatsSeries};
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// The day the stats table starts, and so the default start of the fitted history.
+pub const FIRST_STATS_DATE: &str = "2021-12-01";
+
+// Projections further out than this aren't worth a date
+const MAX_PROJECTION_DAYS: f64 = 365.0 * 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Model {
+    /// A straight line through the adoption percentage.
+    Linear,
+    /// An S-curve levelling out at 100%, fitted as a straight line through the log-odds.
+    Logistic,
+}
+
+impl FromStr for Model {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Model::Linear),
+            "logistic" => Ok(Model::Logistic),
+            _ => Err(Error::Config(format!(
+                "unknown model `{}`, expected linear or logistic",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Model::Linear => write!(f, "linear"),
+            Model::Logistic => write!(f, "logistic"),
+        }
+    }
+}
+
+/// A model fitted to a percentage over time, as `intercept + slope * days` since `origin` (in
+/// log-odds for [`Model::Logistic`]).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fit {
+    pub model: Model,
+    pub origin: NaiveDate,
+    pub intercept: f64,
+    pub slope: f64,
+    /// How much of the variation in the observed percentages the fit explains, from 0 to 1.
+    pub r_squared: f64,
+}
+
+impl Fit {
+    /// Fits `model` to `(date, percentage)` points. Returns `None` without at least two usable
+    /// points on different days.
+    pub fn new(model: Model, points: &[(NaiveDate, f64)]) -> Option<Fit> {
+        let origin = points.iter().map(|(date, _)| *date).min()?;
+
+        // The log-odds are undefined at 0% and 100%, so those points can't inform the S-curve
+        let transformed: Vec<(f64, f64)> = points
+            .iter()
+            .filter(|(_, value)| model == Model::Linear || (*value > 0.0 && *value < 100.0))
+            .map(|(date, value)| {
+                let x = (*date - origin).num_days() as f64;
+                match model {
+                    Model::Linear => (x, *value),
+                    Model::Logistic => (x, logit(*value)),
+                }
+            })
+            .collect();
+
+        let (intercept, slope) = least_squares(&transformed)?;
+        let mut fit = Fit {
+            model,
+            origin,
+            intercept,
+            slope,
+            r_squared: 0.0,
+        };
+        fit.r_squared = fit.r_squared(points);
+        Some(fit)
+    }
+
+    /// The fitted percentage on `date`.
+    pub fn value_at(&self, date: NaiveDate) -> f64 {
+        let y = self.intercept + self.slope * (date - self.origin).num_days() as f64;
+        match self.model {
+            Model::Linear => y,
+            Model::Logistic => 100.0 / (1.0 + (-y).exp()),
+        }
+    }
+
+    /// The first day the fit reaches `target` percent, if it ever does while still growing. The
+    /// fit only looks forward from `origin`, so a target it starts out above has no date.
+    pub fn date_reaching(&self, target: f64) -> Option<NaiveDate> {
+        if self.slope <= 0.0 {
+            return None;
+        }
+
+        let y = match self.model {
+            Model::Linear => target,
+            Model::Logistic if target > 0.0 && target < 100.0 => logit(target),
+            Model::Logistic => return None,
+        };
+        let days = ((y - self.intercept) / self.slope).ceil();
+
+        if !(0.0..=MAX_PROJECTION_DAYS).contains(&days) {
+            return None;
+        }
+        Some(self.origin + Duration::days(days as i64))
+    }
+
+    fn r_squared(&self, points: &[(NaiveDate, f64)]) -> f64 {
+        let mean = points.iter().map(|(_, v)| v).sum::<f64>() / points.len() as f64;
+        let total: f64 = points.iter().map(|(_, v)| (v - mean).powi(2)).sum();
+        let residual: f64 = points
+            .iter()
+            .map(|(date, v)| (v - self.value_at(*date)).powi(2))
+            .sum();
+
+        if total == 0.0 {
+            0.0
+        } else {
+            1.0 - residual / total
+        }
+    }
+}
+
+fn logit(percent: f64) -> f64 {
+    let p = percent / 100.0;
+    (p / (1.0 - p)).ln()
+}
+
+/// The `(intercept, slope)` of the least squares line through `points`.
+fn least_squares(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+
+    if points.len() < 2 || variance == 0.0 {
+        return None;
+    }
+
+    let slope = covariance / variance;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+/// When adoption hit, or is projected to hit, a given percentage.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Milestone {
+    pub target: f64,
+    /// The first recorded day at or above the target.
+    pub reached: Option<NaiveDate>,
+    pub linear: Option<NaiveDate>,
+    pub logistic: Option<NaiveDate>,
+}
+
+/// Both models fitted to the adoption percentage of a series, with the projected milestones.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdoptionForecast {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// The most recently recorded adoption percentage.
+    pub latest: Option<f64>,
+    pub linear: Option<Fit>,
+    pub logistic: Option<Fit>,
+    pub milestones: Vec<Milestone>,
+}
+
+/// Fits the adoption percentage of every recorded (not filled) day in `series` and projects when
+/// it reaches each of the `milestones` percentages.
+///
+/// [Estimated](crate::StatsEntry::estimated) days understate adoption, so they're only fitted when
+/// the series has nothing else. They still count towards milestones already reached.
+pub fn forecast_adoption(series: &StatsSeries, milestones: &[f64]) -> AdoptionForecast {
+    let recorded: Vec<&SeriesPoint> = series.points.iter().filter(|p| !p.filled).collect();
+    let points: Vec<(NaiveDate, f64)> = recorded
+        .iter()
+        .map(|p| (p.date, Metric::Adoption.value(&p.entry)))
+        .collect();
+
+    let exact: Vec<(NaiveDate, f64)> = recorded
+        .iter()
+        .zip(&points)
+        .filter(|(p, _)| !p.entry.estimated)
+        .map(|(_, point)| *point)
+        .collect();
+    let fitted = if exact.is_empty() { &points } else { &exact };
+
+    let linear = Fit::new(Model::Linear, fitted);
+    let logistic = Fit::new(Model::Logistic, fitted);
+
+    let milestones = milestones
+        .iter()
+        .map(|&target| {
+            let reached = points
+                .iter()
+                .find(|(_, value)| *value >= target)
+                .map(|(date, _)| *date);
+
+            // Once reached, there's nothing left to project
+            let project = |fit: &Option<Fit>| match reached {
+                Some(_) => None,
+                None => fit.as_ref()?.date_reaching(target),
+            };
+
+            Milestone {
+                target,
+                reached,
+                linear: project(&linear),
+                logistic: project(&logistic),
+            }
+        })
+        .collect();
+
+    AdoptionForecast {
+        from: series.from,
+        to: series.to,
+        latest: points.last().map(|(_, value)| *value),
+        linear,
+        logistic,
+        milestones,
+    }
+}
+
+impl fmt::Display for AdoptionForecast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let latest = match self.latest {
+            Some(latest) => latest,
+            None => {
+                return write!(
+                    f,
+                    "No stats between {} and {} to forecast from",
+                    self.from, self.to
+                )
+            }
+        };
+
+        writeln!(
+            f,
+            "Adoption forecast from {} to {} ({:.2}% now)",
+            self.from, self.to, latest
+        )?;
+        if let Some(fit) = &self.linear {
+            writeln!(
+                f,
+                "Linear: {:+.3}pp/day (R² {:.2})",
+                fit.slope, fit.r_squared
+            )?;
+        }
+        if let Some(fit) = &self.logistic {
+            writeln!(
+                f,
+                "Logistic: {:+.2}% growth rate/day (R² {:.2})",
+                fit.slope * 100.0,
+                fit.r_squared
+            )?;
+        }
+
+        let date = |date: Option<NaiveDate>| date.map_or(String::from("never"), |d| d.to_string());
+        let lines: Vec<String> = self
+            .milestones
+            .iter()
+            .map(|m| match m.reached {
+                Some(reached) => format!("{}%: reached on {}", m.target, reached),
+                None => format!(
+                    "{}%: {} (linear), {} (logistic)",
+                    m.target,
+                    date(m.linear),
+                    date(m.logistic)
+                ),
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GapPolicy, StatsEntry};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    fn series(adoption: impl Fn(i64) -> f64, days: i64) -> StatsSeries {
+        let from = date("2022-01-01");
+        let entries = (0..days)
+            .map(|day| {
+                let dual = (adoption(day) * 100.0).round() as isize;
+                StatsEntry {
+                    timestamp: (from + Duration::days(day)).format("%F").to_string(),
+                    total_packages: 10_000,
+                    dual,
+                    cjs: 10_000 - dual,
+                    ..StatsEntry::default()
+                }
+            })
+            .collect();
+
+        StatsSeries::from_entries(
+            from,
+            from + Duration::days(days - 1),
+            entries,
+            GapPolicy::Flag,
+        )
+    }
+
+    #[test]
+    fn test_linear_projection() {
+        // 10% on the first day, rising by 0.1pp a day reaches 50% 400 days in
+        let forecast = forecast_adoption(&series(|day| 10.0 + day as f64 * 0.1, 30), &[5.0, 50.0]);
+
+        let linear = forecast.linear.as_ref().unwrap();
+        assert!((linear.slope - 0.1).abs() < 1e-6);
+        assert!(linear.r_squared > 0.999);
+
+        assert_eq!(forecast.milestones[0].reached, Some(date("2022-01-01")));
+        assert_eq!(forecast.milestones[0].linear, None);
+        assert_eq!(forecast.milestones[1].reached, None);
+        assert_eq!(
+            forecast.milestones[1].linear,
+            Some(date("2022-01-01") + Duration::days(400))
+        );
+    }
+
+    #[test]
+    fn test_logistic_projection() {
+        // An S-curve crossing 50% on day 200
+        let curve = |day: i64| 100.0 / (1.0 + (-0.02 * (day - 200) as f64).exp());
+        let forecast = forecast_adoption(&series(curve, 60), &[50.0]);
+
+        let logistic = forecast.logistic.as_ref().unwrap();
+        assert!((logistic.slope - 0.02).abs() < 1e-3);
+
+        let projected = forecast.milestones[0].logistic.unwrap();
+        let expected = date("2022-01-01") + Duration::days(200);
+        assert!((projected - expected).num_days().abs() <= 2);
+        assert!(forecast.milestones[0].linear.unwrap() > projected);
+    }
+
+    #[test]
+    fn test_estimated_days_are_not_fitted() {
+        // Estimated days read 5pp low, which a fit across the switch would take as growth
+        let mut history = series(|_| 20.0, 20);
+        for point in &mut history.points[..10] {
+            point.entry.estimated = true;
+            point.entry.dual -= 500;
+            point.entry.cjs += 500;
+        }
+
+        let forecast = forecast_adoption(&history, &[15.0, 50.0]);
+        assert_eq!(forecast.linear.unwrap().slope, 0.0);
+        assert_eq!(forecast.milestones[0].reached, Some(date("2022-01-01")));
+        assert_eq!(forecast.milestones[1].linear, None);
+    }
+
+    #[test]
+    fn test_no_date_for_targets_the_fit_starts_above() {
+        let fit = Fit {
+            model: Model::Linear,
+            origin: date("2022-01-01"),
+            intercept: 60.0,
+            slope: 0.1,
+            r_squared: 1.0,
+        };
+
+        assert_eq!(fit.date_reaching(50.0), None);
+        assert_eq!(fit.date_reaching(60.0), Some(date("2022-01-01")));
+        assert_eq!(fit.date_reaching(61.0), Some(date("2022-01-11")));
+
+        let logistic = Fit {
+            model: Model::Logistic,
+            intercept: logit(60.0),
+            ..fit
+        };
+        assert_eq!(logistic.date_reaching(25.0), None);
+    }
+
+    #[test]
+    fn test_flat_or_missing_series_has_no_projection() {
+        let flat = forecast_adoption(&series(|_| 20.0, 10), &[50.0]);
+        assert_eq!(flat.milestones[0].linear, None);
+        assert_eq!(flat.milestones[0].logistic, None);
+
+        let empty = forecast_adoption(&series(|_| 20.0, 0), &[50.0]);
+        assert_eq!(empty.latest, None);
+        assert!(empty.linear.is_none());
+    }
+}
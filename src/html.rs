@@ -0,0 +1,399 @@
+//! A single page report of the collected data, with the charts inlined as SVG and a little
+//! script for sorting the package table, so it can be published or attached anywhere as one file.
+
+use crate::{notify::escape_html, AuditEntry, Chart, Metric, Package, PackageStatus, StatsSeries};
+use std::collections::BTreeMap;
+
+const STYLE: &str =
+    "body { font-family: sans-serif; margin: 2em auto; max-width: 64em; color: #1c2833; }
+h1, h2 { font-weight: normal; }
+.cards { display: flex; gap: 1em; flex-wrap: wrap; }
+.card { border: 1px solid #d5d8dc; border-radius: 4px; padding: 0.8em 1.2em; }
+.card strong { display: block; font-size: 1.6em; }
+.up { color: #1e8449; }
+.down { color: #c0392b; }
+figure { margin: 1em 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #eaecee; }
+th { cursor: pointer; user-select: none; }
+th[aria-sort=ascending]::after { content: \" ▲\"; }
+th[aria-sort=descending]::after { content: \" ▼\"; }
+tr.inactive { color: #99a3a4; }
+input { padding: 0.3em; margin-bottom: 0.6em; width: 20em; }
+.timeline li { margin: 0.2em 0; }";
+
+/// Sorts the table by the clicked column, numerically when the cells have a `data-sort` value,
+/// and filters its rows by the search box.
+const SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach((th, column) => {
+  th.addEventListener('click', () => {
+    const table = th.closest('table');
+    const ascending = th.getAttribute('aria-sort') !== 'ascending';
+    table.querySelectorAll('th').forEach(other => other.removeAttribute('aria-sort'));
+    th.setAttribute('aria-sort', ascending ? 'ascending' : 'descending');
+    const key = row => {
+      const cell = row.cells[column];
+      return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent;
+    };
+    const rows = Array.from(table.tBodies[0].rows);
+    rows.sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      const order = typeof x === 'number' ? x - y : x.localeCompare(y);
+      return ascending ? order : -order;
+    });
+    rows.forEach(row => table.tBodies[0].appendChild(row));
+  });
+});
+document.getElementById('package-filter').addEventListener('input', event => {
+  const search = event.target.value.toLowerCase();
+  document.querySelectorAll('#packages tbody tr').forEach(row => {
+    row.hidden = !row.cells[0].textContent.toLowerCase().includes(search);
+  });
+});";
+
+/// Everything the report shows.
+#[derive(Debug, Clone)]
+pub struct HtmlReport {
+    pub title: String,
+    /// When the report was generated, shown under the title.
+    pub generated: String,
+    pub series: StatsSeries,
+    pub packages: Vec<Package>,
+    /// The audit log over the range of `series`, oldest first.
+    pub audits: Vec<AuditEntry>,
+    /// How many of the most recent audit entries the timeline lists.
+    pub timeline_limit: usize,
+}
+
+impl HtmlReport {
+    pub fn new(
+        series: StatsSeries,
+        packages: Vec<Package>,
+        audits: Vec<AuditEntry>,
+        generated: impl Into<String>,
+    ) -> Self {
+        HtmlReport {
+            title: String::from("ESM Checker report"),
+            generated: generated.into(),
+            series,
+            packages,
+            audits,
+            timeline_limit: 500,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn timeline_limit(mut self, limit: usize) -> Self {
+        self.timeline_limit = limit;
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let title = escape_html(&self.title);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{title}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>Stats from {} to {}, generated {}.</p>\n",
+            self.series.from,
+            self.series.to,
+            escape_html(&self.generated),
+        );
+
+        html.push_str(&self.cards());
+        html.push_str(&self.charts());
+        html.push_str(&self.package_table());
+        html.push_str(&self.timeline());
+
+        html.push_str(&format!(
+            "<script>\n{}\n</script>\n</body>\n</html>\n",
+            SCRIPT
+        ));
+        html
+    }
+
+    /// The latest figures, with how they moved over the range.
+    fn cards(&self) -> String {
+        let (first, latest) = match (self.series.first(), self.series.latest()) {
+            (Some(first), Some(latest)) => (&first.entry, &latest.entry),
+            _ => return String::from("<p>No stats were recorded in this range.</p>\n"),
+        };
+        let diff = latest - first;
+
+        let card = |name: &str, value: String, change: f64, unit: &str| {
+            let class = if change > 0.0 {
+                "up"
+            } else if change < 0.0 {
+                "down"
+            } else {
+                ""
+            };
+            format!(
+                "<div class=\"card\">{}<strong>{}</strong><span class=\"{}\">{:+}{} since {}</span></div>\n",
+                name,
+                value,
+                class,
+                change,
+                unit,
+                escape_html(&diff.since)
+            )
+        };
+
+        let mut html = String::from("<div class=\"cards\">\n");
+        html.push_str(&card(
+            "ESM adoption",
+            format!("{:.2}%", latest.adoption()),
+            (diff.adoption_pp * 100.0).round() / 100.0,
+            "pp",
+        ));
+        html.push_str(&card(
+            "Packages",
+            latest.total_packages.to_string(),
+            diff.total_packages as f64,
+            "",
+        ));
+        html.push_str(&card("Dual", latest.dual.to_string(), diff.dual as f64, ""));
+        html.push_str(&card(
+            "ESM only",
+            latest.esm_only.to_string(),
+            diff.esm_only as f64,
+            "",
+        ));
+        html.push_str("</div>\n");
+        html
+    }
+
+    fn charts(&self) -> String {
+        let charts = [
+            Chart::line("ESM adoption")
+                .unit("%")
+                .metrics(&self.series, &[Metric::Adoption]),
+            Chart::stacked_area("Packages by status")
+                .metrics(&self.series, &[Metric::Cjs, Metric::Dual, Metric::EsmOnly]),
+            Chart::line("Packages by field").metrics(
+                &self.series,
+                &[
+                    Metric::TypeModule,
+                    Metric::ExportsRequire,
+                    Metric::ExportsNoRequire,
+                ],
+            ),
+        ];
+
+        let mut html = String::from("<h2>Trends</h2>\n");
+        for chart in charts {
+            html.push_str(&format!("<figure>\n{}</figure>\n", chart.svg()));
+        }
+        html
+    }
+
+    fn package_table(&self) -> String {
+        let mut packages: Vec<&Package> = self.packages.iter().collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut html = format!(
+            "<h2>Packages</h2>\n<input id=\"package-filter\" type=\"search\" placeholder=\"Filter {} packages\">\n<table id=\"packages\" class=\"sortable\">\n<thead><tr><th>Name</th><th>Version</th><th>Status</th><th>type: module</th><th>exports.require</th><th>exports without require</th><th>Deprecated</th><th>Inactive</th></tr></thead>\n<tbody>\n",
+            packages.len()
+        );
+        let flag = |set: bool| {
+            format!(
+                "<td data-sort=\"{}\">{}</td>",
+                set as u8,
+                if set { "✔" } else { "" }
+            )
+        };
+
+        for pkg in packages {
+            let status = pkg.status();
+            html.push_str(&format!(
+                "<tr{}><td>{}</td><td>{}</td><td data-sort=\"{}\">{}</td>{}{}{}<td>{}</td><td>{}</td></tr>\n",
+                if pkg.inactive.is_some() { " class=\"inactive\"" } else { "" },
+                escape_html(&pkg.name),
+                escape_html(pkg.version.as_deref().unwrap_or("")),
+                status_rank(status),
+                status,
+                flag(pkg.type_module),
+                flag(pkg.exports_require),
+                flag(pkg.exports_no_require),
+                escape_html(pkg.deprecated.as_deref().unwrap_or("")),
+                pkg.inactive.map(|i| i.to_string()).unwrap_or_default()
+            ));
+        }
+
+        html.push_str("</tbody>\n</table>\n");
+        html
+    }
+
+    /// The most recent audit entries grouped by day, newest first, with the entries of one check
+    /// of a package on a single line.
+    fn timeline(&self) -> String {
+        let mut html = String::from("<h2>Timeline</h2>\n");
+        if self.audits.is_empty() {
+            html.push_str("<p>No changes were recorded in this range.</p>\n");
+            return html;
+        }
+
+        let skip = self.audits.len().saturating_sub(self.timeline_limit);
+        let mut days: BTreeMap<&str, Vec<(String, Vec<&AuditEntry>)>> = BTreeMap::new();
+        for audit in &self.audits[skip..] {
+            let checks = days.entry(audit.timestamp.as_str()).or_default();
+            let key = format!(
+                "{}@{}",
+                audit.package_name,
+                audit.new_version.as_deref().unwrap_or("")
+            );
+            match checks.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, entries)) => entries.push(audit),
+                None => checks.push((key, vec![audit])),
+            }
+        }
+
+        html.push_str("<div class=\"timeline\">\n");
+        for (day, checks) in days.iter().rev() {
+            html.push_str(&format!("<h3>{}</h3>\n<ul>\n", escape_html(day)));
+            for (_, entries) in checks {
+                html.push_str(&format!("<li>{}</li>\n", timeline_entry(entries)));
+            }
+            html.push_str("</ul>\n");
+        }
+        if skip > 0 {
+            html.push_str(&format!("<p>…and {} earlier changes.</p>\n", skip));
+        }
+        html.push_str("</div>\n");
+        html
+    }
+}
+
+/// Orders statuses from CommonJS to ESM only when sorting the table.
+fn status_rank(status: PackageStatus) -> u8 {
+    match status {
+        PackageStatus::Cjs => 0,
+        PackageStatus::Dual => 1,
+        PackageStatus::EsmOnly => 2,
+    }
+}
+
+/// Such as `chalk 4.1.2 → 5.0.0: cjs → esm-only (type_module, exports_no_require)`.
+fn timeline_entry(entries: &[&AuditEntry]) -> String {
+    let audit = entries[0];
+    let mut line = format!("<strong>{}</strong>", escape_html(&audit.package_name));
+
+    match (&audit.old_version, &audit.new_version) {
+        (Some(old), Some(new)) if old != new => {
+            line.push_str(&format!(" {} → {}", escape_html(old), escape_html(new)))
+        }
+        (_, Some(new)) => line.push_str(&format!(" {}", escape_html(new))),
+        _ => {}
+    }
+    match (audit.old_status, audit.new_status) {
+        (Some(old), Some(new)) if old != new => {
+            let class = match audit.transition() {
+                Some(crate::Transition::Adoption) => "up",
+                Some(crate::Transition::Regression) => "down",
+                _ => "",
+            };
+            line.push_str(&format!(
+                ": <span class=\"{}\">{} → {}</span>",
+                class, old, new
+            ))
+        }
+        _ => line.push(':'),
+    }
+
+    let changes: Vec<String> = entries
+        .iter()
+        .map(|a| match &a.message {
+            Some(message) => format!("{}: {}", a.change, message),
+            None => a.change.clone(),
+        })
+        .collect();
+    line.push_str(&format!(" ({})", escape_html(&changes.join(", "))));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GapPolicy, Inactive, StatsEntry};
+    use chrono::NaiveDate;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    fn report() -> HtmlReport {
+        let entry = |timestamp: &str, dual: isize| StatsEntry {
+            timestamp: timestamp.to_owned(),
+            total_packages: 100,
+            exports_require: dual,
+            dual,
+            cjs: 100 - dual,
+            ..StatsEntry::default()
+        };
+        let series = StatsSeries::from_entries(
+            date("2022-01-01"),
+            date("2022-01-03"),
+            vec![entry("2022-01-01", 10), entry("2022-01-03", 12)],
+            GapPolicy::Flag,
+        );
+        let packages = vec![
+            Package {
+                name: String::from("left-pad"),
+                inactive: Some(Inactive::Unpublished),
+                ..Package::default()
+            },
+            Package {
+                name: String::from("<script>"),
+                version: Some(String::from("1.0.0")),
+                exports_require: true,
+                type_module: true,
+                ..Package::default()
+            },
+        ];
+        let went_dual = |change: &str| AuditEntry {
+            package_name: String::from("chalk"),
+            timestamp: String::from("2022-01-03"),
+            change: change.to_owned(),
+            old_version: Some(String::from("4.1.2")),
+            new_version: Some(String::from("5.0.0")),
+            old_status: Some(PackageStatus::Cjs),
+            new_status: Some(PackageStatus::Dual),
+            ..AuditEntry::default()
+        };
+
+        HtmlReport::new(
+            series,
+            packages,
+            vec![went_dual("type_module"), went_dual("exports_require")],
+            "2022-01-03",
+        )
+    }
+
+    #[test]
+    fn test_render() {
+        let html = report().render();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(
+            html.contains("<strong>12.00%</strong><span class=\"up\">+2pp since 2022-01-01</span>")
+        );
+        assert_eq!(html.matches("<svg ").count(), 3);
+        // Escaped, and sorted by name
+        assert!(
+            html.contains("<tr><td>&lt;script&gt;</td><td>1.0.0</td><td data-sort=\"1\">dual</td>")
+        );
+        assert!(html.contains("<tr class=\"inactive\"><td>left-pad</td>"));
+        assert!(html.contains(
+            "<li><strong>chalk</strong> 4.1.2 → 5.0.0: <span class=\"up\">cjs → dual</span> (type_module, exports_require)</li>"
+        ));
+        assert!(!html.contains("earlier changes"));
+    }
+
+    #[test]
+    fn test_timeline_limit() {
+        let html = report().timeline_limit(1).render();
+
+        assert!(html.contains("(exports_require)</li>"));
+        assert!(html.contains("<p>…and 1 earlier changes.</p>"));
+    }
+}
@@ -0,0 +1,345 @@
+//! Loading a JSON Lines export back into a store, for seeding a fresh store from a snapshot.
+
+use crate::{
+    store::Store, AuditEntry, Column, ColumnType, Error, Package, Snapshot, StatsEntry,
+    AUDIT_COLUMNS, PACKAGE_COLUMNS, STATS_COLUMNS,
+};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+type Row = serde_json::Map<String, Value>;
+
+/// Reads the rows of a `.jsonl` export, checking each against `columns`. A missing file reads as
+/// no rows. Columns which aren't part of the schema are ignored, so snapshots written by newer
+/// versions can still be imported.
+fn read_rows(path: &Path, columns: &[Column]) -> Result<Vec<Row>, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut rows = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let invalid = |reason: String| Error::InvalidRecord {
+            path: path.display().to_string(),
+            line: i + 1,
+            reason,
+        };
+        let row = match serde_json::from_str(line).map_err(|e| invalid(e.to_string()))? {
+            Value::Object(row) => row,
+            _ => return Err(invalid(String::from("expected a JSON object"))),
+        };
+        check_row(&row, columns).map_err(invalid)?;
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn check_row(row: &Row, columns: &[Column]) -> Result<(), String> {
+    for column in columns {
+        let value = match row.get(column.name) {
+            None | Some(Value::Null) if column.nullable => continue,
+            None => return Err(format!("missing column `{}`", column.name)),
+            Some(value) => value,
+        };
+
+        let valid = match column.kind {
+            ColumnType::Utf8 => value.is_string(),
+            ColumnType::Int64 => value.is_i64(),
+            ColumnType::Boolean => value.is_boolean(),
+            ColumnType::Json => true,
+        };
+        if !valid {
+            return Err(format!(
+                "expected column `{}` to be {:?}, found {}",
+                column.name, column.kind, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Rows have been checked against their schema by the time these are used
+
+fn string(row: &Row, column: &str) -> String {
+    optional_string(row, column).unwrap_or_default()
+}
+
+fn optional_string(row: &Row, column: &str) -> Option<String> {
+    row.get(column)?.as_str().map(str::to_owned)
+}
+
+fn int(row: &Row, column: &str) -> isize {
+    row.get(column).and_then(Value::as_i64).unwrap_or_default() as isize
+}
+
+fn boolean(row: &Row, column: &str) -> bool {
+    row.get(column).and_then(Value::as_bool).unwrap_or_default()
+}
+
+fn json<T: serde::de::DeserializeOwned + Default>(row: &Row, column: &str) -> T {
+    row.get(column)
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+impl Snapshot {
+    /// Reads the `stats.jsonl`, `packages.jsonl` and `audits.jsonl` files written by
+    /// [`Snapshot::write`] from `dir`. Tables without a file are left empty.
+    pub fn read(dir: &Path) -> Result<Self, Error> {
+        let stats = read_rows(&dir.join("stats.jsonl"), STATS_COLUMNS)?
+            .iter()
+            .map(|row| StatsEntry {
+                timestamp: string(row, "timestamp"),
+                total_packages: int(row, "total_packages"),
+                failures: int(row, "failures"),
+                type_module: int(row, "type_module"),
+                exports_require: int(row, "exports_require"),
+                exports_no_require: int(row, "exports_no_require"),
+                cjs: int(row, "cjs"),
+                dual: int(row, "dual"),
+                esm_only: int(row, "esm_only"),
+                estimated: boolean(row, "estimated"),
+            })
+            .collect();
+
+        // `status` is derived from the other columns, so it isn't read back
+        let packages = read_rows(&dir.join("packages.jsonl"), PACKAGE_COLUMNS)?
+            .iter()
+            .map(|row| Package {
+                name: string(row, "package_name"),
+                exports_require: boolean(row, "exports_require"),
+                exports_no_require: boolean(row, "exports_no_require"),
+                type_module: boolean(row, "type_module"),
+                version: optional_string(row, "version"),
+                published: optional_string(row, "published"),
+                manifest: json(row, "manifest"),
+                deprecated: optional_string(row, "deprecated"),
+                inactive: optional_string(row, "inactive").and_then(|s| s.parse().ok()),
+            })
+            .collect();
+
+        let audits = read_rows(&dir.join("audits.jsonl"), AUDIT_COLUMNS)?
+            .iter()
+            .map(|row| AuditEntry {
+                package_name: string(row, "package_name"),
+                timestamp: string(row, "timestamp"),
+                change: string(row, "change"),
+                old_value: boolean(row, "old_value"),
+                new_value: boolean(row, "new_value"),
+                seq: row.get("seq").and_then(Value::as_u64),
+                package_name_id: optional_string(row, "package_name_id"),
+                old_version: optional_string(row, "old_version"),
+                new_version: optional_string(row, "new_version"),
+                published: optional_string(row, "published"),
+                manifest_diff: json(row, "manifest_diff"),
+                // `transition` is derived from the statuses, so it isn't read back
+                old_status: optional_string(row, "old_status").and_then(|s| s.parse().ok()),
+                new_status: optional_string(row, "new_status").and_then(|s| s.parse().ok()),
+                message: optional_string(row, "message"),
+            })
+            .collect();
+
+        Ok(Snapshot {
+            stats,
+            packages,
+            audits,
+        })
+    }
+}
+
+/// What happened to the rows of one table during an import.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TableSummary {
+    /// Rows in the snapshot.
+    pub read: usize,
+    /// Rows with the same key as a later row in the snapshot, which replaced them.
+    pub duplicates: usize,
+    /// Rows the store already had, unchanged.
+    pub existing: usize,
+    /// Rows written to the store, or which would have been on a dry run.
+    pub written: usize,
+}
+
+impl fmt::Display for TableSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} read, {} duplicates, {} already stored, {} written",
+            self.read, self.duplicates, self.existing, self.written
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub stats: TableSummary,
+    pub packages: TableSummary,
+    pub audits: TableSummary,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "stats: {}", self.stats)?;
+        writeln!(f, "packages: {}", self.packages)?;
+        write!(f, "audits: {}", self.audits)
+    }
+}
+
+/// Keeps the last row for every key, preserving the order keys were first seen in.
+fn dedupe<T: Clone, K: Ord + Clone>(rows: &[T], key: impl Fn(&T) -> K) -> (Vec<T>, usize) {
+    let mut order = vec![];
+    let mut latest = BTreeMap::new();
+
+    for row in rows {
+        let k = key(row);
+        if latest.insert(k.clone(), row.clone()).is_none() {
+            order.push(k);
+        }
+    }
+
+    let unique: Vec<T> = order.iter().filter_map(|k| latest.remove(k)).collect();
+    let duplicates = rows.len() - unique.len();
+    (unique, duplicates)
+}
+
+/// Audit entries are keyed by their contents. `package_name_id` is left out, as DynamoDB gives
+/// entries without one a random id when they're written, so the same entry read back from the
+/// table wouldn't match the snapshot it was imported from.
+fn audit_key(audit: &AuditEntry) -> String {
+    let content = AuditEntry {
+        package_name_id: None,
+        ..audit.clone()
+    };
+    serde_json::to_string(&content).unwrap_or_default()
+}
+
+/// Writes `snapshot` into `store`, skipping rows which are duplicated in the snapshot or already
+/// stored. With `dry_run` nothing is written, but the summary still reports what would have been.
+pub async fn import_snapshot(
+    store: &dyn Store,
+    snapshot: &Snapshot,
+    dry_run: bool,
+) -> Result<ImportSummary, Error> {
+    let mut summary = ImportSummary::default();
+
+    let (stats, duplicates) = dedupe(&snapshot.stats, |s| s.timestamp.clone());
+    summary.stats.read = snapshot.stats.len();
+    summary.stats.duplicates = duplicates;
+    let stored: HashMap<String, StatsEntry> = store
+        .stats()
+        .await?
+        .into_iter()
+        .map(|s| (s.timestamp.clone(), s))
+        .collect();
+    for entry in stats {
+        if stored.get(&entry.timestamp) == Some(&entry) {
+            summary.stats.existing += 1;
+            continue;
+        }
+        if !dry_run {
+            store.put_stats(&entry).await?;
+        }
+        summary.stats.written += 1;
+    }
+
+    let (packages, duplicates) = dedupe(&snapshot.packages, |p| p.name.clone());
+    summary.packages.read = snapshot.packages.len();
+    summary.packages.duplicates = duplicates;
+    let stored = store.packages().await?;
+    for pkg in packages {
+        if stored.get(&pkg.name) == Some(&pkg) {
+            summary.packages.existing += 1;
+            continue;
+        }
+        if !dry_run {
+            store.put_package(&pkg).await?;
+        }
+        summary.packages.written += 1;
+    }
+
+    let (audits, duplicates) = dedupe(&snapshot.audits, audit_key);
+    summary.audits.read = snapshot.audits.len();
+    summary.audits.duplicates = duplicates;
+    let stored: HashSet<String> = store.audits().await?.iter().map(audit_key).collect();
+    for audit in audits {
+        if stored.contains(&audit_key(&audit)) {
+            summary.audits.existing += 1;
+            continue;
+        }
+        if !dry_run {
+            store.put_audit(&audit).await?;
+        }
+        summary.audits.written += 1;
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedupe_keeps_the_last_row_in_first_seen_order() {
+        let rows = vec![("b", 1), ("a", 1), ("b", 2), ("c", 1), ("a", 2)];
+
+        let (unique, duplicates) = dedupe(&rows, |(k, _)| *k);
+
+        assert_eq!(unique, vec![("b", 2), ("a", 2), ("c", 1)]);
+        assert_eq!(duplicates, 2);
+    }
+
+    #[test]
+    fn test_audit_key_ignores_the_table_id() {
+        let audit = AuditEntry {
+            package_name: String::from("chalk"),
+            timestamp: String::from("2022-01-01"),
+            change: String::from("type_module"),
+            new_value: true,
+            ..AuditEntry::default()
+        };
+        let written = AuditEntry {
+            package_name_id: Some(audit.key()),
+            ..audit.clone()
+        };
+
+        assert_eq!(audit_key(&audit), audit_key(&written));
+        assert_ne!(
+            audit_key(&audit),
+            audit_key(&AuditEntry {
+                new_value: false,
+                ..audit.clone()
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_row() {
+        let row = |json: &str| match serde_json::from_str(json).unwrap() {
+            Value::Object(row) => row,
+            _ => unreachable!(),
+        };
+        let valid = r#"{"package_name": "a", "timestamp": "2022-01-01", "change": "type_module", "old_value": false, "new_value": true}"#;
+
+        assert!(check_row(&row(valid), AUDIT_COLUMNS).is_ok());
+        assert_eq!(
+            check_row(&row(r#"{"package_name": "a"}"#), AUDIT_COLUMNS).unwrap_err(),
+            "missing column `timestamp`"
+        );
+        assert_eq!(
+            check_row(&row(&valid.replace("false", "\"no\"")), AUDIT_COLUMNS).unwrap_err(),
+            "expected column `old_value` to be Boolean, found \"no\""
+        );
+    }
+}
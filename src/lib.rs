@@ -1,170 +1,239 @@
-use futures::{stream::FuturesUnordered, StreamExt};
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, fs, ops::Sub};
-
-#[derive(Debug)]
-pub struct StatsEntry {
-    pub type_module: isize,
-    pub exports_require: isize,
-    pub exports_no_require: isize,
-    pub timestamp: String,
+use std::{collections::HashMap, fmt};
+
+mod analysis;
+mod audit;
+mod badge;
+mod chart;
+mod check;
+mod checkpoint;
+mod error;
+mod export;
+mod follow;
+mod forecast;
+mod html;
+mod import;
+pub mod notify;
+mod regressions;
+mod report;
+mod scan;
+mod server;
+mod stats;
+pub mod store;
+mod template;
+mod trends;
+mod watchlist;
+
+pub use analysis::{analyze_packages, Analysis, Subpath, Target};
+pub use audit::{
+    add_publish_time, deactivate_package, diff_packages, AuditEntry, AuditQuery, FieldChange,
+    Transition, AUDITED_FIELDS, LIFECYCLE_CHANGES,
+};
+pub use badge::Badge;
+pub use chart::{Chart, ChartKind, ChartSeries};
+pub use check::{
+    generate_packages, read_package_list, stream_packages, Cache, CheckOptions, PackageReport,
+    PackageSpec, Source, NPM_REGISTRY_URL, NPM_REPLICATE_URL, UNPKG_URL,
+};
+pub use error::Error;
+pub use export::{
+    Column, ColumnType, Format, Snapshot, AUDIT_COLUMNS, PACKAGE_COLUMNS, STATS_COLUMNS,
+};
+pub use follow::{follow_changes, Change, Feed, FollowCheckpoint, FollowOptions, FollowSummary};
+pub use forecast::{forecast_adoption, AdoptionForecast, Fit, Milestone, Model, FIRST_STATS_DATE};
+pub use html::HtmlReport;
+pub use import::{import_snapshot, ImportSummary, TableSummary};
+pub use regressions::{find_regressions, post_regressions, Regression};
+pub use report::{notable_changes, NotableChange, Period, StatsReport};
+pub use scan::{
+    read_registry_list, scan_registry, ListFingerprint, ScanCheckpoint, ScanOptions, ScanStats,
+};
+pub use server::Server;
+pub use stats::{StatsDelta, StatsEntry};
+pub use template::{
+    ChannelTemplates, MetricContext, NotableContext, ReportContext, ReportTemplate,
+};
+pub use trends::{stats_series, GapPolicy, Interval, Metric, SeriesPoint, StatsSeries};
+pub use watchlist::{send_watch_alerts, watchlist_notifiers, WatchAlert, Watchlist};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub exports_require: bool,
+    pub exports_no_require: bool,
+    pub type_module: bool,
+    /// The version which was classified. Packages stored before versions were recorded don't
+    /// have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// When `version` was published, from the `time` map of the registry document. Known when
+    /// the full document was fetched, such as from the replicate or a mirror, and looked up by
+    /// [`add_publish_time`] for packages checked from a single manifest once they change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    /// The manifest fields the classification was made from.
+    #[serde(default)]
+    pub manifest: ManifestFields,
+    /// The npm deprecation message of `version`, if its author deprecated it. npm only records it
+    /// in the registry's copy of the manifest, so it's never set when checking from unpkg.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    /// Why the package is no longer checked. Rows are marked rather than deleted so the package
+    /// keeps its history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inactive: Option<Inactive>,
 }
 
-impl From<HashMap<String, aws_sdk_dynamodb::model::AttributeValue>> for StatsEntry {
-    fn from(map: HashMap<String, aws_sdk_dynamodb::model::AttributeValue>) -> Self {
-        StatsEntry {
-            type_module: map["type_module"].as_n().unwrap().parse::<isize>().unwrap(),
-            exports_require: map["exports_require"]
-                .as_n()
-                .unwrap()
-                .parse::<isize>()
-                .unwrap(),
-            exports_no_require: map["exports_no_require"]
-                .as_n()
-                .unwrap()
-                .parse::<isize>()
-                .unwrap(),
-            timestamp: map["timestamp"].as_s().unwrap().to_string(),
-        }
-    }
+/// The fields of a `package.json` which decide how it can be imported.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFields {
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub package_type: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exports: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<Value>,
 }
 
-impl Sub for StatsEntry {
-    type Output = Self;
+impl ManifestFields {
+    pub fn from_manifest(package_json: &Value) -> Self {
+        let field = |name: &str| package_json.get(name).cloned();
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Self {
-            type_module: self.type_module - rhs.type_module,
-            exports_require: self.exports_require - rhs.exports_require,
-            exports_no_require: self.exports_no_require - rhs.exports_no_require,
-            timestamp: self.timestamp,
+        ManifestFields {
+            package_type: field("type"),
+            exports: field("exports"),
+            main: field("main"),
+            module: field("module"),
         }
     }
-}
 
-#[derive(Debug, Default, Clone)]
-pub struct Package {
-    pub name: String,
-    pub exports_require: bool,
-    pub exports_no_require: bool,
-    pub type_module: bool,
+    /// Every field which differs between `self` and `other`, by its `package.json` name.
+    pub fn diff(
+        &self,
+        other: &ManifestFields,
+    ) -> Vec<(&'static str, Option<Value>, Option<Value>)> {
+        [
+            ("type", &self.package_type, &other.package_type),
+            ("exports", &self.exports, &other.exports),
+            ("main", &self.main, &other.main),
+            ("module", &self.module, &other.module),
+        ]
+        .into_iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(field, old, new)| (field, old.clone(), new.clone()))
+        .collect()
+    }
 }
 
 impl Package {
     pub fn has_values(&self) -> bool {
         self.exports_no_require || self.exports_require || self.type_module
     }
-}
 
-impl From<HashMap<String, aws_sdk_dynamodb::model::AttributeValue>> for Package {
-    fn from(hash: HashMap<String, aws_sdk_dynamodb::model::AttributeValue>) -> Self {
-        Package {
-            name: hash["package_name"].as_s().unwrap().to_owned(),
-            exports_require: hash["exports_require"].as_bool().unwrap().to_owned(),
-            exports_no_require: hash["exports_no_require"].as_bool().unwrap().to_owned(),
-            type_module: hash["type_module"].as_bool().unwrap().to_owned(),
+    pub fn status(&self) -> PackageStatus {
+        if self.exports_require {
+            PackageStatus::Dual
+        } else if self.exports_no_require || self.type_module {
+            PackageStatus::EsmOnly
+        } else {
+            PackageStatus::Cjs
         }
     }
 }
 
-pub struct AuditEntry {
-    pub package_name: String,
-    pub timestamp: String,
-    pub change: String,
-    pub old_value: bool,
-    pub new_value: bool,
+/// How a package can be consumed, summarizing its classification fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackageStatus {
+    /// No ESM entry points, so only `require` works.
+    Cjs,
+    /// Has an `exports.require` condition alongside its ESM entry points.
+    Dual,
+    /// Ships ESM without a `require` condition, either through `exports` or `type: module`.
+    EsmOnly,
 }
 
-pub async fn generate_packages(short: bool) -> Result<Vec<Package>, Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
-        .user_agent("esm-checker/0.3.0 (+https://github.com/lannonbr/esm-checker)")
-        .build()
-        .unwrap();
-
-    let mut initial_package_list: Vec<String> = fs::read_to_string("packages.txt")
-        .unwrap()
-        .split_whitespace()
-        .into_iter()
-        .map(|c| c.to_owned())
-        .collect();
-
-    if short {
-        initial_package_list.truncate(100);
+impl fmt::Display for PackageStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageStatus::Cjs => write!(f, "cjs"),
+            PackageStatus::Dual => write!(f, "dual"),
+            PackageStatus::EsmOnly => write!(f, "esm-only"),
+        }
     }
+}
 
-    let mut requests = FuturesUnordered::new();
-
-    let mut pkgs: Vec<Package> = vec![];
-
-    for package in initial_package_list {
-        let client = client.clone();
-        requests.push(tokio::spawn(async move {
-            let url = format!("https://unpkg.com/{}@latest/package.json", package);
-            client.get(url).send().await.unwrap().text().await.unwrap()
-        }));
-
-        if requests.len() > 30 {
-            let package_json_str = requests.next().await.unwrap().unwrap();
-
-            let package = generate_pkg(package_json_str);
-
-            if package.is_some() {
-                pkgs.push(package.unwrap());
-            }
+impl std::str::FromStr for PackageStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cjs" => Ok(PackageStatus::Cjs),
+            "dual" => Ok(PackageStatus::Dual),
+            "esm-only" => Ok(PackageStatus::EsmOnly),
+            _ => Err(Error::Config(format!(
+                "unknown status `{}`, expected cjs, dual or esm-only",
+                s
+            ))),
         }
     }
+}
 
-    while let Some(unpkg_resp) = requests.next().await {
-        let package_json_str = unpkg_resp.unwrap();
-
-        let package = generate_pkg(package_json_str);
+/// Why a stored package stopped being checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Inactive {
+    /// It was taken out of the package list.
+    Removed,
+    /// The registry no longer has it.
+    Unpublished,
+}
 
-        if package.is_some() {
-            pkgs.push(package.unwrap());
+impl fmt::Display for Inactive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inactive::Removed => write!(f, "removed"),
+            Inactive::Unpublished => write!(f, "unpublished"),
         }
     }
-
-    Ok(pkgs)
 }
 
-fn generate_pkg(json_str: String) -> Option<Package> {
-    let package_json: Value = match serde_json::from_str(&json_str) {
-        Ok(e) => e,
-        Err(e) => {
-            eprintln!("{}", e);
-            println!("Str: {}", json_str);
-            return None;
+impl std::str::FromStr for Inactive {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "removed" => Ok(Inactive::Removed),
+            "unpublished" => Ok(Inactive::Unpublished),
+            _ => Err(Error::Config(format!(
+                "unknown inactive reason `{}`, expected removed or unpublished",
+                s
+            ))),
         }
-    };
-    let name_opt = package_json.get("name");
-
-    let mut new_package = Package::default();
+    }
+}
 
-    let name = name_opt.unwrap().as_str().unwrap();
-    new_package.name = name.to_string();
+impl From<HashMap<String, AttributeValue>> for Package {
+    fn from(hash: HashMap<String, AttributeValue>) -> Self {
+        let string = |key: &str| hash.get(key).and_then(|v| v.as_s().ok()).cloned();
 
-    if let Some(package_type) = package_json.get("type") {
-        if package_type.as_str().unwrap() == "module" {
-            new_package.type_module = true;
-        } else {
-            new_package.type_module = false;
-        }
-    } else {
-        new_package.type_module = false;
-    }
-    if let Some(exports) = package_json.get("exports") {
-        let exports_str = exports.to_string();
-        if exports_str.contains("require") {
-            new_package.exports_require = true;
-            new_package.exports_no_require = false;
-        } else {
-            new_package.exports_no_require = true;
-            new_package.exports_require = false;
+        Package {
+            name: hash["package_name"].as_s().unwrap().to_owned(),
+            exports_require: hash["exports_require"].as_bool().unwrap().to_owned(),
+            exports_no_require: hash["exports_no_require"].as_bool().unwrap().to_owned(),
+            type_module: hash["type_module"].as_bool().unwrap().to_owned(),
+            version: string("version"),
+            published: string("published"),
+            // Stored as a JSON string, since DynamoDB maps can't hold arbitrary JSON
+            manifest: string("manifest")
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            deprecated: string("deprecated"),
+            inactive: string("inactive").and_then(|s| s.parse().ok()),
         }
-    } else {
-        new_package.exports_no_require = false;
-        new_package.exports_require = false;
     }
-
-    Some(new_package)
 }
@@ -0,0 +1,118 @@
+use super::{post_json, truncate_lines, Field, Message, Notifier, Trend};
+use crate::Error;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// Discord only allows this many characters in an embed description.
+const DESCRIPTION_LIMIT: usize = 4096;
+/// Discord only allows this many characters in the `content` of a plain message.
+const CONTENT_LIMIT: usize = 2000;
+
+/// A Discord channel webhook.
+#[derive(Debug, Clone)]
+pub struct Discord {
+    url: String,
+}
+
+impl Discord {
+    pub fn new(url: impl Into<String>) -> Self {
+        Discord { url: url.into() }
+    }
+
+    /// Plain messages are sent as text. Messages with fields or events are sent as embeds: one for
+    /// the fields, colored by the trend of the message, and one listing the events.
+    pub fn payload(message: &Message) -> Value {
+        if !message.is_structured() {
+            let lines: Vec<String> = std::iter::once(format!("[{}]", message.title))
+                .chain(message.text.lines().map(str::to_owned))
+                .collect();
+            return json!({ "content": truncate_lines(&lines, CONTENT_LIMIT) });
+        }
+
+        let mut embeds = vec![];
+
+        let mut stats = json!({
+            "title": message.title,
+            "color": color(message.trend),
+            "fields": message.fields.iter().map(embed_field).collect::<Vec<_>>(),
+        });
+        if let Some(summary) = &message.summary {
+            stats["description"] = json!(summary);
+        }
+        embeds.push(stats);
+
+        if !message.events.is_empty() {
+            embeds.push(json!({
+                "title": "Notable changes",
+                "color": color(message.trend),
+                "description": truncate_lines(&message.events, DESCRIPTION_LIMIT),
+            }));
+        }
+
+        json!({ "embeds": embeds })
+    }
+}
+
+fn color(trend: Option<Trend>) -> u32 {
+    match trend {
+        Some(Trend::Up) => 0x2ecc71,
+        Some(Trend::Down) => 0xe74c3c,
+        Some(Trend::Flat) | None => 0x95a5a6,
+    }
+}
+
+/// Changes are shown in a `diff` code block, which Discord colors green for lines starting with
+/// `+` and red for lines starting with `-`.
+fn embed_field(field: &Field) -> Value {
+    let value = match (&field.change, field.trend) {
+        (Some(change), Some(trend)) => {
+            let marker = match trend {
+                Trend::Up => '+',
+                Trend::Down => '-',
+                Trend::Flat => ' ',
+            };
+            format!(
+                "{}\n```diff\n{} {} {}\n```",
+                field.value,
+                marker,
+                trend.arrow(),
+                change
+            )
+        }
+        (Some(change), None) => format!("{}\n{}", field.value, change),
+        (None, _) => field.value.clone(),
+    };
+
+    json!({
+        "name": field.name,
+        "value": value,
+        "inline": field.inline,
+    })
+}
+
+#[async_trait]
+impl Notifier for Discord {
+    fn kind(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        post_json(&self.url, &Discord::payload(message)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_content_is_truncated() {
+        let text: Vec<String> = (0..100).map(|i| format!("{:040}", i)).collect();
+        let payload = Discord::payload(&Message::new("Title", text.join("\n")));
+
+        let content = payload["content"].as_str().unwrap();
+        assert!(content.chars().count() <= CONTENT_LIMIT);
+        assert!(content.starts_with("[Title]\n0000"));
+        assert!(content.ends_with(" more"));
+    }
+}
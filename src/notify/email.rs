@@ -0,0 +1,244 @@
+use super::{escape_html, Message, Notifier, Trend};
+use crate::Error;
+use async_trait::async_trait;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+    },
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use std::str::FromStr;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with `STARTTLS`, refusing to send without it. Usually port 587.
+    #[default]
+    Starttls,
+    /// Connect over TLS from the start. Usually port 465.
+    Tls,
+    /// No encryption at all, only for local relays and test sinks such as MailHog.
+    None,
+}
+
+impl SmtpTls {
+    fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::Starttls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starttls" => Ok(SmtpTls::Starttls),
+            "tls" => Ok(SmtpTls::Tls),
+            "none" => Ok(SmtpTls::None),
+            _ => Err(Error::Config(format!(
+                "unknown SMTP_TLS `{}`, expected starttls, tls or none",
+                s
+            ))),
+        }
+    }
+}
+
+/// The SMTP server emails are sent through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpConfig {
+    pub host: String,
+    /// Defaults to the usual port for `tls`.
+    pub port: Option<u16>,
+    pub tls: SmtpTls,
+    /// The username and password to log in with, if the server needs them.
+    pub credentials: Option<(String, String)>,
+    /// The sender, such as `ESM Checker <esm-checker@example.com>`.
+    pub from: String,
+}
+
+impl SmtpConfig {
+    /// Reads `SMTP_HOST`, `SMTP_PORT`, `SMTP_TLS`, `SMTP_USERNAME`, `SMTP_PASSWORD` and
+    /// `SMTP_FROM`.
+    pub fn from_env() -> Result<Self, Error> {
+        let var = |name: &str| std::env::var(name).ok();
+        let required = |name: &str| {
+            var(name).ok_or_else(|| {
+                Error::Config(format!("{} must be set to send email notifications", name))
+            })
+        };
+
+        let port = match var("SMTP_PORT") {
+            Some(port) => Some(
+                port.parse()
+                    .map_err(|_| Error::Config(format!("invalid SMTP_PORT `{}`", port)))?,
+            ),
+            None => None,
+        };
+
+        Ok(SmtpConfig {
+            host: required("SMTP_HOST")?,
+            port,
+            tls: var("SMTP_TLS").as_deref().unwrap_or("starttls").parse()?,
+            credentials: var("SMTP_USERNAME").zip(var("SMTP_PASSWORD")),
+            from: required("SMTP_FROM")?,
+        })
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, Error> {
+    address
+        .trim()
+        .parse()
+        .map_err(|e| Error::Config(format!("invalid email address `{}`: {}", address, e)))
+}
+
+/// Emails sent over SMTP, with a plain text and an HTML version of the message.
+#[derive(Debug, Clone)]
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    pub fn new(config: &SmtpConfig, recipients: &[&str]) -> Result<Self, Error> {
+        if recipients.is_empty() {
+            return Err(Error::Config(String::from(
+                "an email channel needs at least one recipient",
+            )));
+        }
+
+        let tls = match config.tls {
+            SmtpTls::Starttls => Tls::Required(tls_parameters(&config.host)?),
+            SmtpTls::Tls => Tls::Wrapper(tls_parameters(&config.host)?),
+            SmtpTls::None => Tls::None,
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            .port(config.port.unwrap_or_else(|| config.tls.default_port()))
+            .tls(tls);
+        if let Some((username, password)) = &config.credentials {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Email {
+            transport: builder.build(),
+            from: mailbox(&config.from)?,
+            to: recipients
+                .iter()
+                .map(|r| mailbox(r))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Parses the comma separated recipients of an `email:` channel, sending through the server
+    /// configured by [`SmtpConfig::from_env`].
+    pub fn from_target(target: &str) -> Result<Self, Error> {
+        let recipients: Vec<&str> = target.split(',').filter(|r| !r.is_empty()).collect();
+        Email::new(&SmtpConfig::from_env()?, &recipients)
+    }
+
+    pub fn subject(message: &Message) -> String {
+        match &message.summary {
+            Some(summary) => format!("{}: {}", message.title, summary),
+            None => message.title.clone(),
+        }
+    }
+
+    pub fn plain_body(message: &Message) -> String {
+        format!("{}\n\n{}\n", message.title, message.text)
+    }
+
+    /// The fields as a table and the events as a list when there are any, otherwise the text.
+    pub fn html_body(message: &Message) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<body style=\"font-family: sans-serif\">\n<h1>{}</h1>\n",
+            escape_html(&message.title)
+        );
+
+        if !message.is_structured() {
+            html.push_str(&format!("<pre>{}</pre>\n", escape_html(&message.text)));
+            html.push_str("</body>\n</html>\n");
+            return html;
+        }
+
+        if let Some(summary) = &message.summary {
+            html.push_str(&format!("<p>{}</p>\n", escape_html(summary)));
+        }
+        if !message.fields.is_empty() {
+            html.push_str("<table cellpadding=\"6\">\n");
+            for field in &message.fields {
+                let change = match (&field.change, field.trend) {
+                    (Some(change), trend) => {
+                        let color = match trend {
+                            Some(Trend::Up) => "#1e8449",
+                            Some(Trend::Down) => "#c0392b",
+                            _ => "#7f8c8d",
+                        };
+                        let arrow = trend.map(Trend::arrow).unwrap_or_default();
+                        format!(
+                            "<span style=\"color: {}\">{} {}</span>",
+                            color,
+                            arrow,
+                            escape_html(change)
+                        )
+                    }
+                    (None, _) => String::new(),
+                };
+                html.push_str(&format!(
+                    "<tr><th align=\"left\">{}</th><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(&field.name),
+                    escape_html(&field.value).replace('\n', "<br>"),
+                    change
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+        if !message.events.is_empty() {
+            html.push_str("<h2>Notable changes</h2>\n<ul>\n");
+            for event in &message.events {
+                html.push_str(&format!("<li>{}</li>\n", escape_html(event)));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn tls_parameters(host: &str) -> Result<TlsParameters, Error> {
+    TlsParameters::new(host.to_owned())
+        .map_err(|e| Error::Config(format!("can't set up TLS for {}: {}", host, e)))
+}
+
+#[async_trait]
+impl Notifier for Email {
+    fn kind(&self) -> &'static str {
+        "email"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        let mut builder = lettre::Message::builder()
+            .from(self.from.clone())
+            .subject(Email::subject(message));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+
+        let email = builder
+            .multipart(MultiPart::alternative_plain_html(
+                Email::plain_body(message),
+                Email::html_body(message),
+            ))
+            .map_err(|e| Error::Config(format!("can't build the email: {}", e)))?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use super::{escape_html, send_json, Message, Notifier};
+use crate::Error;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// A Matrix room, posted to through the client-server API with a user's access token.
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    homeserver: String,
+    room_id: String,
+    access_token: String,
+}
+
+impl Matrix {
+    pub fn new(
+        homeserver: impl Into<String>,
+        room_id: impl Into<String>,
+        access_token: impl Into<String>,
+    ) -> Self {
+        Matrix {
+            homeserver: homeserver.into(),
+            room_id: room_id.into(),
+            access_token: access_token.into(),
+        }
+    }
+
+    /// Parses a `<homeserver url>/<room id>` target, such as
+    /// `https://matrix.org/!abcdef:matrix.org`.
+    pub fn from_target(target: &str, access_token: impl Into<String>) -> Result<Self, Error> {
+        match target.rsplit_once('/') {
+            Some((homeserver, room_id)) if room_id.starts_with('!') => {
+                Ok(Matrix::new(homeserver, room_id, access_token))
+            }
+            _ => Err(Error::Config(format!(
+                "expected a matrix channel as <homeserver url>/<room id>, found `{}`",
+                target
+            ))),
+        }
+    }
+
+    /// An `m.text` event, with the title in bold in the HTML version.
+    pub fn payload(message: &Message) -> Value {
+        json!({
+            "msgtype": "m.text",
+            "body": format!("{}\n{}", message.title, message.text),
+            "format": "org.matrix.custom.html",
+            "formatted_body": format!(
+                "<strong>{}</strong><br>{}",
+                escape_html(&message.title),
+                escape_html(&message.text).replace('\n', "<br>")
+            ),
+        })
+    }
+
+    fn send_url(&self) -> String {
+        // Every event needs a transaction id, which the homeserver uses to drop retried sends
+        format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.homeserver.trim_end_matches('/'),
+            encode_path_segment(&self.room_id),
+            uuid::Uuid::new_v4().to_simple()
+        )
+    }
+}
+
+#[async_trait]
+impl Notifier for Matrix {
+    fn kind(&self) -> &'static str {
+        "matrix"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        send_json(
+            reqwest::Method::PUT,
+            &self.send_url(),
+            Some(&self.access_token),
+            &Matrix::payload(message),
+        )
+        .await
+    }
+}
+
+/// Room ids contain `!` and `:`, which have to be percent encoded in a path.
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_target() {
+        let matrix = Matrix::from_target("https://matrix.org/!abc:matrix.org", "token").unwrap();
+
+        assert_eq!(matrix.homeserver, "https://matrix.org");
+        assert_eq!(matrix.room_id, "!abc:matrix.org");
+        assert!(matrix.send_url().starts_with(
+            "https://matrix.org/_matrix/client/v3/rooms/%21abc%3Amatrix.org/send/m.room.message/"
+        ));
+        assert!(Matrix::from_target("https://matrix.org/#esm:matrix.org", "token").is_err());
+    }
+}
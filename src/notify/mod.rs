@@ -0,0 +1,346 @@
+//! Where reports are sent.
+//!
+//! Channels are listed in the `ESM_CHECKER_NOTIFY` environment variable, separated by whitespace,
+//! each as `<kind>:<target>`:
+//!
+//! - `discord:<webhook url>`
+//! - `slack:<incoming webhook url>`
+//! - `teams:<incoming webhook url>`
+//! - `matrix:<homeserver url>/<room id>`, authenticated with `MATRIX_ACCESS_TOKEN`
+//! - `webhook:<url>`: any endpoint accepting `{"title": ..., "text": ...}` as JSON
+//! - `email:<address>[,<address>]...`: sent through the SMTP server configured by the `SMTP_*`
+//!   variables, see [`SmtpConfig::from_env`]
+//!
+//! When it isn't set, `DISCORD_WEBHOOK` is used as a single Discord channel.
+
+use crate::{check::http_client, Error};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+
+mod discord;
+mod email;
+mod matrix;
+mod slack;
+mod teams;
+mod webhook;
+
+pub use discord::Discord;
+pub use email::{Email, SmtpConfig, SmtpTls};
+pub use matrix::Matrix;
+pub use slack::Slack;
+pub use teams::Teams;
+pub use webhook::Webhook;
+
+/// A report to send.
+///
+/// `text` is the whole report as plain text, with one item per line. Channels with richer layouts
+/// can show the `summary`, `fields` and `events` instead, which repeat parts of `text`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Message {
+    pub title: String,
+    pub text: String,
+    /// A line introducing the fields.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// The figures of the report, such as one per metric.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<Field>,
+    /// Notable things which happened, such as packages going ESM only.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// The overall direction of the report, for channels which color messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trend: Option<Trend>,
+}
+
+impl Message {
+    pub fn new(title: impl Into<String>, text: impl Into<String>) -> Self {
+        Message {
+            title: title.into(),
+            text: text.into(),
+            summary: None,
+            fields: vec![],
+            events: vec![],
+            trend: None,
+        }
+    }
+
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    pub fn field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn events(mut self, events: Vec<String>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn trend(mut self, trend: Trend) -> Self {
+        self.trend = Some(trend);
+        self
+    }
+
+    /// Whether there is more to show than the plain text.
+    pub fn is_structured(&self) -> bool {
+        !self.fields.is_empty() || !self.events.is_empty()
+    }
+}
+
+/// A single figure of a report, such as the count of one metric and how it moved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+    /// How the value changed, such as `+3 (+0.15pp)`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trend: Option<Trend>,
+    /// Whether the field can sit next to others, rather than on its own line.
+    pub inline: bool,
+}
+
+impl Field {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Field {
+            name: name.into(),
+            value: value.into(),
+            change: None,
+            trend: None,
+            inline: true,
+        }
+    }
+
+    pub fn change(mut self, change: impl Into<String>, trend: Trend) -> Self {
+        self.change = Some(change.into());
+        self.trend = Some(trend);
+        self
+    }
+
+    pub fn block(mut self) -> Self {
+        self.inline = false;
+        self
+    }
+}
+
+/// Which way a figure moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trend {
+    Up,
+    Down,
+    Flat,
+}
+
+impl Trend {
+    pub fn of(change: f64) -> Self {
+        if change > 0.0 {
+            Trend::Up
+        } else if change < 0.0 {
+            Trend::Down
+        } else {
+            Trend::Flat
+        }
+    }
+
+    pub fn arrow(self) -> &'static str {
+        match self {
+            Trend::Up => "▲",
+            Trend::Down => "▼",
+            Trend::Flat => "▬",
+        }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// The kind of channel, as written in `ESM_CHECKER_NOTIFY`.
+    fn kind(&self) -> &'static str;
+
+    async fn notify(&self, message: &Message) -> Result<(), Error>;
+}
+
+/// Every kind of channel, as written in `ESM_CHECKER_NOTIFY`.
+pub const KINDS: [&str; 6] = ["discord", "slack", "teams", "matrix", "webhook", "email"];
+
+/// Parses a single `<kind>:<target>` channel.
+pub fn from_spec(spec: &str) -> Result<Box<dyn Notifier>, Error> {
+    let invalid = || {
+        Error::Config(format!(
+            "unknown notification channel `{}`, expected one of discord:, slack:, teams:, matrix:, webhook: or email: followed by its target",
+            spec
+        ))
+    };
+
+    let (kind, target) = spec.split_once(':').ok_or_else(invalid)?;
+    match kind {
+        "discord" => Ok(Box::new(Discord::new(target))),
+        "slack" => Ok(Box::new(Slack::new(target))),
+        "teams" => Ok(Box::new(Teams::new(target))),
+        "matrix" => {
+            let token = std::env::var("MATRIX_ACCESS_TOKEN").map_err(|_| {
+                Error::Config(String::from(
+                    "MATRIX_ACCESS_TOKEN must be set to notify a matrix channel",
+                ))
+            })?;
+            Ok(Box::new(Matrix::from_target(target, token)?))
+        }
+        "webhook" => Ok(Box::new(Webhook::new(target))),
+        "email" => Ok(Box::new(Email::from_target(target)?)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses whitespace separated `<kind>:<target>` channels.
+pub fn from_channels(channels: &str) -> Result<Vec<Box<dyn Notifier>>, Error> {
+    channels.split_whitespace().map(from_spec).collect()
+}
+
+/// Every channel configured by `ESM_CHECKER_NOTIFY`, falling back to `DISCORD_WEBHOOK`.
+pub fn from_env() -> Result<Vec<Box<dyn Notifier>>, Error> {
+    let notifiers: Vec<Box<dyn Notifier>> = match std::env::var("ESM_CHECKER_NOTIFY") {
+        Ok(channels) => from_channels(&channels)?,
+        Err(_) => match std::env::var("DISCORD_WEBHOOK") {
+            Ok(url) => vec![Box::new(Discord::new(url))],
+            Err(_) => vec![],
+        },
+    };
+
+    if notifiers.is_empty() {
+        return Err(Error::Config(String::from(
+            "set ESM_CHECKER_NOTIFY (or DISCORD_WEBHOOK) to send notifications",
+        )));
+    }
+    Ok(notifiers)
+}
+
+/// Sends `message` to every channel, carrying on past failures so one broken channel doesn't
+/// keep the report from the others. Returns the failures along with the kind of channel.
+pub async fn notify_all(
+    notifiers: &[Box<dyn Notifier>],
+    message: &Message,
+) -> Vec<(&'static str, Error)> {
+    notify_each(notifiers, &vec![message.clone(); notifiers.len()]).await
+}
+
+/// Like [`notify_all`], but with a message per channel, such as when some channels have their own
+/// template.
+pub async fn notify_each(
+    notifiers: &[Box<dyn Notifier>],
+    messages: &[Message],
+) -> Vec<(&'static str, Error)> {
+    let mut failures = vec![];
+    for (notifier, message) in notifiers.iter().zip(messages) {
+        if let Err(e) = notifier.notify(message).await {
+            failures.push((notifier.kind(), e));
+        }
+    }
+    failures
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Joins `lines` into at most `limit` characters, replacing the lines which don't fit with a count.
+pub(crate) fn truncate_lines(lines: &[String], limit: usize) -> String {
+    let mut text = String::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let more = format!("…and {} more", lines.len() - i);
+        // Leave room for the count in case a later line doesn't fit
+        let reserve = if i + 1 < lines.len() {
+            more.len() + 1
+        } else {
+            0
+        };
+        if text.chars().count() + line.chars().count() + 1 + reserve > limit {
+            text.push_str(&more);
+            return text;
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    text.trim_end().to_owned()
+}
+
+/// Sends `body` to `url` as JSON with `method`, failing on any non-success status.
+pub(crate) async fn send_json(
+    method: reqwest::Method,
+    url: &str,
+    token: Option<&str>,
+    body: &Value,
+) -> Result<(), Error> {
+    let http_err = |source| Error::Http {
+        url: url.to_owned(),
+        source,
+    };
+
+    let mut request = http_client()
+        .request(method, url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let resp = request.send().await.map_err(http_err)?;
+
+    if !resp.status().is_success() {
+        return Err(Error::Status {
+            url: url.to_owned(),
+            status: resp.status().as_u16(),
+        });
+    }
+    Ok(())
+}
+
+/// Posts `body` to `url` as JSON.
+pub(crate) async fn post_json(url: &str, body: &Value) -> Result<(), Error> {
+    send_json(reqwest::Method::POST, url, None, body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_lines() {
+        let lines: Vec<String> = (1..=5).map(|i| format!("line {}", i)).collect();
+
+        assert_eq!(
+            truncate_lines(&lines, 100),
+            "line 1\nline 2\nline 3\nline 4\nline 5"
+        );
+        assert_eq!(truncate_lines(&lines, 30), "line 1\nline 2\n…and 3 more");
+    }
+
+    #[test]
+    fn test_from_spec() {
+        assert_eq!(
+            from_spec("slack:https://hooks.slack.com/services/T0/B0/x")
+                .unwrap()
+                .kind(),
+            "slack"
+        );
+        assert_eq!(
+            from_spec("webhook:http://localhost:8080/esm")
+                .unwrap()
+                .kind(),
+            "webhook"
+        );
+        assert!(matches!(
+            from_spec("irc:#esm"),
+            Err(Error::Config(message)) if message.contains("irc:#esm")
+        ));
+        assert!(from_spec("https://discord.com/api/webhooks/1/x").is_err());
+    }
+}
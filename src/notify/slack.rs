@@ -0,0 +1,62 @@
+use super::{escape_html, post_json, truncate_lines, Message, Notifier};
+use crate::Error;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// Slack rejects header blocks with more than this many characters of text.
+const HEADER_LIMIT: usize = 150;
+/// Slack rejects section blocks with more than this many characters of text.
+const SECTION_LIMIT: usize = 3000;
+
+/// A Slack incoming webhook.
+#[derive(Debug, Clone)]
+pub struct Slack {
+    url: String,
+}
+
+impl Slack {
+    pub fn new(url: impl Into<String>) -> Self {
+        Slack { url: url.into() }
+    }
+
+    /// A header block for the title and a section for the text, with the whole message as the
+    /// `text` fallback used by notifications. Slack reads `&`, `<` and `>` in mrkdwn as the start
+    /// of entities and links, so they're escaped in the text. Blocks over Slack's limits are cut
+    /// short, the section a line at a time.
+    pub fn payload(message: &Message) -> Value {
+        let text = escape_html(&message.text);
+        let lines: Vec<String> = text.lines().map(str::to_owned).collect();
+        let header = match message.title.chars().count() > HEADER_LIMIT {
+            true => {
+                let title: String = message.title.chars().take(HEADER_LIMIT - 1).collect();
+                format!("{}…", title)
+            }
+            false => message.title.clone(),
+        };
+
+        json!({
+            "text": format!("{}\n{}", escape_html(&message.title), text),
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": header },
+                },
+                {
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": truncate_lines(&lines, SECTION_LIMIT) },
+                },
+            ],
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    fn kind(&self) -> &'static str {
+        "slack"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        post_json(&self.url, &Slack::payload(message)).await
+    }
+}
@@ -0,0 +1,39 @@
+use super::{post_json, Message, Notifier};
+use crate::Error;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// A Microsoft Teams incoming webhook.
+#[derive(Debug, Clone)]
+pub struct Teams {
+    url: String,
+}
+
+impl Teams {
+    pub fn new(url: impl Into<String>) -> Self {
+        Teams { url: url.into() }
+    }
+
+    /// A legacy `MessageCard`, which every Teams incoming webhook accepts.
+    pub fn payload(message: &Message) -> Value {
+        json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": message.title,
+            "title": message.title,
+            // Card text is markdown, where a single newline doesn't break the line
+            "text": message.text.replace('\n', "\n\n"),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Teams {
+    fn kind(&self) -> &'static str {
+        "teams"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        post_json(&self.url, &Teams::payload(message)).await
+    }
+}
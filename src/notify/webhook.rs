@@ -0,0 +1,31 @@
+use super::{post_json, Message, Notifier};
+use crate::Error;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// Any endpoint which accepts the message as `{"title": ..., "text": ...}`.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Webhook { url: url.into() }
+    }
+
+    pub fn payload(message: &Message) -> Value {
+        json!(message)
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    fn kind(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, message: &Message) -> Result<(), Error> {
+        post_json(&self.url, &Webhook::payload(message)).await
+    }
+}
@@ -0,0 +1,169 @@
+//! Reporting packages which stopped shipping ESM or dropped their `require` condition, the changes
+//! most likely to break someone.
+
+use crate::{notify::post_json, AuditEntry, Error, PackageStatus, Transition};
+use serde::Serialize;
+use std::fmt;
+
+/// A package whose status regressed, gathered from the audit entries of a single check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Regression {
+    pub package_name: String,
+    pub timestamp: String,
+    pub old_status: PackageStatus,
+    pub new_status: PackageStatus,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub published: Option<String>,
+    /// The classification fields which changed.
+    pub changes: Vec<String>,
+}
+
+impl Regression {
+    /// Whether CommonJS consumers can no longer `require` the package at all.
+    pub fn breaks_require(&self) -> bool {
+        self.old_status == PackageStatus::Dual && self.new_status == PackageStatus::EsmOnly
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("?"));
+
+        write!(
+            f,
+            "{} {} -> {} ({}): {} -> {}, {}",
+            self.package_name,
+            version(&self.old_version),
+            version(&self.new_version),
+            self.timestamp,
+            self.old_status,
+            self.new_status,
+            if self.breaks_require() {
+                "dropped the require condition"
+            } else {
+                "stopped shipping ESM"
+            }
+        )
+    }
+}
+
+/// Groups the audit entries classified as [`Transition::Regression`] into one regression per
+/// package per check, oldest first.
+pub fn find_regressions(audits: &[AuditEntry]) -> Vec<Regression> {
+    let mut regressions: Vec<Regression> = vec![];
+
+    for audit in audits {
+        if audit.transition() != Some(Transition::Regression) {
+            continue;
+        }
+
+        // The entries of one check share the timestamp and versions
+        let existing = regressions.iter_mut().find(|r| {
+            r.package_name == audit.package_name
+                && r.timestamp == audit.timestamp
+                && r.new_version == audit.new_version
+        });
+
+        match existing {
+            Some(regression) => regression.changes.push(audit.change.clone()),
+            None => regressions.push(Regression {
+                package_name: audit.package_name.clone(),
+                timestamp: audit.timestamp.clone(),
+                old_status: audit.old_status.unwrap_or(PackageStatus::Cjs),
+                new_status: audit.new_status.unwrap_or(PackageStatus::Cjs),
+                old_version: audit.old_version.clone(),
+                new_version: audit.new_version.clone(),
+                published: audit.published.clone(),
+                changes: vec![audit.change.clone()],
+            }),
+        }
+    }
+
+    regressions
+}
+
+/// Posts `regressions` to a webhook as `{"content": <summary>, "regressions": [...]}`, which
+/// Discord shows as a message and anything else can read the structured list from. Nothing is
+/// sent when there aren't any.
+pub async fn post_regressions(webhook: &str, regressions: &[Regression]) -> Result<(), Error> {
+    if regressions.is_empty() {
+        return Ok(());
+    }
+
+    let lines: Vec<String> = regressions.iter().map(|r| r.to_string()).collect();
+    let body = serde_json::json!({
+        "content": format!(
+            "[ESM Checker]\n{} package(s) regressed\n{}",
+            regressions.len(),
+            lines.join("\n")
+        ),
+        "regressions": regressions,
+    });
+
+    post_json(webhook, &body).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit(change: &str, old: PackageStatus, new: PackageStatus) -> AuditEntry {
+        AuditEntry {
+            package_name: String::from("test-package"),
+            timestamp: String::from("2022-01-20"),
+            change: change.to_owned(),
+            old_version: Some(String::from("1.0.0")),
+            new_version: Some(String::from("2.0.0")),
+            old_status: Some(old),
+            new_status: Some(new),
+            ..AuditEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_transitions() {
+        use PackageStatus::*;
+
+        assert_eq!(Transition::classify(Cjs, Dual), Transition::Adoption);
+        assert_eq!(Transition::classify(Cjs, EsmOnly), Transition::Adoption);
+        assert_eq!(Transition::classify(Dual, EsmOnly), Transition::Regression);
+        assert_eq!(Transition::classify(EsmOnly, Cjs), Transition::Regression);
+        assert_eq!(Transition::classify(EsmOnly, Dual), Transition::Neutral);
+        assert_eq!(Transition::classify(Dual, Dual), Transition::Neutral);
+    }
+
+    #[test]
+    fn test_find_regressions_groups_entries_of_one_check() {
+        use PackageStatus::*;
+
+        let audits = vec![
+            audit("exports_require", Dual, EsmOnly),
+            audit("exports_no_require", Dual, EsmOnly),
+            AuditEntry {
+                package_name: String::from("adopter"),
+                ..audit("type_module", Cjs, EsmOnly)
+            },
+            // Recorded before statuses were, so it can't be judged
+            AuditEntry {
+                package_name: String::from("legacy"),
+                old_status: None,
+                new_status: None,
+                ..audit("exports_require", Dual, Cjs)
+            },
+        ];
+
+        let regressions = find_regressions(&audits);
+
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(
+            regressions[0].changes,
+            vec!["exports_require", "exports_no_require"]
+        );
+        assert!(regressions[0].breaks_require());
+        assert_eq!(
+            regressions[0].to_string(),
+            "test-package 1.0.0 -> 2.0.0 (2022-01-20): dual -> esm-only, dropped the require condition"
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Summarizing how the stats moved between two runs, along with the packages behind the change,
+//! as a message for the notification channels.
+
+use crate::{
+    notify::{Field, Message, Trend},
+    store::Store,
+    trends::months_before,
+    AdoptionForecast, AuditEntry, AuditQuery, Error, PackageStatus, StatsDelta, StatsEntry,
+    Transition,
+};
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// The title of every report message.
+pub(crate) const TITLE: &str = "ESM Checker";
+
+/// Notable changes listed in the text of a report, the rest are only counted. A quarter can have
+/// hundreds.
+const NOTABLE_LIMIT: usize = 25;
+
+/// How far back a report looks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    /// Any range of days, given explicitly.
+    Custom,
+}
+
+impl Period {
+    /// The day a period ending on `end` is compared with, or `None` for a custom period. Months
+    /// clamp to the last day of shorter months.
+    pub fn start(self, end: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Period::Day => Some(end - Duration::days(1)),
+            Period::Week => Some(end - Duration::weeks(1)),
+            Period::Month => Some(months_before(end, 1)),
+            Period::Quarter => Some(months_before(end, 3)),
+            Period::Custom => None,
+        }
+    }
+
+    /// How the period introduces a report, such as `Weekly`.
+    pub fn label(self) -> &'static str {
+        match self {
+            Period::Day => "Daily",
+            Period::Week => "Weekly",
+            Period::Month => "Monthly",
+            Period::Quarter => "Quarterly",
+            Period::Custom => "Custom",
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" | "daily" => Ok(Period::Day),
+            "week" | "weekly" => Ok(Period::Week),
+            "month" | "monthly" => Ok(Period::Month),
+            "quarter" | "quarterly" => Ok(Period::Quarter),
+            "custom" => Ok(Period::Custom),
+            _ => Err(Error::Config(format!(
+                "unknown period `{}`, expected day, week, month, quarter or custom",
+                s
+            ))),
+        }
+    }
+}
+
+/// A package which started shipping ESM in some form, or changed how it does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NotableChange {
+    pub package_name: String,
+    pub timestamp: String,
+    pub old_status: PackageStatus,
+    pub new_status: PackageStatus,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub transition: Transition,
+}
+
+impl fmt::Display for NotableChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("?"));
+
+        write!(
+            f,
+            "{} {} -> {} ({}): {} -> {}",
+            self.package_name,
+            version(&self.old_version),
+            version(&self.new_version),
+            self.timestamp,
+            self.old_status,
+            self.new_status
+        )
+    }
+}
+
+impl NotableChange {
+    pub(crate) fn trend(&self) -> Trend {
+        match self.transition {
+            Transition::Adoption => Trend::Up,
+            Transition::Regression => Trend::Down,
+            Transition::Neutral => Trend::Flat,
+        }
+    }
+}
+
+/// The packages in `audits` which went dual or ESM only, one per package per check, oldest first.
+pub fn notable_changes(audits: &[AuditEntry]) -> Vec<NotableChange> {
+    let mut changes: Vec<NotableChange> = vec![];
+
+    for audit in audits {
+        let (old_status, new_status) = match (audit.old_status, audit.new_status) {
+            (Some(old), Some(new)) if old != new && new != PackageStatus::Cjs => (old, new),
+            _ => continue,
+        };
+
+        // The entries of one check share the timestamp and versions
+        let seen = changes.iter().any(|c| {
+            c.package_name == audit.package_name
+                && c.timestamp == audit.timestamp
+                && c.new_version == audit.new_version
+        });
+        if seen {
+            continue;
+        }
+
+        changes.push(NotableChange {
+            package_name: audit.package_name.clone(),
+            timestamp: audit.timestamp.clone(),
+            old_status,
+            new_status,
+            old_version: audit.old_version.clone(),
+            new_version: audit.new_version.clone(),
+            transition: Transition::classify(old_status, new_status),
+        });
+    }
+
+    changes
+}
+
+/// How the stats moved since an earlier run.
+#[derive(Debug, Clone)]
+pub struct StatsReport {
+    pub current: StatsEntry,
+    pub diff: StatsDelta,
+    pub notable: Vec<NotableChange>,
+    pub forecast: Option<AdoptionForecast>,
+    /// The period the report covers, which introduces it when set.
+    pub period: Option<Period>,
+}
+
+impl StatsReport {
+    pub fn new(current: StatsEntry, previous: &StatsEntry) -> Self {
+        StatsReport {
+            diff: &current - previous,
+            current,
+            notable: vec![],
+            forecast: None,
+            period: None,
+        }
+    }
+
+    /// Compares the first and last stats recorded from `from` to `to`, so a missing day at either
+    /// end falls back to the nearest one inside the range, along with the notable changes after
+    /// the first of them.
+    pub async fn between(store: &dyn Store, from: NaiveDate, to: NaiveDate) -> Result<Self, Error> {
+        // The stats table is partitioned by month, which a range query spans on its own
+        let entries = store
+            .stats_range(&from.format("%F").to_string(), &to.format("%F").to_string())
+            .await?;
+        let (first, last) = match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) if entries.len() > 1 => (first, last),
+            _ => {
+                return Err(Error::Store(format!(
+                    "a report needs stats from at least two days between {} and {}, found {}",
+                    from,
+                    to,
+                    entries.len()
+                )))
+            }
+        };
+
+        // Changes on the first day were already part of the previous report
+        let since = NaiveDate::parse_from_str(&first.timestamp, "%F")
+            .map_err(|e| Error::Store(format!("invalid stats day `{}`: {}", first.timestamp, e)))?
+            + Duration::days(1);
+        let audits = store
+            .query_audits(&AuditQuery {
+                from: Some(since.format("%F").to_string()),
+                to: Some(last.timestamp.clone()),
+                ..AuditQuery::default()
+            })
+            .await?;
+
+        Ok(StatsReport::new(last.clone(), first).notable(notable_changes(&audits)))
+    }
+
+    pub fn notable(mut self, notable: Vec<NotableChange>) -> Self {
+        self.notable = notable;
+        self
+    }
+
+    pub fn forecast(mut self, forecast: AdoptionForecast) -> Self {
+        self.forecast = Some(forecast);
+        self
+    }
+
+    pub fn period(mut self, period: Period) -> Self {
+        self.period = Some(period);
+        self
+    }
+
+    /// Such as `Weekly stats from 2022-01-13 to 2022-01-20`, or plain `Stats` for custom periods.
+    pub fn summary(&self) -> String {
+        let label = match self.period {
+            Some(Period::Custom) | None => String::from("Stats"),
+            Some(period) => format!("{} stats", period.label()),
+        };
+        format!(
+            "{} from {} to {}",
+            label, self.diff.since, self.diff.timestamp
+        )
+    }
+
+    /// The report as plain text, with a field per metric and an event per notable change for
+    /// channels which can show them.
+    pub fn message(&self) -> Message {
+        let (current, diff) = (&self.current, &self.diff);
+
+        let heading = match self.period {
+            Some(_) => self.summary(),
+            None => format!("Stats for {}", diff.timestamp),
+        };
+        let mut text = format!(
+            "{}\ntype_module: {:+} ({:+.2}pp)\nexports_require: {:+} ({:+.2}pp)\nexports_no_require: {:+} ({:+.2}pp)\ntotal_packages: {:+}\nESM adoption: {:.2}% ({:+.2}pp)",
+            heading,
+            diff.type_module,
+            diff.type_module_pp,
+            diff.exports_require,
+            diff.exports_require_pp,
+            diff.exports_no_require,
+            diff.exports_no_require_pp,
+            diff.total_packages,
+            current.adoption(),
+            diff.adoption_pp
+        );
+
+        let events: Vec<String> = self
+            .notable
+            .iter()
+            .map(|c| format!("{} {}", c.trend().arrow(), c))
+            .collect();
+        if !events.is_empty() {
+            let listed = events.len().min(NOTABLE_LIMIT);
+            text.push_str(&format!(
+                "\n\nNotable changes:\n{}",
+                events[..listed].join("\n")
+            ));
+            if listed < events.len() {
+                let count = |t| self.notable.iter().filter(|c| c.transition == t).count();
+                text.push_str(&format!(
+                    "\n…and {} more, {} adoptions and {} regressions in all",
+                    events.len() - listed,
+                    count(Transition::Adoption),
+                    count(Transition::Regression)
+                ));
+            }
+        }
+        if let Some(forecast) = &self.forecast {
+            text.push_str(&format!("\n\n{}", forecast));
+        }
+
+        let share = |name: &str, count: isize, change: isize, pp: f64| {
+            Field::new(name, format!("{} ({:.1}%)", count, current.percent(count)))
+                .change(format!("{:+} ({:+.2}pp)", change, pp), Trend::of(pp))
+        };
+
+        let mut message = Message::new(TITLE, text)
+            .summary(self.summary())
+            .field(share(
+                "type_module",
+                current.type_module,
+                diff.type_module,
+                diff.type_module_pp,
+            ))
+            .field(share(
+                "exports_require",
+                current.exports_require,
+                diff.exports_require,
+                diff.exports_require_pp,
+            ))
+            .field(share(
+                "exports_no_require",
+                current.exports_no_require,
+                diff.exports_no_require,
+                diff.exports_no_require_pp,
+            ))
+            .field(
+                Field::new("total_packages", current.total_packages.to_string()).change(
+                    format!("{:+}", diff.total_packages),
+                    Trend::of(diff.total_packages as f64),
+                ),
+            )
+            .field(
+                Field::new("ESM adoption", format!("{:.2}%", current.adoption())).change(
+                    format!("{:+.2}pp", diff.adoption_pp),
+                    Trend::of(diff.adoption_pp),
+                ),
+            )
+            .events(events)
+            .trend(Trend::of(diff.adoption_pp));
+
+        if let Some(forecast) = &self.forecast {
+            message = message.field(Field::new("Forecast", forecast.to_string()).block());
+        }
+
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit(
+        package_name: &str,
+        change: &str,
+        old: PackageStatus,
+        new: PackageStatus,
+    ) -> AuditEntry {
+        AuditEntry {
+            package_name: package_name.to_owned(),
+            timestamp: String::from("2022-01-20"),
+            change: change.to_owned(),
+            new_version: Some(String::from("2.0.0")),
+            old_status: Some(old),
+            new_status: Some(new),
+            ..AuditEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_notable_changes() {
+        use PackageStatus::*;
+
+        let audits = vec![
+            audit("went-esm", "type_module", Cjs, EsmOnly),
+            audit("went-esm", "exports_no_require", Cjs, EsmOnly),
+            audit("went-dual", "exports_require", Cjs, Dual),
+            audit("went-cjs", "exports_require", Dual, Cjs),
+            audit("dropped-require", "exports_require", Dual, EsmOnly),
+        ];
+
+        let changes = notable_changes(&audits);
+
+        let names: Vec<&str> = changes.iter().map(|c| c.package_name.as_str()).collect();
+        assert_eq!(names, vec!["went-esm", "went-dual", "dropped-require"]);
+        assert_eq!(changes[2].transition, Transition::Regression);
+        assert_eq!(
+            changes[0].to_string(),
+            "went-esm ? -> 2.0.0 (2022-01-20): cjs -> esm-only"
+        );
+    }
+
+    #[test]
+    fn test_period() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%F").unwrap();
+
+        assert_eq!("monthly".parse::<Period>().unwrap(), Period::Month);
+        assert!("fortnight".parse::<Period>().is_err());
+        assert_eq!(
+            Period::Week.start(date("2022-03-03")),
+            Some(date("2022-02-24"))
+        );
+        assert_eq!(
+            Period::Quarter.start(date("2022-05-31")),
+            Some(date("2022-02-28"))
+        );
+        assert_eq!(Period::Custom.start(date("2022-05-31")), None);
+    }
+
+    #[test]
+    fn test_message_lists_a_limited_number_of_notable_changes() {
+        let stats = |timestamp: &str| StatsEntry {
+            timestamp: timestamp.to_owned(),
+            total_packages: 100,
+            ..StatsEntry::default()
+        };
+        let audits: Vec<AuditEntry> = (0..30)
+            .map(|i| {
+                let new = if i < 28 {
+                    PackageStatus::Dual
+                } else {
+                    PackageStatus::EsmOnly
+                };
+                let old = if i < 28 {
+                    PackageStatus::Cjs
+                } else {
+                    PackageStatus::Dual
+                };
+                audit(&format!("package-{}", i), "exports_require", old, new)
+            })
+            .collect();
+        let report = StatsReport::new(stats("2022-03-31"), &stats("2021-12-31"))
+            .notable(notable_changes(&audits))
+            .period(Period::Quarter);
+
+        let message = report.message();
+
+        assert!(message
+            .text
+            .starts_with("Quarterly stats from 2021-12-31 to 2022-03-31\n"));
+        assert!(message
+            .text
+            .ends_with("▲ package-24 ? -> 2.0.0 (2022-01-20): cjs -> dual\n…and 5 more, 28 adoptions and 2 regressions in all"));
+        assert_eq!(message.events.len(), 30);
+    }
+
+    #[test]
+    fn test_message() {
+        let stats = |timestamp: &str, dual: isize| StatsEntry {
+            timestamp: timestamp.to_owned(),
+            total_packages: 100,
+            exports_require: dual,
+            dual,
+            cjs: 100 - dual,
+            ..StatsEntry::default()
+        };
+        let report = StatsReport::new(stats("2022-01-20", 12), &stats("2022-01-13", 10)).notable(
+            notable_changes(&[audit(
+                "went-dual",
+                "exports_require",
+                PackageStatus::Cjs,
+                PackageStatus::Dual,
+            )]),
+        );
+
+        let message = report.message();
+
+        assert!(message.text.starts_with("Stats for 2022-01-20\n"));
+        assert!(message
+            .text
+            .ends_with("Notable changes:\n▲ went-dual ? -> 2.0.0 (2022-01-20): cjs -> dual"));
+        assert_eq!(message.trend, Some(Trend::Up));
+        assert_eq!(message.fields.len(), 5);
+        assert_eq!(message.fields[1].value, "12 (12.0%)");
+        assert_eq!(message.fields[1].change.as_deref(), Some("+2 (+2.00pp)"));
+        assert_eq!(message.fields[0].trend, Some(Trend::Flat));
+        assert_eq!(message.events.len(), 1);
+    }
+}
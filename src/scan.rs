@@ -0,0 +1,183 @@
+use crate::{checkpoint, stream_packages, CheckOptions, Error, PackageReport, Source};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Registry wide counts of how packages are shipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanStats {
+    /// Packages which were classified.
+    pub scanned: usize,
+    /// Packages which couldn't be fetched or had an unusable manifest.
+    pub failed: usize,
+    pub type_module: usize,
+    pub exports_require: usize,
+    pub exports_no_require: usize,
+}
+
+impl ScanStats {
+    pub fn record(&mut self, result: &Result<PackageReport, Error>) {
+        match result {
+            Ok(report) => {
+                self.scanned += 1;
+                self.type_module += report.package.type_module as usize;
+                self.exports_require += report.package.exports_require as usize;
+                self.exports_no_require += report.package.exports_no_require as usize;
+            }
+            Err(_) => self.failed += 1,
+        }
+    }
+
+    /// Every package which has been attempted, whether or not it could be classified.
+    pub fn total(&self) -> usize {
+        self.scanned + self.failed
+    }
+
+    /// `count` as a percentage of the classified packages.
+    pub fn percent(&self, count: usize) -> f64 {
+        if self.scanned == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.scanned as f64
+        }
+    }
+}
+
+/// How far a scan has gotten through the registry, saved after every batch so an interrupted scan
+/// can pick up where it left off.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCheckpoint {
+    /// Index into the registry list of the next package to check.
+    pub position: usize,
+    pub stats: ScanStats,
+    /// The list `position` points into. Checkpoints saved before lists were recorded don't have
+    /// one, and are trusted to match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list: Option<ListFingerprint>,
+}
+
+/// Identifies a registry list, so a scan isn't resumed at the same index of a regenerated one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListFingerprint {
+    pub len: usize,
+    /// The 64-bit FNV-1a hash of the names, in hex. Unlike the hasher in std, it's stable across
+    /// Rust releases, which checkpoints outlive.
+    pub hash: String,
+}
+
+impl ListFingerprint {
+    pub fn of(names: &[String]) -> Self {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        // Each name ends with a comma, so `["ab", "c"]` and `["a", "bc"]` differ
+        for byte in names.iter().flat_map(|name| name.bytes().chain([b','])) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        ListFingerprint {
+            len: names.len(),
+            hash: format!("{:016x}", hash),
+        }
+    }
+}
+
+impl ScanCheckpoint {
+    /// Loads the checkpoint at `path`, if a scan has already been started.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        checkpoint::load(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        checkpoint::save(self, path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    source: Source,
+    checkpoint: PathBuf,
+    concurrency: usize,
+    batch_size: usize,
+}
+
+impl ScanOptions {
+    pub fn new(source: Source, checkpoint: impl Into<PathBuf>) -> Self {
+        ScanOptions {
+            source,
+            checkpoint: checkpoint.into(),
+            concurrency: 30,
+            batch_size: 500,
+        }
+    }
+
+    /// The maximum number of requests in flight at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How many packages to check between checkpoints.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+}
+
+/// Reads a comma separated list of package names, such as `registry.txt`.
+pub fn read_registry_list(path: &str) -> Result<Vec<String>, Error> {
+    Ok(fs::read_to_string(path)?
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect())
+}
+
+/// Classifies every package in `names`, resuming from the checkpoint in `options` if there is one.
+/// A checkpoint saved while scanning a different list is an error rather than resumed, as its
+/// position would skip or repeat packages.
+///
+/// `on_batch` is called with the updated checkpoint each time a batch has been saved.
+pub async fn scan_registry<F>(
+    names: &[String],
+    options: &ScanOptions,
+    mut on_batch: F,
+) -> Result<ScanCheckpoint, Error>
+where
+    F: FnMut(&ScanCheckpoint),
+{
+    let fingerprint = ListFingerprint::of(names);
+    let mut checkpoint = ScanCheckpoint::load(&options.checkpoint)?.unwrap_or_default();
+    match &checkpoint.list {
+        Some(list) if *list != fingerprint => {
+            return Err(Error::Config(format!(
+                "{} was saved while scanning a different list of {} packages, restart the scan to check this one",
+                options.checkpoint.display(),
+                list.len
+            )))
+        }
+        _ => checkpoint.list = Some(fingerprint),
+    }
+
+    while checkpoint.position < names.len() {
+        let end = names.len().min(checkpoint.position + options.batch_size);
+
+        let batch = CheckOptions::new(&names[checkpoint.position..end])
+            .concurrency(options.concurrency)
+            .source(options.source.clone());
+
+        let mut reports = stream_packages(&batch);
+        while let Some(report) = reports.next().await {
+            checkpoint.stats.record(&report);
+        }
+
+        checkpoint.position = end;
+        checkpoint.save(&options.checkpoint)?;
+        on_batch(&checkpoint);
+    }
+
+    Ok(checkpoint)
+}
@@ -0,0 +1,403 @@
+//! The HTTP server behind `esm-checker serve`, answering from the configured store so the site
+//! and other consumers don't need credentials for the tables.
+//!
+//! - `GET /badge/<name>.svg`: the badge of a tracked package. Scoped names can be given as is
+//!   (`/badge/@babel/core.svg`) or percent-encoded (`/badge/%40babel%2Fcore.svg`).
+//! - `GET /api/stats?from=&to=`: the stats entries between two days, oldest first. Either end
+//!   can be left out.
+//! - `GET /api/stats/latest`: the most recent stats entry.
+//! - `GET /api/packages?q=&status=&limit=&offset=`: tracked packages sorted by name, optionally
+//!   narrowed to names containing `q` and to one status.
+//! - `GET /api/packages/<name>`: a single tracked package.
+//! - `GET /api/audits?package=&from=&to=&change=&transition=&limit=&offset=`: entries in the
+//!   audit log, as with `esm-checker audits`. Without a package or `from`, only the last 30 days
+//!   are looked up.
+//! - `GET /api/check/<name[@version]>`: fetches and classifies any package from the registry
+//!   right away, as with `esm-checker check`. Nothing is written to the store.
+//!
+//! API responses are JSON, errors included as `{"error": "<message>"}`.
+
+use crate::{
+    analyze_packages, store::Store, AuditEntry, AuditQuery, Badge, Error, Package, PackageSpec,
+    PackageStatus, Source, Transition, FIRST_STATS_DATE, NPM_REGISTRY_URL,
+};
+use chrono::{Duration as Days, NaiveDate, Utc};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    net::TcpListener,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+/// How long clients and CDNs may reuse a badge. The packages are checked once a day, so there's
+/// little point in asking for it more often.
+const BADGE_MAX_AGE: u32 = 3600;
+/// Packages or audit entries listed per page when no `limit` is given.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+/// How long the package list is reused across `/api/packages` requests, rather than reading the
+/// whole table for every page and search.
+const PACKAGE_CACHE_TTL: Duration = Duration::from_secs(300);
+/// How far back `/api/audits` looks when it's given neither a package nor a first day.
+const DEFAULT_AUDIT_DAYS: i64 = 30;
+
+/// Every tracked package sorted by name, and when it was read from the store.
+type PackageCache = Option<(Instant, Arc<Vec<Package>>)>;
+
+#[derive(Clone)]
+pub struct Server {
+    store: Arc<dyn Store>,
+    source: Source,
+    packages: Arc<Mutex<PackageCache>>,
+}
+
+/// Why a request couldn't be answered.
+enum Failure {
+    BadRequest(String),
+    NotFound(String),
+    /// The registry couldn't be reached or returned something unusable.
+    Upstream(Error),
+    Internal(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Internal(e)
+    }
+}
+
+type Reply = Result<Response<Body>, Failure>;
+
+impl Server {
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        Server {
+            store,
+            source: Source::Registry(NPM_REGISTRY_URL.to_owned()),
+            packages: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Where `/api/check` fetches manifests from, the npm registry by default.
+    pub fn source(mut self, source: Source) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Answers requests on `listener` until `shutdown` completes.
+    pub async fn run(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let make_service = make_service_fn(move |_| {
+            let server = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(req).await) }
+                }))
+            }
+        });
+
+        hyper::Server::from_tcp(listener)?
+            .serve(make_service)
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        Ok(())
+    }
+
+    async fn respond(&self, req: Request<Body>) -> Response<Body> {
+        if req.method() != Method::GET {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+        }
+
+        let path = req.uri().path();
+        let params = Params::from_query(req.uri().query());
+        let result = if let Some(name) = path
+            .strip_prefix("/badge/")
+            .and_then(|p| p.strip_suffix(".svg"))
+        {
+            self.badge(&decode(name)).await
+        } else if let Some(route) = path.strip_prefix("/api/") {
+            self.api(route, &params).await
+        } else {
+            Err(Failure::NotFound(format!("no route for {}", path)))
+        };
+
+        match result {
+            Ok(res) => res,
+            Err(Failure::BadRequest(message)) => error(StatusCode::BAD_REQUEST, &message),
+            Err(Failure::NotFound(message)) => error(StatusCode::NOT_FOUND, &message),
+            Err(Failure::Upstream(e)) => error(StatusCode::BAD_GATEWAY, &e.to_string()),
+            Err(Failure::Internal(e)) => {
+                eprintln!("failed to answer {}: {}", path, e);
+                error(StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+            }
+        }
+    }
+
+    /// Untracked packages get an `unknown` badge rather than a 404, so READMEs don't show a
+    /// broken image.
+    async fn badge(&self, name: &str) -> Reply {
+        let badge = match self.store.package(name).await? {
+            Some(pkg) => Badge::for_package(&pkg),
+            None => Badge::unknown(),
+        };
+
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, "image/svg+xml")
+            .header(
+                header::CACHE_CONTROL,
+                format!("public, max-age={}", BADGE_MAX_AGE),
+            )
+            .body(Body::from(badge.svg()))
+            .unwrap())
+    }
+
+    async fn api(&self, route: &str, params: &Params) -> Reply {
+        match route {
+            "stats" => self.stats(params).await,
+            "stats/latest" => self.latest_stats().await,
+            "packages" => self.packages(params).await,
+            "audits" => self.audits(params).await,
+            _ => {
+                if let Some(name) = route.strip_prefix("packages/") {
+                    self.package(&decode(name)).await
+                } else if let Some(spec) = route.strip_prefix("check/") {
+                    self.check(PackageSpec::from(decode(spec))).await
+                } else {
+                    Err(Failure::NotFound(format!("no route for /api/{}", route)))
+                }
+            }
+        }
+    }
+
+    async fn stats(&self, params: &Params) -> Reply {
+        let from = params.date("from")?;
+        let to = params.date("to")?;
+
+        let entries = match (from, to) {
+            (None, None) => self.store.stats().await?,
+            (from, to) => {
+                let from = from.unwrap_or_else(|| FIRST_STATS_DATE.to_owned());
+                let to =
+                    to.unwrap_or_else(|| Utc::now().naive_utc().date().format("%F").to_string());
+                self.store.stats_range(&from, &to).await?
+            }
+        };
+        Ok(json(&entries))
+    }
+
+    async fn latest_stats(&self) -> Reply {
+        match self.store.latest_stats().await? {
+            Some(entry) => Ok(json(&entry)),
+            None => Err(Failure::NotFound(String::from(
+                "no stats have been recorded",
+            ))),
+        }
+    }
+
+    async fn packages(&self, params: &Params) -> Reply {
+        let search = params.get("q").map(str::to_lowercase);
+        let status: Option<PackageStatus> = params.parse("status")?;
+        let (limit, offset) = params.page()?;
+
+        let all = self.package_list().await?;
+        let packages: Vec<&Package> = all
+            .iter()
+            .filter(|pkg| {
+                search
+                    .as_ref()
+                    .is_none_or(|q| pkg.name.to_lowercase().contains(q))
+                    && status.is_none_or(|s| pkg.status() == s)
+            })
+            .collect();
+
+        Ok(json(&PackagePage {
+            total: packages.len(),
+            packages: packages.into_iter().skip(offset).take(limit).collect(),
+        }))
+    }
+
+    /// Every tracked package sorted by name, read from the store at most once per
+    /// [`PACKAGE_CACHE_TTL`].
+    async fn package_list(&self) -> Result<Arc<Vec<Package>>, Error> {
+        // Held while reading the store, so concurrent requests wait for one read
+        let mut cache = self.packages.lock().await;
+        if let Some((read_at, packages)) = cache.as_ref() {
+            if read_at.elapsed() < PACKAGE_CACHE_TTL {
+                return Ok(packages.clone());
+            }
+        }
+
+        let mut packages: Vec<Package> = self.store.packages().await?.into_values().collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        let packages = Arc::new(packages);
+        *cache = Some((Instant::now(), packages.clone()));
+        Ok(packages)
+    }
+
+    async fn package(&self, name: &str) -> Reply {
+        match self.store.package(name).await? {
+            Some(pkg) => Ok(json(&pkg)),
+            None => Err(Failure::NotFound(format!("{} isn't tracked", name))),
+        }
+    }
+
+    async fn audits(&self, params: &Params) -> Reply {
+        let (limit, offset) = params.page()?;
+        let mut query = AuditQuery {
+            package: params.get("package").map(str::to_owned),
+            from: params.date("from")?,
+            to: params.date("to")?,
+            change: params.get("change").map(str::to_owned),
+            transition: params.parse::<Transition>("transition")?,
+        };
+        // Without a package or a first day the store would have to read the whole audit log
+        if query.package.is_none() && query.from.is_none() {
+            let to = match &query.to {
+                Some(to) => NaiveDate::parse_from_str(to, "%F").unwrap(),
+                None => Utc::now().naive_utc().date(),
+            };
+            query.from = Some(
+                (to - Days::days(DEFAULT_AUDIT_DAYS))
+                    .format("%F")
+                    .to_string(),
+            );
+            query.to = Some(to.format("%F").to_string());
+        }
+
+        let audits = self.store.query_audits(&query).await?;
+        Ok(json(&AuditPage {
+            total: audits.len(),
+            audits: audits.into_iter().skip(offset).take(limit).collect(),
+        }))
+    }
+
+    async fn check(&self, spec: PackageSpec) -> Reply {
+        let result = analyze_packages(std::slice::from_ref(&spec), &self.source)
+            .await
+            .remove(0);
+
+        match result {
+            Ok(analysis) => Ok(json(&analysis)),
+            Err(e @ Error::Unpublished { .. }) => Err(Failure::NotFound(e.to_string())),
+            Err(e) if e.status() == Some(404) => {
+                Err(Failure::NotFound(format!("{} isn't published", spec)))
+            }
+            Err(e) => Err(Failure::Upstream(e)),
+        }
+    }
+}
+
+/// A page of `/api/packages`, with the number of packages matching across every page.
+#[derive(Serialize)]
+struct PackagePage<'a> {
+    total: usize,
+    packages: Vec<&'a Package>,
+}
+
+/// A page of `/api/audits`, with the number of entries matching across every page.
+#[derive(Serialize)]
+struct AuditPage {
+    total: usize,
+    audits: Vec<AuditEntry>,
+}
+
+/// The parameters of a query string, decoded.
+struct Params(HashMap<String, String>);
+
+impl Params {
+    fn from_query(query: Option<&str>) -> Self {
+        let pairs = query
+            .unwrap_or_default()
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_query(key), decode_query(value))
+            });
+        Params(pairs.collect())
+    }
+
+    /// The value of `key`, unless it's missing or empty.
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .get(key)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse<T>(&self, key: &str) -> Result<Option<T>, Failure>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(key)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| Failure::BadRequest(format!("invalid {} `{}`: {}", key, value, e)))
+            })
+            .transpose()
+    }
+
+    /// The `limit` and `offset` of a page of results.
+    fn page(&self) -> Result<(usize, usize), Failure> {
+        let limit = self.parse("limit")?.unwrap_or(DEFAULT_PAGE_SIZE);
+        if limit > MAX_PAGE_SIZE {
+            return Err(Failure::BadRequest(format!(
+                "limit can be at most {}",
+                MAX_PAGE_SIZE
+            )));
+        }
+        Ok((limit, self.parse("offset")?.unwrap_or(0)))
+    }
+
+    /// A day given as `%Y-%m-%d`, checked so it compares correctly against the stored ones.
+    fn date(&self, key: &str) -> Result<Option<String>, Failure> {
+        Ok(self
+            .parse::<NaiveDate>(key)?
+            .map(|date| date.format("%F").to_string()))
+    }
+}
+
+/// Percent-decodes a segment of the path. A `+` is kept as is, as in `pkg@1.0.0+build.1`.
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+/// Percent-decodes a key or value of the query string, taking `+` as a space like forms do.
+fn decode_query(s: &str) -> String {
+    decode(&s.replace('+', " "))
+}
+
+/// Every API response may be read from other origins, such as the site.
+fn json<T: Serialize>(body: &T) -> Response<Body> {
+    json_with_status(StatusCode::OK, body)
+}
+
+fn json_with_status<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(serde_json::to_string(body).unwrap_or_default()))
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json_with_status(status, &serde_json::json!({ "error": message }))
+}
@@ -0,0 +1,340 @@
+use crate::{Package, PackageStatus};
+use aws_sdk_dynamodb::model::AttributeValue;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, ops::Sub};
+
+/// The aggregate numbers from a single day's run, one row of the stats table.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsEntry {
+    /// The day the stats were collected, formatted as `%Y-%m-%d`.
+    pub timestamp: String,
+    /// Packages which were classified.
+    pub total_packages: isize,
+    /// Packages which couldn't be fetched or had an unusable manifest.
+    pub failures: isize,
+    pub type_module: isize,
+    pub exports_require: isize,
+    pub exports_no_require: isize,
+    /// Packages with a [`PackageStatus::Cjs`] classification.
+    pub cjs: isize,
+    /// Packages with a [`PackageStatus::Dual`] classification.
+    pub dual: isize,
+    /// Packages with a [`PackageStatus::EsmOnly`] classification.
+    pub esm_only: isize,
+    /// Whether `cjs`, `dual` and `esm_only` were estimated from the field counts, for rows
+    /// written before the per-status counts were recorded. Estimates count packages with only
+    /// `type: module` as CJS, so they understate adoption compared with later rows.
+    #[serde(default)]
+    pub estimated: bool,
+}
+
+impl StatsEntry {
+    /// Tallies a run which classified `packages` and failed to classify `failures` more.
+    pub fn from_packages(timestamp: &str, packages: &[Package], failures: usize) -> Self {
+        let count = |f: fn(&Package) -> bool| packages.iter().filter(|p| f(p)).count() as isize;
+
+        StatsEntry {
+            timestamp: timestamp.to_owned(),
+            total_packages: packages.len() as isize,
+            failures: failures as isize,
+            type_module: count(|p| p.type_module),
+            exports_require: count(|p| p.exports_require),
+            exports_no_require: count(|p| p.exports_no_require),
+            cjs: count(|p| p.status() == PackageStatus::Cjs),
+            dual: count(|p| p.status() == PackageStatus::Dual),
+            esm_only: count(|p| p.status() == PackageStatus::EsmOnly),
+            estimated: false,
+        }
+    }
+
+    /// The partition key of the stats table, the `%Y-%m` of the timestamp.
+    pub fn year_month(&self) -> &str {
+        self.timestamp.get(..7).unwrap_or(&self.timestamp)
+    }
+
+    /// `count` as a percentage of the classified packages.
+    pub fn percent(&self, count: isize) -> f64 {
+        if self.total_packages == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total_packages as f64
+        }
+    }
+
+    /// The percentage of packages which ship ESM in some form, either dual or ESM only.
+    pub fn adoption(&self) -> f64 {
+        self.percent(self.dual + self.esm_only)
+    }
+}
+
+fn number(map: &HashMap<String, AttributeValue>, key: &str) -> Option<isize> {
+    map.get(key)?.as_n().ok()?.parse::<isize>().ok()
+}
+
+impl From<HashMap<String, AttributeValue>> for StatsEntry {
+    fn from(map: HashMap<String, AttributeValue>) -> Self {
+        let type_module = number(&map, "type_module").unwrap();
+        let exports_require = number(&map, "exports_require").unwrap();
+        let exports_no_require = number(&map, "exports_no_require").unwrap();
+        let total_packages = number(&map, "total_packages").unwrap_or_default();
+
+        // Rows written before the per-status counts existed only have the field counts, which
+        // approximate them (packages with only `type: module` end up counted as CJS).
+        let estimated = !map.contains_key("esm_only");
+        let dual = number(&map, "dual").unwrap_or(exports_require);
+        let esm_only = number(&map, "esm_only").unwrap_or(exports_no_require);
+        let cjs = number(&map, "cjs").unwrap_or(total_packages - dual - esm_only);
+
+        StatsEntry {
+            timestamp: map["timestamp"].as_s().unwrap().to_string(),
+            total_packages,
+            failures: number(&map, "failures").unwrap_or_default(),
+            type_module,
+            exports_require,
+            exports_no_require,
+            cjs,
+            dual,
+            esm_only,
+            estimated,
+        }
+    }
+}
+
+impl From<&StatsEntry> for HashMap<String, AttributeValue> {
+    fn from(entry: &StatsEntry) -> Self {
+        let n = |v: isize| AttributeValue::N(v.to_string());
+
+        let mut map = HashMap::from([
+            (
+                String::from("year_month"),
+                AttributeValue::S(entry.year_month().to_owned()),
+            ),
+            (
+                String::from("timestamp"),
+                AttributeValue::S(entry.timestamp.clone()),
+            ),
+            (String::from("total_packages"), n(entry.total_packages)),
+            (String::from("failures"), n(entry.failures)),
+            (String::from("type_module"), n(entry.type_module)),
+            (String::from("exports_require"), n(entry.exports_require)),
+            (
+                String::from("exports_no_require"),
+                n(entry.exports_no_require),
+            ),
+        ]);
+
+        // Estimates are left out so the row keeps reading back as estimated
+        if !entry.estimated {
+            map.insert(String::from("cjs"), n(entry.cjs));
+            map.insert(String::from("dual"), n(entry.dual));
+            map.insert(String::from("esm_only"), n(entry.esm_only));
+        }
+        map
+    }
+}
+
+/// The change between two [`StatsEntry`]s, produced by subtracting the older from the newer.
+///
+/// Counts are absolute differences while the `*_pp` fields are the change in percentage points of
+/// each count's share of the total, so growth in the package list doesn't read as adoption.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct StatsDelta {
+    /// The timestamp of the newer entry.
+    pub timestamp: String,
+    /// The timestamp of the older entry.
+    pub since: String,
+    pub total_packages: isize,
+    pub failures: isize,
+    pub type_module: isize,
+    pub exports_require: isize,
+    pub exports_no_require: isize,
+    pub cjs: isize,
+    pub dual: isize,
+    pub esm_only: isize,
+    pub type_module_pp: f64,
+    pub exports_require_pp: f64,
+    pub exports_no_require_pp: f64,
+    pub cjs_pp: f64,
+    pub dual_pp: f64,
+    pub esm_only_pp: f64,
+    pub adoption_pp: f64,
+}
+
+impl Sub for StatsEntry {
+    type Output = StatsDelta;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        &self - &rhs
+    }
+}
+
+impl Sub for &StatsEntry {
+    type Output = StatsDelta;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let pp = |new: isize, old: isize| self.percent(new) - rhs.percent(old);
+
+        StatsDelta {
+            timestamp: self.timestamp.clone(),
+            since: rhs.timestamp.clone(),
+            total_packages: self.total_packages - rhs.total_packages,
+            failures: self.failures - rhs.failures,
+            type_module: self.type_module - rhs.type_module,
+            exports_require: self.exports_require - rhs.exports_require,
+            exports_no_require: self.exports_no_require - rhs.exports_no_require,
+            cjs: self.cjs - rhs.cjs,
+            dual: self.dual - rhs.dual,
+            esm_only: self.esm_only - rhs.esm_only,
+            type_module_pp: pp(self.type_module, rhs.type_module),
+            exports_require_pp: pp(self.exports_require, rhs.exports_require),
+            exports_no_require_pp: pp(self.exports_no_require, rhs.exports_no_require),
+            cjs_pp: pp(self.cjs, rhs.cjs),
+            dual_pp: pp(self.dual, rhs.dual),
+            esm_only_pp: pp(self.esm_only, rhs.esm_only),
+            adoption_pp: self.adoption() - rhs.adoption(),
+        }
+    }
+}
+
+impl fmt::Display for StatsEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Total packages: {}", self.total_packages)?;
+        writeln!(f, "Failed to check: {}", self.failures)?;
+        writeln!(
+            f,
+            "Packages with a `type: module` field: {} ({:.1}%)",
+            self.type_module,
+            self.percent(self.type_module)
+        )?;
+        writeln!(
+            f,
+            "Packages with a `exports.require` field: {} ({:.1}%)",
+            self.exports_require,
+            self.percent(self.exports_require)
+        )?;
+        writeln!(
+            f,
+            "Packages without an explicit `exports.require` that may be ESM only: {} ({:.1}%)",
+            self.exports_no_require,
+            self.percent(self.exports_no_require)
+        )?;
+        write!(
+            f,
+            "CJS: {}, dual: {}, ESM only: {} ({:.1}% ship ESM)",
+            self.cjs,
+            self.dual,
+            self.esm_only,
+            self.adoption()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pkg(type_module: bool, exports_require: bool, exports_no_require: bool) -> Package {
+        Package {
+            name: String::from("test-package"),
+            type_module,
+            exports_require,
+            exports_no_require,
+            ..Package::default()
+        }
+    }
+
+    #[test]
+    fn test_from_packages() {
+        let packages = vec![
+            pkg(false, false, false),
+            pkg(false, true, false),
+            pkg(true, true, false),
+            pkg(true, false, true),
+            pkg(true, false, false),
+        ];
+
+        let entry = StatsEntry::from_packages("2022-01-20", &packages, 2);
+
+        assert_eq!(entry.total_packages, 5);
+        assert_eq!(entry.failures, 2);
+        assert_eq!(entry.type_module, 3);
+        assert_eq!(entry.exports_require, 2);
+        assert_eq!(entry.exports_no_require, 1);
+        assert_eq!(entry.cjs, 1);
+        assert_eq!(entry.dual, 2);
+        assert_eq!(entry.esm_only, 2);
+        assert_eq!(entry.adoption(), 80.0);
+        assert_eq!(entry.year_month(), "2022-01");
+    }
+
+    #[test]
+    fn test_sub_percentage_points() {
+        let old = StatsEntry {
+            timestamp: String::from("2022-01-13"),
+            total_packages: 100,
+            dual: 10,
+            esm_only: 10,
+            cjs: 80,
+            ..StatsEntry::default()
+        };
+        let new = StatsEntry {
+            timestamp: String::from("2022-01-20"),
+            total_packages: 200,
+            dual: 30,
+            esm_only: 20,
+            cjs: 150,
+            ..StatsEntry::default()
+        };
+
+        let delta = new - old;
+
+        assert_eq!(delta.timestamp, "2022-01-20");
+        assert_eq!(delta.since, "2022-01-13");
+        assert_eq!(delta.total_packages, 100);
+        assert_eq!(delta.dual, 20);
+        assert_eq!(delta.esm_only, 10);
+        assert_eq!(delta.dual_pp, 5.0);
+        assert_eq!(delta.esm_only_pp, 0.0);
+        assert_eq!(delta.cjs_pp, -5.0);
+        assert_eq!(delta.adoption_pp, 5.0);
+    }
+
+    #[test]
+    fn test_from_legacy_attributes() {
+        let map = HashMap::from([
+            (
+                String::from("timestamp"),
+                AttributeValue::S(String::from("2022-01-01")),
+            ),
+            (
+                String::from("total_packages"),
+                AttributeValue::N(String::from("100")),
+            ),
+            (
+                String::from("type_module"),
+                AttributeValue::N(String::from("8")),
+            ),
+            (
+                String::from("exports_require"),
+                AttributeValue::N(String::from("12")),
+            ),
+            (
+                String::from("exports_no_require"),
+                AttributeValue::N(String::from("5")),
+            ),
+        ]);
+
+        let entry = StatsEntry::from(map);
+
+        assert_eq!(entry.total_packages, 100);
+        assert_eq!(entry.failures, 0);
+        assert_eq!(entry.dual, 12);
+        assert_eq!(entry.esm_only, 5);
+        assert_eq!(entry.cjs, 83);
+        assert!(entry.estimated);
+
+        let written = HashMap::from(&entry);
+        assert!(!written.contains_key("esm_only"));
+        assert_eq!(StatsEntry::from(written), entry);
+    }
+}
@@ -0,0 +1,534 @@
+use super::Store;
+use crate::{AuditEntry, AuditQuery, Error, Package, StatsEntry, FIRST_STATS_DATE};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::client::fluent_builders::Query;
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_types::region::Region;
+use chrono::{Datelike, NaiveDate, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+
+/// How many days of the audit table are queried at once.
+const DAY_QUERY_CONCURRENCY: usize = 16;
+
+/// The names of the tables provisioned by the CDK stack. Commands only need the tables they
+/// touch, so any of them may be left out.
+#[derive(Debug, Clone, Default)]
+pub struct DynamoTables {
+    pub stats: Option<String>,
+    pub packages: Option<String>,
+    pub audits: Option<String>,
+}
+
+impl DynamoTables {
+    pub fn from_env() -> Self {
+        DynamoTables {
+            stats: std::env::var("DYNAMO_STATS_TABLE_NAME").ok(),
+            packages: std::env::var("DYNAMO_PACKAGE_TABLE_NAME").ok(),
+            audits: std::env::var("DYNAMO_AUDIT_TABLE_NAME").ok(),
+        }
+    }
+}
+
+pub struct DynamoStore {
+    client: aws_sdk_dynamodb::Client,
+    tables: DynamoTables,
+}
+
+impl DynamoStore {
+    pub fn new(client: aws_sdk_dynamodb::Client, tables: DynamoTables) -> Self {
+        DynamoStore { client, tables }
+    }
+
+    /// Connects to DynamoDB in `AWS_REGION` using the tables named by the `DYNAMO_*_TABLE_NAME`
+    /// environment variables. Setting `DYNAMO_ENDPOINT` points the client somewhere other than
+    /// AWS, such as a DynamoDB Local instance on `http://localhost:8000`.
+    pub async fn from_env() -> Result<Self, Error> {
+        let aws_region = std::env::var("AWS_REGION")
+            .map(Region::new)
+            .map_err(|_| Error::Config(String::from("AWS_REGION is not set")))?;
+        let config = aws_config::from_env().region(aws_region).load().await;
+
+        let mut builder = aws_sdk_dynamodb::config::Builder::from(&config);
+        if let Ok(endpoint) = std::env::var("DYNAMO_ENDPOINT") {
+            let uri = endpoint.parse().map_err(|e| {
+                Error::Config(format!("invalid DYNAMO_ENDPOINT `{}`: {}", endpoint, e))
+            })?;
+            builder = builder.endpoint_resolver(aws_sdk_dynamodb::Endpoint::immutable(uri));
+        }
+
+        Ok(DynamoStore::new(
+            aws_sdk_dynamodb::Client::from_conf(builder.build()),
+            DynamoTables::from_env(),
+        ))
+    }
+
+    pub fn client(&self) -> &aws_sdk_dynamodb::Client {
+        &self.client
+    }
+
+    fn stats_table(&self) -> Result<&str, Error> {
+        required(&self.tables.stats, "DYNAMO_STATS_TABLE_NAME")
+    }
+
+    fn package_table(&self) -> Result<&str, Error> {
+        required(&self.tables.packages, "DYNAMO_PACKAGE_TABLE_NAME")
+    }
+
+    fn audit_table(&self) -> Result<&str, Error> {
+        required(&self.tables.audits, "DYNAMO_AUDIT_TABLE_NAME")
+    }
+
+    /// Every item in `table`, following the scan across pages.
+    async fn scan(&self, table: &str) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut items = vec![];
+        let mut start_key = None;
+
+        loop {
+            let scan_result = self
+                .client
+                .scan()
+                .table_name(table)
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(store_err)?;
+
+            items.extend(scan_result.items.unwrap_or_default());
+
+            start_key = scan_result.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    /// Every item matched by `query`, following it across pages.
+    async fn query_all(&self, query: Query) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+        let mut items = vec![];
+        let mut start_key = None;
+
+        loop {
+            let query_output = query
+                .clone()
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(store_err)?;
+
+            items.extend(query_output.items.unwrap_or_default());
+
+            start_key = query_output.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+fn required<'a>(table: &'a Option<String>, var: &str) -> Result<&'a str, Error> {
+    table.as_deref().ok_or_else(|| {
+        Error::Config(format!(
+            "There should be a table name defined in the {} environment variable",
+            var
+        ))
+    })
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(date, "%F")
+        .map_err(|e| Error::Config(format!("invalid date `{}`: {}", date, e)))
+}
+
+/// Every `%Y-%m` partition key between two `%Y-%m-%d` dates.
+fn year_months(from: &str, to: &str) -> Result<Vec<String>, Error> {
+    let (from, to) = (parse_date(from)?, parse_date(to)?);
+
+    let mut months = vec![];
+    let mut month = NaiveDate::from_ymd(from.year(), from.month(), 1);
+    while month <= to {
+        months.push(month.format("%Y-%m").to_string());
+        month = match month.month() {
+            12 => NaiveDate::from_ymd(month.year() + 1, 1, 1),
+            m => NaiveDate::from_ymd(month.year(), m + 1, 1),
+        };
+    }
+    Ok(months)
+}
+
+/// Every day between two `%Y-%m-%d` dates, inclusive.
+fn days(from: &str, to: &str) -> Result<Vec<String>, Error> {
+    let (from, to) = (parse_date(from)?, parse_date(to)?);
+
+    let mut days = vec![];
+    let mut day = from;
+    while day <= to {
+        days.push(day.format("%F").to_string());
+        day = day.succ();
+    }
+    Ok(days)
+}
+
+/// Scans and queries return items in key order, which isn't chronological across partitions.
+fn sort_audits(audits: &mut [AuditEntry]) {
+    audits.sort_by(|a, b| {
+        (&a.timestamp, a.seq, &a.package_name).cmp(&(&b.timestamp, b.seq, &b.package_name))
+    });
+}
+
+fn store_err<E: std::fmt::Display>(e: E) -> Error {
+    Error::Store(e.to_string())
+}
+
+#[async_trait]
+impl Store for DynamoStore {
+    async fn put_stats(&self, stats: &StatsEntry) -> Result<(), Error> {
+        self.client
+            .put_item()
+            .table_name(self.stats_table()?)
+            .set_item(Some(stats.into()))
+            .send()
+            .await
+            .map_err(store_err)?;
+        Ok(())
+    }
+
+    async fn stats_on(&self, date: &str) -> Result<Option<StatsEntry>, Error> {
+        let query_output = self
+            .client
+            .query()
+            .table_name(self.stats_table()?)
+            .key_condition_expression("year_month = :ym and #timestamp = :t")
+            .expression_attribute_names("#timestamp", "timestamp")
+            .expression_attribute_values(
+                ":ym",
+                AttributeValue::S(date.get(..7).unwrap_or(date).to_owned()),
+            )
+            .expression_attribute_values(":t", AttributeValue::S(date.to_owned()))
+            .send()
+            .await
+            .map_err(store_err)?;
+
+        Ok(query_output
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .next()
+            .map(StatsEntry::from))
+    }
+
+    async fn stats(&self) -> Result<Vec<StatsEntry>, Error> {
+        let mut entries: Vec<StatsEntry> = self
+            .scan(self.stats_table()?)
+            .await?
+            .into_iter()
+            .map(StatsEntry::from)
+            .collect();
+        entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        Ok(entries)
+    }
+
+    async fn latest_stats(&self) -> Result<Option<StatsEntry>, Error> {
+        let today = Utc::now().format("%F").to_string();
+
+        // Runs are daily, so the current month or the one before it almost always has the entry
+        for year_month in year_months(FIRST_STATS_DATE, &today)?.into_iter().rev() {
+            let query_output = self
+                .client
+                .query()
+                .table_name(self.stats_table()?)
+                .key_condition_expression("year_month = :ym")
+                .expression_attribute_values(":ym", AttributeValue::S(year_month))
+                .scan_index_forward(false)
+                .limit(1)
+                .send()
+                .await
+                .map_err(store_err)?;
+
+            if let Some(item) = query_output.items.unwrap_or_default().into_iter().next() {
+                return Ok(Some(StatsEntry::from(item)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn stats_range(&self, from: &str, to: &str) -> Result<Vec<StatsEntry>, Error> {
+        let mut entries = vec![];
+
+        // The table is partitioned by month, so every month in the range needs its own query
+        for year_month in year_months(from, to)? {
+            let query = self
+                .client
+                .query()
+                .table_name(self.stats_table()?)
+                .key_condition_expression("year_month = :ym and #timestamp BETWEEN :from AND :to")
+                .expression_attribute_names("#timestamp", "timestamp")
+                .expression_attribute_values(":ym", AttributeValue::S(year_month))
+                .expression_attribute_values(":from", AttributeValue::S(from.to_owned()))
+                .expression_attribute_values(":to", AttributeValue::S(to.to_owned()));
+
+            entries.extend(
+                self.query_all(query)
+                    .await?
+                    .into_iter()
+                    .map(StatsEntry::from),
+            );
+        }
+
+        entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        Ok(entries)
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, Package>, Error> {
+        Ok(self
+            .scan(self.package_table()?)
+            .await?
+            .into_iter()
+            .map(|item| {
+                let pkg = Package::from(item);
+                (pkg.name.clone(), pkg)
+            })
+            .collect())
+    }
+
+    async fn package(&self, name: &str) -> Result<Option<Package>, Error> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(self.package_table()?)
+            .key("package_name", AttributeValue::S(name.to_owned()))
+            .send()
+            .await
+            .map_err(store_err)?;
+
+        Ok(output.item.map(Package::from))
+    }
+
+    async fn put_package(&self, pkg: &Package) -> Result<(), Error> {
+        let mut set = vec![
+            String::from("exports_require=:exports_require"),
+            String::from("exports_no_require=:exports_no_require"),
+            String::from("type_module=:type_module"),
+            String::from("#manifest=:manifest"),
+        ];
+        let mut remove = vec![];
+
+        // An update rather than a put, so attributes written by other tools (such as
+        // `greatest_semver`) are left alone
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.package_table()?)
+            .key("package_name", AttributeValue::S(pkg.name.clone()))
+            .expression_attribute_names("#manifest", "manifest")
+            .expression_attribute_values(
+                ":exports_require",
+                AttributeValue::Bool(pkg.exports_require),
+            )
+            .expression_attribute_values(
+                ":exports_no_require",
+                AttributeValue::Bool(pkg.exports_no_require),
+            )
+            .expression_attribute_values(":type_module", AttributeValue::Bool(pkg.type_module))
+            .expression_attribute_values(
+                ":manifest",
+                AttributeValue::S(serde_json::to_string(&pkg.manifest)?),
+            );
+
+        // `version` is a reserved word, so every optional attribute goes through a name
+        for (name, value) in [
+            ("version", pkg.version.clone()),
+            ("published", pkg.published.clone()),
+            ("deprecated", pkg.deprecated.clone()),
+            ("inactive", pkg.inactive.map(|i| i.to_string())),
+        ] {
+            request = request.expression_attribute_names(format!("#{}", name), name);
+            match value {
+                Some(value) => {
+                    set.push(format!("#{0}=:{0}", name));
+                    request = request.expression_attribute_values(
+                        format!(":{}", name),
+                        AttributeValue::S(value),
+                    );
+                }
+                None => remove.push(format!("#{}", name)),
+            }
+        }
+
+        let mut update_expression = format!("SET {}", set.join(", "));
+        if !remove.is_empty() {
+            update_expression.push_str(&format!(" REMOVE {}", remove.join(", ")));
+        }
+
+        request
+            .update_expression(update_expression)
+            .send()
+            .await
+            .map_err(store_err)?;
+        Ok(())
+    }
+
+    async fn audits(&self) -> Result<Vec<AuditEntry>, Error> {
+        let mut audits: Vec<AuditEntry> = self
+            .scan(self.audit_table()?)
+            .await?
+            .into_iter()
+            .map(AuditEntry::from)
+            .collect();
+        sort_audits(&mut audits);
+        Ok(audits)
+    }
+
+    async fn query_audits(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, Error> {
+        let table = self.audit_table()?;
+
+        let items = match query {
+            // The packageIndex GSI is keyed by package name and then day
+            AuditQuery {
+                package: Some(package),
+                from,
+                to,
+                ..
+            } => {
+                let range = match (from, to) {
+                    (Some(_), Some(_)) => " and #timestamp BETWEEN :from AND :to",
+                    (Some(_), None) => " and #timestamp >= :from",
+                    (None, Some(_)) => " and #timestamp <= :to",
+                    (None, None) => "",
+                };
+
+                let mut request = self
+                    .client
+                    .query()
+                    .table_name(table)
+                    .index_name("packageIndex")
+                    .key_condition_expression(format!("package_name = :package{}", range))
+                    .expression_attribute_values(":package", AttributeValue::S(package.clone()));
+                if !range.is_empty() {
+                    request = request.expression_attribute_names("#timestamp", "timestamp");
+                }
+                for (name, value) in [(":from", from), (":to", to)] {
+                    if let Some(value) = value {
+                        request = request
+                            .expression_attribute_values(name, AttributeValue::S(value.clone()));
+                    }
+                }
+
+                self.query_all(request).await?
+            }
+            // The table itself is partitioned by day
+            AuditQuery {
+                from: Some(from),
+                to: Some(to),
+                ..
+            } => {
+                // A query per day, so long ranges such as the HTML report's are run side by side
+                let queries = days(from, to)?.into_iter().map(|day| {
+                    let request = self
+                        .client
+                        .query()
+                        .table_name(table)
+                        .key_condition_expression("#timestamp = :day")
+                        .expression_attribute_names("#timestamp", "timestamp")
+                        .expression_attribute_values(":day", AttributeValue::S(day));
+                    self.query_all(request)
+                });
+                let days: Vec<Vec<_>> = stream::iter(queries)
+                    .buffer_unordered(DAY_QUERY_CONCURRENCY)
+                    .try_collect()
+                    .await?;
+                days.into_iter().flatten().collect()
+            }
+            _ => self.scan(table).await?,
+        };
+
+        // Whatever the keys couldn't narrow down (such as the change type) is filtered here, as a
+        // filter expression would read the same items anyway
+        let mut audits: Vec<AuditEntry> = items
+            .into_iter()
+            .map(AuditEntry::from)
+            .filter(|audit| query.matches(audit))
+            .collect();
+        sort_audits(&mut audits);
+        Ok(audits)
+    }
+
+    async fn put_audit(&self, audit: &AuditEntry) -> Result<(), Error> {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(self.audit_table()?)
+            .item("timestamp", AttributeValue::S(audit.timestamp.clone()))
+            .item("package_name_id", AttributeValue::S(audit.key()))
+            .item(
+                "package_name",
+                AttributeValue::S(audit.package_name.clone()),
+            )
+            .item("change", AttributeValue::S(audit.change.clone()))
+            .item("old_value", AttributeValue::Bool(audit.old_value))
+            .item("new_value", AttributeValue::Bool(audit.new_value));
+
+        if let Some(seq) = audit.seq {
+            request = request.item("seq", AttributeValue::N(seq.to_string()));
+        }
+        for (name, value) in [
+            ("old_version", &audit.old_version),
+            ("new_version", &audit.new_version),
+            ("published", &audit.published),
+            ("message", &audit.message),
+        ] {
+            if let Some(value) = value {
+                request = request.item(name, AttributeValue::S(value.clone()));
+            }
+        }
+        for (name, status) in [
+            ("old_status", audit.old_status),
+            ("new_status", audit.new_status),
+        ] {
+            if let Some(status) = status {
+                request = request.item(name, AttributeValue::S(status.to_string()));
+            }
+        }
+        if !audit.manifest_diff.is_empty() {
+            request = request.item(
+                "manifest_diff",
+                AttributeValue::S(serde_json::to_string(&audit.manifest_diff)?),
+            );
+        }
+
+        request.send().await.map_err(store_err)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_year_months_across_a_year_boundary() {
+        assert_eq!(
+            year_months("2021-11-28", "2022-01-03").unwrap(),
+            vec!["2021-11", "2021-12", "2022-01"]
+        );
+        assert_eq!(
+            year_months("2022-01-03", "2022-01-10").unwrap(),
+            vec!["2022-01"]
+        );
+        assert!(year_months("2022-02-10", "2022-01-10").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_days_across_a_month_boundary() {
+        assert_eq!(
+            days("2022-01-30", "2022-02-01").unwrap(),
+            vec!["2022-01-30", "2022-01-31", "2022-02-01"]
+        );
+        assert!(days("2022-01-30", "not-a-date").is_err());
+    }
+}
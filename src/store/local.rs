@@ -0,0 +1,125 @@
+use super::Store;
+use crate::{AuditEntry, Error, Package, StatsEntry};
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// A store kept as JSON files in a local directory:
+///
+/// - `stats.json`: the stats of every run, keyed by day
+/// - `packages.json`: every package, keyed by name
+/// - `audits.jsonl`: one audit entry per line, in the order they were written
+pub struct LocalStore {
+    dir: PathBuf,
+    // Serializes the read-modify-write of the JSON files
+    lock: Mutex<()>,
+}
+
+impl LocalStore {
+    /// Opens the store in `dir`, creating the directory if it doesn't exist yet.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(LocalStore {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn read_map<T: serde::de::DeserializeOwned>(
+        &self,
+        file: &str,
+    ) -> Result<BTreeMap<String, T>, Error> {
+        match fs::read_to_string(self.dir.join(file)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_map<T: serde::Serialize>(
+        &self,
+        file: &str,
+        map: &BTreeMap<String, T>,
+    ) -> Result<(), Error> {
+        fs::write(self.dir.join(file), serde_json::to_string_pretty(map)?)?;
+        Ok(())
+    }
+
+    fn append<T: serde::Serialize>(&self, file: &str, value: &T) -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(file))?;
+        writeln!(f, "{}", serde_json::to_string(value)?)?;
+        Ok(())
+    }
+}
+
+fn read_lines<T: serde::de::DeserializeOwned>(path: &std::path::Path) -> Result<Vec<T>, Error> {
+    match fs::read_to_string(path) {
+        Ok(lines) => lines
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Error::from))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[async_trait]
+impl Store for LocalStore {
+    async fn put_stats(&self, stats: &StatsEntry) -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.read_map("stats.json")?;
+        entries.insert(stats.timestamp.clone(), stats.clone());
+        self.write_map("stats.json", &entries)
+    }
+
+    async fn stats_on(&self, date: &str) -> Result<Option<StatsEntry>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read_map("stats.json")?.remove(date))
+    }
+
+    async fn stats(&self) -> Result<Vec<StatsEntry>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        let entries: BTreeMap<String, StatsEntry> = self.read_map("stats.json")?;
+        Ok(entries.into_values().collect())
+    }
+
+    async fn stats_range(&self, from: &str, to: &str) -> Result<Vec<StatsEntry>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        let entries: BTreeMap<String, StatsEntry> = self.read_map("stats.json")?;
+        Ok(entries
+            .range(from.to_owned()..=to.to_owned())
+            .map(|(_, entry)| entry.clone())
+            .collect())
+    }
+
+    async fn packages(&self) -> Result<HashMap<String, Package>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read_map("packages.json")?.into_iter().collect())
+    }
+
+    async fn put_package(&self, pkg: &Package) -> Result<(), Error> {
+        let _guard = self.lock.lock().unwrap();
+        let mut packages = self.read_map("packages.json")?;
+        packages.insert(pkg.name.clone(), pkg.clone());
+        self.write_map("packages.json", &packages)
+    }
+
+    async fn audits(&self) -> Result<Vec<AuditEntry>, Error> {
+        read_lines(&self.dir.join("audits.jsonl"))
+    }
+
+    async fn put_audit(&self, audit: &AuditEntry) -> Result<(), Error> {
+        self.append("audits.jsonl", audit)
+    }
+}
@@ -0,0 +1,80 @@
+//! Where stats, package state and the audit log are persisted.
+//!
+//! The store is chosen with the `ESM_CHECKER_STORE` environment variable:
+//!
+//! - `dynamo` (the default): the DynamoDB tables provisioned by the CDK stack, named by the
+//!   `DYNAMO_*_TABLE_NAME` variables.
+//! - `local:<dir>`: JSON files in a local directory, handy for development and tests.
+
+use crate::{AuditEntry, AuditQuery, Error, Package, StatsEntry};
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+mod dynamo;
+mod local;
+
+pub use dynamo::{DynamoStore, DynamoTables};
+pub use local::LocalStore;
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Records the stats of a run, replacing any already recorded for the same day.
+    async fn put_stats(&self, stats: &StatsEntry) -> Result<(), Error>;
+
+    /// The stats recorded on `date` (`%Y-%m-%d`), if there was a run that day.
+    async fn stats_on(&self, date: &str) -> Result<Option<StatsEntry>, Error>;
+
+    /// Every entry ever recorded, oldest first.
+    async fn stats(&self) -> Result<Vec<StatsEntry>, Error>;
+
+    /// The most recently recorded entry. Stores which can read it without going through every
+    /// entry should.
+    async fn latest_stats(&self) -> Result<Option<StatsEntry>, Error> {
+        Ok(self.stats().await?.pop())
+    }
+
+    /// Every entry recorded between `from` and `to` (`%Y-%m-%d`, inclusive), oldest first.
+    async fn stats_range(&self, from: &str, to: &str) -> Result<Vec<StatsEntry>, Error>;
+
+    /// Every tracked package, keyed by name.
+    async fn packages(&self) -> Result<HashMap<String, Package>, Error>;
+
+    /// The tracked package called `name`. Stores which can look a single package up by its key
+    /// should, rather than reading every package.
+    async fn package(&self, name: &str) -> Result<Option<Package>, Error> {
+        Ok(self.packages().await?.remove(name))
+    }
+
+    /// Creates the row for `pkg`, or updates the classification of an existing one.
+    async fn put_package(&self, pkg: &Package) -> Result<(), Error>;
+
+    /// Every entry in the audit log, oldest first.
+    async fn audits(&self) -> Result<Vec<AuditEntry>, Error>;
+
+    /// The entries in the audit log matching `query`, oldest first. Stores which can narrow the
+    /// lookup with an index should, rather than filtering every entry.
+    async fn query_audits(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, Error> {
+        Ok(self
+            .audits()
+            .await?
+            .into_iter()
+            .filter(|audit| query.matches(audit))
+            .collect())
+    }
+
+    async fn put_audit(&self, audit: &AuditEntry) -> Result<(), Error>;
+}
+
+/// Opens the store selected by `ESM_CHECKER_STORE`.
+pub async fn from_env() -> Result<Box<dyn Store>, Error> {
+    let setting = std::env::var("ESM_CHECKER_STORE").unwrap_or_else(|_| String::from("dynamo"));
+
+    match setting.split_once(':') {
+        None if setting == "dynamo" => Ok(Box::new(DynamoStore::from_env().await?)),
+        Some(("local", dir)) => Ok(Box::new(LocalStore::open(dir)?)),
+        _ => Err(Error::Config(format!(
+            "unknown ESM_CHECKER_STORE `{}`, expected `dynamo` or `local:<dir>`",
+            setting
+        ))),
+    }
+}
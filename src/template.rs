@@ -0,0 +1,330 @@
+//! Report messages written from a user supplied [Handlebars](https://handlebarsjs.com) template
+//! rather than the built in layout, so each kind of channel can get its own wording.
+//!
+//! Templates are rendered against a [`ReportContext`]. Numbers are raw, so two helpers are
+//! registered to format them: `{{fixed value 2}}` prints `value` with 2 decimals and
+//! `{{signed value 2}}` does the same with a leading `+` or `-`. The number of decimals defaults
+//! to 0. Output isn't HTML escaped, and referring to a field which doesn't exist is an error.
+
+use crate::{
+    notify::{self, Message, Notifier, Trend},
+    report::TITLE,
+    AdoptionForecast, Error, NotableChange, Period, StatsDelta, StatsEntry, StatsReport,
+};
+use handlebars::{
+    no_escape, Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+};
+use serde::Serialize;
+use std::{collections::HashMap, path::Path};
+
+const TEMPLATE_NAME: &str = "report";
+
+/// One of the counts of a report, with how it moved.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricContext {
+    /// The name of the stats column, such as `type_module`.
+    pub name: &'static str,
+    pub count: isize,
+    /// The share of all packages, as a percentage.
+    pub percent: f64,
+    pub change: isize,
+    /// The change of the share, in percentage points.
+    pub pp: f64,
+    pub trend: Trend,
+    pub arrow: &'static str,
+}
+
+/// A notable change, along with how it reads in the built in report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NotableContext {
+    #[serde(flatten)]
+    pub change: NotableChange,
+    pub arrow: &'static str,
+    /// Such as `chalk 4.1.2 -> 5.0.0 (2022-01-18): cjs -> esm-only`.
+    pub description: String,
+}
+
+/// Everything a template can refer to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportContext {
+    pub title: String,
+    /// `day`, `week`, `month`, `quarter` or `custom`, if the report covers a period.
+    pub period: Option<Period>,
+    /// Such as `Weekly stats from 2022-01-13 to 2022-01-20`.
+    pub summary: String,
+    /// The day of the earlier run.
+    pub from: String,
+    /// The day of the latest run.
+    pub to: String,
+    pub current: StatsEntry,
+    pub diff: StatsDelta,
+    /// The current adoption percentage, see [`StatsEntry::adoption`].
+    pub adoption: f64,
+    /// The direction of adoption, `up`, `down` or `flat`.
+    pub trend: Trend,
+    pub arrow: &'static str,
+    /// `type_module`, `exports_require`, `exports_no_require`, `cjs`, `dual` and `esm_only`, in
+    /// that order.
+    pub metrics: Vec<MetricContext>,
+    /// The packages which went dual or ESM only, oldest first.
+    pub notable: Vec<NotableContext>,
+    /// Only set when a forecast was asked for, so guard it with `{{#if forecast}}`.
+    pub forecast: Option<AdoptionForecast>,
+    /// The forecast as written in the built in report.
+    pub forecast_text: Option<String>,
+}
+
+impl ReportContext {
+    pub fn new(report: &StatsReport) -> Self {
+        let (current, diff) = (&report.current, &report.diff);
+
+        let metric = |name, count, change, pp: f64| MetricContext {
+            name,
+            count,
+            percent: current.percent(count),
+            change,
+            pp,
+            trend: Trend::of(pp),
+            arrow: Trend::of(pp).arrow(),
+        };
+        let trend = Trend::of(diff.adoption_pp);
+
+        ReportContext {
+            title: TITLE.to_owned(),
+            period: report.period,
+            summary: report.summary(),
+            from: diff.since.clone(),
+            to: diff.timestamp.clone(),
+            current: current.clone(),
+            diff: diff.clone(),
+            adoption: current.adoption(),
+            trend,
+            arrow: trend.arrow(),
+            metrics: vec![
+                metric(
+                    "type_module",
+                    current.type_module,
+                    diff.type_module,
+                    diff.type_module_pp,
+                ),
+                metric(
+                    "exports_require",
+                    current.exports_require,
+                    diff.exports_require,
+                    diff.exports_require_pp,
+                ),
+                metric(
+                    "exports_no_require",
+                    current.exports_no_require,
+                    diff.exports_no_require,
+                    diff.exports_no_require_pp,
+                ),
+                metric("cjs", current.cjs, diff.cjs, diff.cjs_pp),
+                metric("dual", current.dual, diff.dual, diff.dual_pp),
+                metric(
+                    "esm_only",
+                    current.esm_only,
+                    diff.esm_only,
+                    diff.esm_only_pp,
+                ),
+            ],
+            notable: report
+                .notable
+                .iter()
+                .map(|change| NotableContext {
+                    change: change.clone(),
+                    arrow: change.trend().arrow(),
+                    description: change.to_string(),
+                })
+                .collect(),
+            forecast: report.forecast.clone(),
+            forecast_text: report.forecast.as_ref().map(|f| f.to_string()),
+        }
+    }
+}
+
+/// A parsed report template.
+#[derive(Debug)]
+pub struct ReportTemplate {
+    registry: Handlebars<'static>,
+}
+
+impl ReportTemplate {
+    /// Parses `source`, failing on invalid syntax.
+    pub fn new(source: &str) -> Result<Self, Error> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(no_escape);
+        registry.register_helper("fixed", Box::new(number_helper(false)));
+        registry.register_helper("signed", Box::new(number_helper(true)));
+        registry
+            .register_template_string(TEMPLATE_NAME, source)
+            .map_err(|e| Error::Template(e.to_string()))?;
+
+        Ok(ReportTemplate { registry })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("can't read template {}: {}", path.display(), e)))?;
+        ReportTemplate::new(&source)
+            .map_err(|e| Error::Template(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn render(&self, report: &StatsReport) -> Result<String, Error> {
+        self.registry
+            .render(TEMPLATE_NAME, &ReportContext::new(report))
+            .map_err(|e| Error::Template(e.to_string()))
+    }
+
+    /// The rendered template as a plain message, which every channel shows as is.
+    pub fn message(&self, report: &StatsReport) -> Result<Message, Error> {
+        let rendered = self.render(report)?;
+        Ok(Message::new(TITLE, rendered.trim_end()))
+    }
+}
+
+/// `{{fixed value digits}}` and `{{signed value digits}}`.
+fn number_helper(
+    signed: bool,
+) -> impl Fn(&Helper, &Handlebars, &Context, &mut RenderContext, &mut dyn Output) -> HelperResult
+       + Send
+       + Sync {
+    move |h, _, _, _, out| {
+        let value = h
+            .param(0)
+            .and_then(|p| p.value().as_f64())
+            .ok_or_else(|| RenderError::new(format!("{} expects a number", h.name())))?;
+        let digits = h.param(1).and_then(|p| p.value().as_u64()).unwrap_or(0) as usize;
+
+        let text = if signed {
+            format!("{:+.*}", digits, value)
+        } else {
+            format!("{:.*}", digits, value)
+        };
+        out.write(&text)?;
+        Ok(())
+    }
+}
+
+/// The templates to use for some kinds of channel, with an optional fallback for the others.
+/// Channels without one get the built in report.
+#[derive(Debug, Default)]
+pub struct ChannelTemplates {
+    default: Option<ReportTemplate>,
+    by_kind: HashMap<String, ReportTemplate>,
+}
+
+impl ChannelTemplates {
+    /// Parses `[<kind>=]<file>` arguments, where a file without a kind applies to every channel
+    /// which doesn't have its own.
+    pub fn from_args(args: &[String]) -> Result<Self, Error> {
+        let mut templates = ChannelTemplates::default();
+
+        for arg in args {
+            match arg.split_once('=') {
+                Some((kind, path)) if notify::KINDS.contains(&kind) => {
+                    templates
+                        .by_kind
+                        .insert(kind.to_owned(), ReportTemplate::from_file(path)?);
+                }
+                Some((kind, _)) if !kind.contains(['/', '\\', '.']) => {
+                    return Err(Error::Config(format!(
+                        "unknown channel kind `{}` in --template {}, expected one of {}",
+                        kind,
+                        arg,
+                        notify::KINDS.join(", ")
+                    )));
+                }
+                _ => {
+                    if templates.default.is_some() {
+                        return Err(Error::Config(String::from(
+                            "only one --template can apply to every channel",
+                        )));
+                    }
+                    templates.default = Some(ReportTemplate::from_file(arg)?);
+                }
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// The template for channels without one of their own.
+    pub fn fallback(&self) -> Option<&ReportTemplate> {
+        self.default.as_ref()
+    }
+
+    pub fn for_kind(&self, kind: &str) -> Option<&ReportTemplate> {
+        self.by_kind.get(kind).or(self.default.as_ref())
+    }
+
+    /// The message for each of `notifiers`, in the same order.
+    pub fn messages(
+        &self,
+        notifiers: &[Box<dyn Notifier>],
+        report: &StatsReport,
+    ) -> Result<Vec<Message>, Error> {
+        notifiers
+            .iter()
+            .map(|notifier| match self.for_kind(notifier.kind()) {
+                Some(template) => template.message(report),
+                None => Ok(report.message()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> StatsReport {
+        let stats = |timestamp: &str, dual: isize| StatsEntry {
+            timestamp: timestamp.to_owned(),
+            total_packages: 200,
+            exports_require: dual,
+            dual,
+            cjs: 200 - dual,
+            ..StatsEntry::default()
+        };
+        StatsReport::new(stats("2022-01-20", 13), &stats("2022-01-13", 10))
+    }
+
+    #[test]
+    fn test_render() {
+        let template = ReportTemplate::new(
+            "{{from}} to {{to}}: {{fixed adoption 1}}% ({{signed diff.adoption_pp 2}}pp) {{arrow}}\n{{#each metrics}}{{#if change}}{{name}} {{signed change}}\n{{/if}}{{/each}}{{#if forecast}}{{forecast_text}}{{/if}}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            template.render(&report()).unwrap(),
+            "2022-01-13 to 2022-01-20: 6.5% (+1.50pp) ▲\nexports_require +3\ncjs -3\ndual +3\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let template = ReportTemplate::new("{{adoptoin}}").unwrap();
+
+        assert!(matches!(
+            template.render(&report()),
+            Err(Error::Template(_))
+        ));
+        assert!(matches!(
+            ReportTemplate::new("{{#if adoption}}"),
+            Err(Error::Template(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_args() {
+        assert!(matches!(
+            ChannelTemplates::from_args(&[String::from("irc=weekly.hbs")]),
+            Err(Error::Config(message)) if message.contains("irc")
+        ));
+        assert!(ChannelTemplates::default().for_kind("slack").is_none());
+    }
+}
@@ -0,0 +1,395 @@
+//! Time series of the daily stats, for looking at how adoption moves over arbitrary date ranges.
+
+use crate::{store::Store, Error, StatsDelta, StatsEntry};
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// What to do about days in the range without any recorded stats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapPolicy {
+    /// Leave missing days out of the series, only listing them in [`StatsSeries::gaps`].
+    Flag,
+    /// Carry the previous day's stats forward into missing days, marking them as filled. Days
+    /// before the first recorded entry are still left out.
+    Fill,
+}
+
+/// The distance between the two entries compared by [`StatsSeries::deltas`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Day,
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// The day one interval before `date`. Months clamp to the last day of shorter months.
+    pub fn before(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => date - Duration::days(1),
+            Interval::Week => date - Duration::weeks(1),
+            Interval::Month => months_before(date, 1),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" | "daily" => Ok(Interval::Day),
+            "week" | "weekly" => Ok(Interval::Week),
+            "month" | "monthly" => Ok(Interval::Month),
+            _ => Err(Error::Config(format!(
+                "unknown interval `{}`, expected day, week or month",
+                s
+            ))),
+        }
+    }
+}
+
+/// `months` calendar months before `date`, clamping the day to the end of the target month.
+pub(crate) fn months_before(date: NaiveDate, months: u32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 - months as i32;
+    let (year, month0) = (total.div_euclid(12), total.rem_euclid(12) as u32);
+
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month0 + 1, day))
+        .expect("the first of every month is a valid date")
+}
+
+/// A single number tracked by the stats table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    TotalPackages,
+    TypeModule,
+    ExportsRequire,
+    ExportsNoRequire,
+    Cjs,
+    Dual,
+    EsmOnly,
+    /// The percentage of packages which are dual or ESM only.
+    Adoption,
+}
+
+impl Metric {
+    pub fn value(&self, entry: &StatsEntry) -> f64 {
+        match self {
+            Metric::TotalPackages => entry.total_packages as f64,
+            Metric::TypeModule => entry.type_module as f64,
+            Metric::ExportsRequire => entry.exports_require as f64,
+            Metric::ExportsNoRequire => entry.exports_no_require as f64,
+            Metric::Cjs => entry.cjs as f64,
+            Metric::Dual => entry.dual as f64,
+            Metric::EsmOnly => entry.esm_only as f64,
+            Metric::Adoption => entry.adoption(),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "total_packages" => Ok(Metric::TotalPackages),
+            "type_module" => Ok(Metric::TypeModule),
+            "exports_require" => Ok(Metric::ExportsRequire),
+            "exports_no_require" => Ok(Metric::ExportsNoRequire),
+            "cjs" => Ok(Metric::Cjs),
+            "dual" => Ok(Metric::Dual),
+            "esm_only" => Ok(Metric::EsmOnly),
+            "adoption" => Ok(Metric::Adoption),
+            _ => Err(Error::Config(format!("unknown metric `{}`", s))),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Metric::TotalPackages => "total_packages",
+            Metric::TypeModule => "type_module",
+            Metric::ExportsRequire => "exports_require",
+            Metric::ExportsNoRequire => "exports_no_require",
+            Metric::Cjs => "cjs",
+            Metric::Dual => "dual",
+            Metric::EsmOnly => "esm_only",
+            Metric::Adoption => "adoption",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeriesPoint {
+    pub date: NaiveDate,
+    pub entry: StatsEntry,
+    /// Whether the entry was carried forward from an earlier day by [`GapPolicy::Fill`].
+    pub filled: bool,
+}
+
+/// The daily stats between two dates, inclusive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsSeries {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// One point per day with stats, oldest first.
+    pub points: Vec<SeriesPoint>,
+    /// Days in the range without recorded stats, whether or not they were filled.
+    pub gaps: Vec<NaiveDate>,
+}
+
+impl StatsSeries {
+    pub fn from_entries(
+        from: NaiveDate,
+        to: NaiveDate,
+        entries: Vec<StatsEntry>,
+        gaps: GapPolicy,
+    ) -> Self {
+        let by_date: BTreeMap<NaiveDate, StatsEntry> = entries
+            .into_iter()
+            .filter_map(|e| Some((NaiveDate::parse_from_str(&e.timestamp, "%F").ok()?, e)))
+            .collect();
+
+        let mut series = StatsSeries {
+            from,
+            to,
+            points: vec![],
+            gaps: vec![],
+        };
+
+        let mut date = from;
+        while date <= to {
+            match by_date.get(&date) {
+                Some(entry) => series.points.push(SeriesPoint {
+                    date,
+                    entry: entry.clone(),
+                    filled: false,
+                }),
+                None => {
+                    series.gaps.push(date);
+                    if gaps == GapPolicy::Fill {
+                        if let Some(previous) = series.points.last() {
+                            let mut entry = previous.entry.clone();
+                            entry.timestamp = date.format("%F").to_string();
+                            series.points.push(SeriesPoint {
+                                date,
+                                entry,
+                                filled: true,
+                            });
+                        }
+                    }
+                }
+            }
+            date = date.succ();
+        }
+
+        series
+    }
+
+    pub fn get(&self, date: NaiveDate) -> Option<&SeriesPoint> {
+        self.points
+            .binary_search_by_key(&date, |p| p.date)
+            .ok()
+            .map(|i| &self.points[i])
+    }
+
+    pub fn first(&self) -> Option<&SeriesPoint> {
+        self.points.first()
+    }
+
+    pub fn latest(&self) -> Option<&SeriesPoint> {
+        self.points.last()
+    }
+
+    /// The change from one interval earlier for every point where both ends are in the series.
+    /// Changes from an [estimated](StatsEntry::estimated) entry to a recorded one are left out, as
+    /// the difference is mostly the switch in how packages were counted.
+    pub fn deltas(&self, interval: Interval) -> Vec<StatsDelta> {
+        self.points
+            .iter()
+            .filter_map(|point| {
+                let previous = self.get(interval.before(point.date))?;
+                if previous.entry.estimated != point.entry.estimated {
+                    return None;
+                }
+                Some(&point.entry - &previous.entry)
+            })
+            .collect()
+    }
+
+    /// The trailing average of `metric` over the `window` days up to and including each point, for
+    /// every point with a full window of days since the first point. Days without stats are left
+    /// out of the average rather than counted as zero.
+    pub fn moving_average(&self, metric: Metric, window: usize) -> Vec<(NaiveDate, f64)> {
+        let first = match self.points.first() {
+            Some(first) if window > 0 => first.date,
+            _ => return vec![],
+        };
+        let span = Duration::days(window as i64 - 1);
+
+        self.points
+            .iter()
+            .enumerate()
+            .filter(|(_, point)| point.date - span >= first)
+            .map(|(i, point)| {
+                let values: Vec<f64> = self.points[..=i]
+                    .iter()
+                    .rev()
+                    .take_while(|p| p.date >= point.date - span)
+                    .map(|p| metric.value(&p.entry))
+                    .collect();
+                (point.date, values.iter().sum::<f64>() / values.len() as f64)
+            })
+            .collect()
+    }
+}
+
+/// Reads the stats recorded between `from` and `to` (inclusive) into a series.
+pub async fn stats_series(
+    store: &dyn Store,
+    from: NaiveDate,
+    to: NaiveDate,
+    gaps: GapPolicy,
+) -> Result<StatsSeries, Error> {
+    let entries = store
+        .stats_range(&from.format("%F").to_string(), &to.format("%F").to_string())
+        .await?;
+    Ok(StatsSeries::from_entries(from, to, entries, gaps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(date: &str, total: isize, dual: isize, esm_only: isize) -> StatsEntry {
+        StatsEntry {
+            timestamp: date.to_owned(),
+            total_packages: total,
+            dual,
+            esm_only,
+            cjs: total - dual - esm_only,
+            ..StatsEntry::default()
+        }
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%F").unwrap()
+    }
+
+    #[test]
+    fn test_months_before() {
+        assert_eq!(months_before(date("2022-03-31"), 1), date("2022-02-28"));
+        assert_eq!(months_before(date("2022-01-15"), 1), date("2021-12-15"));
+        assert_eq!(months_before(date("2022-05-31"), 3), date("2022-02-28"));
+    }
+
+    #[test]
+    fn test_gaps_are_flagged_or_filled() {
+        let entries = vec![
+            entry("2022-01-30", 100, 10, 5),
+            entry("2022-02-02", 100, 12, 5),
+        ];
+
+        let flagged = StatsSeries::from_entries(
+            date("2022-01-29"),
+            date("2022-02-02"),
+            entries.clone(),
+            GapPolicy::Flag,
+        );
+        assert_eq!(flagged.points.len(), 2);
+        assert_eq!(
+            flagged.gaps,
+            vec![date("2022-01-29"), date("2022-01-31"), date("2022-02-01")]
+        );
+
+        let filled = StatsSeries::from_entries(
+            date("2022-01-29"),
+            date("2022-02-02"),
+            entries,
+            GapPolicy::Fill,
+        );
+        assert_eq!(filled.points.len(), 4);
+        assert_eq!(filled.gaps.len(), 3);
+        let carried = filled.get(date("2022-02-01")).unwrap();
+        assert!(carried.filled);
+        assert_eq!(carried.entry.dual, 10);
+        assert_eq!(carried.entry.timestamp, "2022-02-01");
+    }
+
+    #[test]
+    fn test_weekly_deltas_and_moving_average() {
+        let entries: Vec<StatsEntry> = (1..=8)
+            .map(|day| entry(&format!("2022-01-{:02}", day), 100, day, 0))
+            .collect();
+
+        let series = StatsSeries::from_entries(
+            date("2022-01-01"),
+            date("2022-01-08"),
+            entries,
+            GapPolicy::Flag,
+        );
+
+        let weekly = series.deltas(Interval::Week);
+        assert_eq!(weekly.len(), 1);
+        assert_eq!(weekly[0].since, "2022-01-01");
+        assert_eq!(weekly[0].dual, 7);
+        assert_eq!(weekly[0].adoption_pp, 7.0);
+
+        assert_eq!(series.deltas(Interval::Day).len(), 7);
+
+        let average = series.moving_average(Metric::Dual, 3);
+        assert_eq!(average.len(), 6);
+        assert_eq!(average[0], (date("2022-01-03"), 2.0));
+        assert_eq!(average[5], (date("2022-01-08"), 7.0));
+    }
+
+    #[test]
+    fn test_moving_average_spans_days_rather_than_points() {
+        let entries = vec![
+            entry("2022-01-01", 100, 1, 0),
+            entry("2022-01-02", 100, 2, 0),
+            entry("2022-01-05", 100, 5, 0),
+            entry("2022-01-06", 100, 6, 0),
+        ];
+
+        let series = StatsSeries::from_entries(
+            date("2022-01-01"),
+            date("2022-01-06"),
+            entries,
+            GapPolicy::Flag,
+        );
+
+        // 2022-01-02 has only a day before it, and the 3 days up to 2022-01-05 have no other stats
+        assert_eq!(
+            series.moving_average(Metric::Dual, 3),
+            vec![(date("2022-01-05"), 5.0), (date("2022-01-06"), 5.5)]
+        );
+    }
+
+    #[test]
+    fn test_no_deltas_across_estimated_entries() {
+        let mut estimated = entry("2022-01-01", 100, 10, 5);
+        estimated.estimated = true;
+        let entries = vec![
+            estimated,
+            entry("2022-01-02", 100, 10, 9),
+            entry("2022-01-03", 100, 11, 9),
+        ];
+
+        let series = StatsSeries::from_entries(
+            date("2022-01-01"),
+            date("2022-01-03"),
+            entries,
+            GapPolicy::Flag,
+        );
+
+        let daily = series.deltas(Interval::Day);
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].since, "2022-01-02");
+    }
+}
@@ -0,0 +1,277 @@
+//! Alerting as soon as a package someone depends on changes how it can be imported, rather than
+//! waiting for it to show up in the weekly numbers.
+
+use crate::{
+    notify::{self, notify_all, Message, Notifier, Trend},
+    read_package_list, AuditEntry, Error, Package, PackageStatus, Transition,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// `exports` maps can list hundreds of subpaths, so snippets are cut to this many characters to
+/// stay within the message limits of the channels.
+const SNIPPET_LIMIT: usize = 800;
+
+/// The packages to alert on, read from a whitespace separated list like `packages.txt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Watchlist {
+    packages: BTreeSet<String>,
+}
+
+impl Watchlist {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(packages: I) -> Self {
+        Watchlist {
+            packages: packages.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let packages = read_package_list(path)
+            .map_err(|e| Error::Config(format!("can't read the watchlist {}: {}", path, e)))?;
+        Ok(Watchlist::new(packages))
+    }
+
+    pub fn contains(&self, package: &str) -> bool {
+        self.packages.contains(package)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.packages.iter()
+    }
+
+    /// An alert for every watched package whose status changed in `audits`, one per check, oldest
+    /// first. The `exports` snippet is taken from `packages`, the packages as they were just
+    /// classified, falling back to the manifest diff of the audit entry.
+    pub fn alerts(
+        &self,
+        audits: &[AuditEntry],
+        packages: &HashMap<String, Package>,
+    ) -> Vec<WatchAlert> {
+        let mut alerts: Vec<WatchAlert> = vec![];
+
+        for audit in audits {
+            if !self.contains(&audit.package_name) {
+                continue;
+            }
+            let (old_status, new_status) = match (audit.old_status, audit.new_status) {
+                (Some(old), Some(new)) if old != new => (old, new),
+                _ => continue,
+            };
+
+            // The entries of one check share the timestamp and versions
+            let existing = alerts.iter_mut().find(|a| {
+                a.package_name == audit.package_name
+                    && a.timestamp == audit.timestamp
+                    && a.new_version == audit.new_version
+            });
+            if let Some(alert) = existing {
+                alert.changes.push(audit.change.clone());
+                continue;
+            }
+
+            let exports_diff = audit.manifest_diff.iter().find(|c| c.field == "exports");
+            let exports = match packages.get(&audit.package_name) {
+                Some(pkg) => pkg.manifest.exports.clone(),
+                None => exports_diff.and_then(|c| c.new.clone()),
+            };
+
+            alerts.push(WatchAlert {
+                package_name: audit.package_name.clone(),
+                timestamp: audit.timestamp.clone(),
+                old_status,
+                new_status,
+                old_version: audit.old_version.clone(),
+                new_version: audit.new_version.clone(),
+                transition: Transition::classify(old_status, new_status),
+                changes: vec![audit.change.clone()],
+                exports,
+                old_exports: exports_diff.and_then(|c| c.old.clone()),
+            });
+        }
+
+        alerts
+    }
+}
+
+/// A watched package which changed status.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WatchAlert {
+    pub package_name: String,
+    pub timestamp: String,
+    pub old_status: PackageStatus,
+    pub new_status: PackageStatus,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub transition: Transition,
+    /// The classification fields which changed.
+    pub changes: Vec<String>,
+    /// The `exports` field of the new version.
+    pub exports: Option<Value>,
+    /// The `exports` field of the old version, only when it changed and was recorded.
+    pub old_exports: Option<Value>,
+}
+
+impl fmt::Display for WatchAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = |v: &Option<String>| v.clone().unwrap_or_else(|| String::from("?"));
+
+        write!(
+            f,
+            "{} {} -> {} ({}): {} -> {} ({})",
+            self.package_name,
+            version(&self.old_version),
+            version(&self.new_version),
+            self.timestamp,
+            self.old_status,
+            self.new_status,
+            self.changes.join(", ")
+        )
+    }
+}
+
+impl WatchAlert {
+    /// The alert with the `exports` of the new version, and of the old one when it changed.
+    pub fn message(&self) -> Message {
+        let trend = match self.transition {
+            Transition::Adoption => Trend::Up,
+            Transition::Regression => Trend::Down,
+            Transition::Neutral => Trend::Flat,
+        };
+
+        let mut text = format!(
+            "{} {}\n\nexports:\n{}",
+            trend.arrow(),
+            self,
+            snippet(&self.exports)
+        );
+        if self.old_exports.is_some() {
+            text.push_str(&format!(
+                "\n\nexports before:\n{}",
+                snippet(&self.old_exports)
+            ));
+        }
+
+        Message::new("ESM Checker watchlist", text).trend(trend)
+    }
+}
+
+/// `exports` as indented JSON, cut at a line boundary once it gets past [`SNIPPET_LIMIT`].
+fn snippet(exports: &Option<Value>) -> String {
+    let exports = match exports {
+        Some(exports) => exports,
+        None => return String::from("(none)"),
+    };
+    let json = serde_json::to_string_pretty(exports).unwrap_or_default();
+    if json.chars().count() <= SNIPPET_LIMIT {
+        return json;
+    }
+
+    let mut cut = String::new();
+    for line in json.lines() {
+        if cut.chars().count() + line.chars().count() + 1 > SNIPPET_LIMIT {
+            break;
+        }
+        cut.push_str(line);
+        cut.push('\n');
+    }
+    cut.push('…');
+    cut
+}
+
+/// The channels in `WATCHLIST_NOTIFY`, written like `ESM_CHECKER_NOTIFY`, falling back to the
+/// channels the reports go to.
+pub fn watchlist_notifiers() -> Result<Vec<Box<dyn Notifier>>, Error> {
+    match std::env::var("WATCHLIST_NOTIFY") {
+        Ok(channels) => notify::from_channels(&channels),
+        Err(_) => notify::from_env(),
+    }
+}
+
+/// Sends each alert to every channel as its own message. Returns the failures along with the
+/// kind of channel.
+pub async fn send_watch_alerts(
+    notifiers: &[Box<dyn Notifier>],
+    alerts: &[WatchAlert],
+) -> Vec<(&'static str, Error)> {
+    let mut failures = vec![];
+    for alert in alerts {
+        failures.extend(notify_all(notifiers, &alert.message()).await);
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FieldChange, ManifestFields};
+    use serde_json::json;
+
+    fn audit(package_name: &str, change: &str, new: PackageStatus) -> AuditEntry {
+        AuditEntry {
+            package_name: package_name.to_owned(),
+            timestamp: String::from("2022-01-20"),
+            change: change.to_owned(),
+            old_version: Some(String::from("4.1.2")),
+            new_version: Some(String::from("5.0.0")),
+            old_status: Some(PackageStatus::Cjs),
+            new_status: Some(new),
+            manifest_diff: vec![FieldChange {
+                field: String::from("exports"),
+                old: None,
+                new: Some(json!("./source/index.js")),
+            }],
+            ..AuditEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_alerts() {
+        let watchlist = Watchlist::new(["chalk", "nanoid"]);
+        let audits = vec![
+            audit("chalk", "type_module", PackageStatus::EsmOnly),
+            audit("chalk", "exports_no_require", PackageStatus::EsmOnly),
+            audit("got", "type_module", PackageStatus::EsmOnly),
+            audit("nanoid", "exports_require", PackageStatus::Dual),
+        ];
+        let nanoid = Package {
+            name: String::from("nanoid"),
+            manifest: ManifestFields {
+                exports: Some(json!({ ".": { "import": "./index.js", "require": "./index.cjs" } })),
+                ..ManifestFields::default()
+            },
+            ..Package::default()
+        };
+
+        let alerts = watchlist.alerts(&audits, &HashMap::from([(String::from("nanoid"), nanoid)]));
+
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].changes, vec!["type_module", "exports_no_require"]);
+        assert_eq!(alerts[0].exports, Some(json!("./source/index.js")));
+        assert_eq!(alerts[1].transition, Transition::Adoption);
+        assert_eq!(
+            alerts[1].exports.as_ref().unwrap()["."]["require"],
+            "./index.cjs"
+        );
+        assert_eq!(
+            alerts[0].message().text,
+            "▲ chalk 4.1.2 -> 5.0.0 (2022-01-20): cjs -> esm-only (type_module, exports_no_require)\n\nexports:\n\"./source/index.js\""
+        );
+    }
+
+    #[test]
+    fn test_snippet() {
+        let subpaths: serde_json::Map<String, Value> = (0..100)
+            .map(|i| (format!("./{}", i), json!(format!("./dist/{}.js", i))))
+            .collect();
+
+        let cut = snippet(&Some(Value::Object(subpaths)));
+
+        assert!(cut.chars().count() <= SNIPPET_LIMIT + 1);
+        assert!(cut.ends_with("\n…"));
+        assert_eq!(snippet(&None), "(none)");
+    }
+}
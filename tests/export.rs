@@ -0,0 +1,129 @@
+mod support;
+
+use esm_checker::{
+    store::{LocalStore, Store},
+    AuditEntry, Format, Inactive, ManifestFields, Package, Snapshot, StatsEntry,
+};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use std::fs;
+use support::temp_dir;
+
+async fn seeded_store(dir: &std::path::Path) -> LocalStore {
+    let store = LocalStore::open(dir.join("store")).unwrap();
+
+    for (timestamp, dual) in [("2022-01-02", 3), ("2022-01-01", 2)] {
+        store
+            .put_stats(&StatsEntry {
+                timestamp: String::from(timestamp),
+                total_packages: 10,
+                dual,
+                cjs: 10 - dual,
+                ..StatsEntry::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    store
+        .put_package(&Package {
+            name: String::from("@scope/dual"),
+            exports_require: true,
+            version: Some(String::from("1.1.0")),
+            manifest: ManifestFields {
+                exports: Some(serde_json::json!({ "require": "./index.js" })),
+                ..ManifestFields::default()
+            },
+            ..Package::default()
+        })
+        .await
+        .unwrap();
+    store
+        .put_package(&Package {
+            name: String::from("cjs-only"),
+            inactive: Some(Inactive::Removed),
+            ..Package::default()
+        })
+        .await
+        .unwrap();
+
+    store
+        .put_audit(&AuditEntry {
+            package_name: String::from("@scope/dual"),
+            timestamp: String::from("2022-01-02"),
+            change: String::from("exports_require"),
+            old_value: false,
+            new_value: true,
+            seq: Some(42),
+            ..AuditEntry::default()
+        })
+        .await
+        .unwrap();
+
+    store
+}
+
+#[tokio::test]
+async fn exports_every_table_in_every_format() {
+    let dir = temp_dir("export");
+    let store = seeded_store(&dir).await;
+    let out = dir.join("out");
+
+    let snapshot = Snapshot::from_store(&store).await.unwrap();
+    for format in Format::ALL {
+        snapshot.write(&out, format).unwrap();
+    }
+
+    assert_eq!(
+        fs::read_to_string(out.join("stats.csv")).unwrap(),
+        "timestamp,total_packages,failures,type_module,exports_require,exports_no_require,cjs,dual,esm_only,estimated\n\
+         2022-01-01,10,0,0,0,0,8,2,0,false\n\
+         2022-01-02,10,0,0,0,0,7,3,0,false\n"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("packages.csv")).unwrap(),
+        "package_name,exports_require,exports_no_require,type_module,status,version,published,manifest,deprecated,inactive\n\
+         @scope/dual,true,false,false,dual,1.1.0,,\"{\"\"exports\"\":{\"\"require\"\":\"\"./index.js\"\"}}\",,\n\
+         cjs-only,false,false,false,cjs,,,{},,removed\n"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("audits.csv")).unwrap(),
+        "package_name_id,package_name,timestamp,change,old_value,new_value,seq,old_version,new_version,published,manifest_diff,old_status,new_status,transition,message\n\
+         ,@scope/dual,2022-01-02,exports_require,false,true,42,,,,[],,,,\n"
+    );
+
+    let audits = fs::read_to_string(out.join("audits.jsonl")).unwrap();
+    let audit: serde_json::Value = serde_json::from_str(audits.lines().next().unwrap()).unwrap();
+    assert_eq!(audit["package_name_id"], serde_json::Value::Null);
+    assert_eq!(audit["seq"], 42);
+    let packages = fs::read_to_string(out.join("packages.jsonl")).unwrap();
+    let package: serde_json::Value =
+        serde_json::from_str(packages.lines().next().unwrap()).unwrap();
+    assert_eq!(package["manifest"]["exports"]["require"], "./index.js");
+    assert_eq!(
+        fs::read_to_string(out.join("packages.jsonl"))
+            .unwrap()
+            .lines()
+            .count(),
+        2
+    );
+
+    let reader =
+        SerializedFileReader::new(fs::File::open(out.join("stats.parquet")).unwrap()).unwrap();
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows(), 2);
+    assert_eq!(metadata.schema_descr().num_columns(), 10);
+    let first = reader.get_row_iter(None).unwrap().next().unwrap();
+    assert_eq!(
+        first.to_string(),
+        "{timestamp: \"2022-01-01\", total_packages: 10, failures: 0, type_module: 0, exports_require: 0, exports_no_require: 0, cjs: 8, dual: 2, esm_only: 0, estimated: false}"
+    );
+
+    // Missing optional values stay null rather than being defaulted
+    let reader =
+        SerializedFileReader::new(fs::File::open(out.join("audits.parquet")).unwrap()).unwrap();
+    let audit = reader.get_row_iter(None).unwrap().next().unwrap();
+    assert_eq!(
+        audit.to_string(),
+        "{package_name_id: null, package_name: \"@scope/dual\", timestamp: \"2022-01-02\", change: \"exports_require\", old_value: false, new_value: true, seq: 42, old_version: null, new_version: null, published: null, manifest_diff: \"[]\", old_status: null, new_status: null, transition: null, message: null}"
+    );
+}
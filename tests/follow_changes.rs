@@ -0,0 +1,265 @@
+mod support;
+
+use esm_checker::{
+    follow_changes,
+    store::{LocalStore, Store},
+    Feed, FollowCheckpoint, FollowOptions, Inactive, Package, Source,
+};
+use std::collections::HashSet;
+use support::{fixtures_dir, temp_dir, MockRegistry};
+
+fn tracked() -> HashSet<String> {
+    ["cjs-only", "dual-mode", "esm-only"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect()
+}
+
+async fn seeded_store(dir: &std::path::Path) -> LocalStore {
+    let store = LocalStore::open(dir.join("store")).unwrap();
+    // dual-mode was CommonJS only the last time it was checked
+    store
+        .put_package(&Package {
+            name: String::from("dual-mode"),
+            ..Package::default()
+        })
+        .await
+        .unwrap();
+    store
+        .put_package(&Package {
+            name: String::from("esm-only"),
+            type_module: true,
+            exports_no_require: true,
+            exports_require: false,
+            ..Package::default()
+        })
+        .await
+        .unwrap();
+    store
+}
+
+#[tokio::test]
+async fn rechecks_tracked_packages_from_a_recorded_feed() {
+    let registry = MockRegistry::start().await;
+    let dir = temp_dir("follow");
+    let store = seeded_store(&dir).await;
+    let checkpoint = dir.join("checkpoint.json");
+
+    let options = FollowOptions::new(Feed::File(fixtures_dir().join("changes.json")), &checkpoint)
+        .source(Source::Registry(registry.url().to_owned()));
+
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.changes, 5);
+    assert_eq!(summary.rechecked, 2);
+    assert_eq!(summary.since, 105);
+
+    let mut requests = registry.requests();
+    requests.sort();
+    // The registry only says when a version was published in the whole document, which is
+    // fetched for dual-mode alone as the only package with a change to record
+    assert_eq!(
+        requests,
+        vec!["/dual-mode", "/dual-mode/latest", "/esm-only/latest"]
+    );
+
+    // Only dual-mode changed, and the audit points at its latest publish
+    assert_eq!(summary.audits.len(), 1);
+    let audit = &summary.audits[0];
+    assert_eq!(audit.package_name, "dual-mode");
+    assert_eq!(audit.change, "exports_require");
+    assert_eq!(audit.seq, Some(104));
+    assert_eq!(audit.new_version.as_deref(), Some("2.0.0"));
+    assert_eq!(audit.published.as_deref(), Some("2022-01-18T11:15:00.000Z"));
+    assert_eq!(store.audits().await.unwrap(), summary.audits);
+    let dual = &store.packages().await.unwrap()["dual-mode"];
+    assert!(dual.exports_require);
+    assert_eq!(dual.published, audit.published);
+
+    assert_eq!(
+        FollowCheckpoint::load(&checkpoint).unwrap(),
+        Some(FollowCheckpoint {
+            since: 105,
+            ..FollowCheckpoint::default()
+        })
+    );
+
+    // Caught up, so a second run has nothing to do
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+    assert_eq!(summary.changes, 0);
+    assert_eq!(registry.requests().len(), 3);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn starts_from_the_current_sequence_without_a_checkpoint() {
+    let registry = MockRegistry::start().await;
+    let dir = temp_dir("follow-first-run");
+    let store = seeded_store(&dir).await;
+    let checkpoint = dir.join("checkpoint.json");
+
+    let options = FollowOptions::new(Feed::Http(registry.url().to_owned()), &checkpoint)
+        .source(Source::Registry(registry.url().to_owned()));
+
+    // The changes already in the feed happened before following started
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.changes, 0);
+    assert_eq!(summary.since, 105);
+    assert_eq!(
+        registry.requests(),
+        vec!["/", "/_changes?since=105&limit=1000"]
+    );
+    assert_eq!(
+        FollowCheckpoint::load(&checkpoint).unwrap().unwrap().since,
+        105
+    );
+
+    // Once there is a checkpoint, it's followed from there
+    FollowCheckpoint {
+        since: 103,
+        ..FollowCheckpoint::default()
+    }
+    .save(&checkpoint)
+    .unwrap();
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.changes, 2);
+    assert_eq!(summary.audits.len(), 1);
+    assert_eq!(summary.audits[0].seq, Some(104));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn checkpoints_between_batches() {
+    let registry = MockRegistry::start().await;
+    let dir = temp_dir("follow-batches");
+    let store = seeded_store(&dir).await;
+    let checkpoint = dir.join("checkpoint.json");
+
+    FollowCheckpoint {
+        since: 102,
+        ..FollowCheckpoint::default()
+    }
+    .save(&checkpoint)
+    .unwrap();
+
+    let options = FollowOptions::new(Feed::File(fixtures_dir().join("changes.json")), &checkpoint)
+        .source(Source::Registry(registry.url().to_owned()))
+        .batch_size(2);
+
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.changes, 3);
+    assert_eq!(summary.rechecked, 2);
+    assert_eq!(summary.audits.len(), 1);
+    assert_eq!(summary.audits[0].seq, Some(104));
+    assert_eq!(summary.since, 105);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn retries_packages_which_failed_to_fetch() {
+    let registry = MockRegistry::builder().fail("dual-mode", 2).start().await;
+    let dir = temp_dir("follow-retry");
+    let store = seeded_store(&dir).await;
+    let checkpoint = dir.join("checkpoint.json");
+
+    let options = FollowOptions::new(Feed::File(fixtures_dir().join("changes.json")), &checkpoint)
+        .source(Source::Registry(registry.url().to_owned()));
+
+    // Fails while reading the feed and again once caught up, so it's left for the next run
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.since, 105);
+    assert_eq!(summary.rechecked, 1);
+    assert!(summary.audits.is_empty());
+    assert_eq!(summary.retry, vec!["dual-mode"]);
+    let saved = FollowCheckpoint::load(&checkpoint).unwrap().unwrap();
+    assert_eq!(saved.retry.get("dual-mode"), Some(&104));
+    assert!(!store.packages().await.unwrap()["dual-mode"].exports_require);
+
+    // The feed has nothing new, but the failed change is still applied
+    let summary = follow_changes(&store, &tracked(), &options).await.unwrap();
+
+    assert_eq!(summary.changes, 0);
+    assert_eq!(summary.rechecked, 1);
+    assert!(summary.retry.is_empty());
+    assert_eq!(summary.audits.len(), 1);
+    assert_eq!(summary.audits[0].seq, Some(104));
+    assert!(store.packages().await.unwrap()["dual-mode"].exports_require);
+    assert!(FollowCheckpoint::load(&checkpoint)
+        .unwrap()
+        .unwrap()
+        .retry
+        .is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn marks_deleted_and_missing_packages_unpublished() {
+    let registry = MockRegistry::start().await;
+    let dir = temp_dir("follow-unpublished");
+    let store = seeded_store(&dir).await;
+    store
+        .put_package(&Package {
+            name: String::from("gone"),
+            ..Package::default()
+        })
+        .await
+        .unwrap();
+    let checkpoint = dir.join("checkpoint.json");
+    let feed = dir.join("changes.json");
+    let mut tracked = tracked();
+    tracked.insert(String::from("gone"));
+
+    // esm-only is deleted from the feed, while gone is still listed but the registry 404s
+    std::fs::write(
+        &feed,
+        r#"{"results": [
+            {"seq": 201, "id": "esm-only", "deleted": true},
+            {"seq": 202, "id": "gone"}
+        ], "last_seq": 202}"#,
+    )
+    .unwrap();
+    let options = FollowOptions::new(Feed::File(feed.clone()), &checkpoint)
+        .source(Source::Registry(registry.url().to_owned()));
+
+    let summary = follow_changes(&store, &tracked, &options).await.unwrap();
+
+    assert_eq!(registry.requests(), vec!["/gone/latest"]);
+    let mut audits = summary.audits.clone();
+    audits.sort_by_key(|a| a.seq);
+    assert_eq!(audits.len(), 2);
+    assert_eq!(audits[0].package_name, "esm-only");
+    assert_eq!(audits[0].change, "unpublished");
+    assert_eq!(audits[0].seq, Some(201));
+    assert_eq!(audits[1].package_name, "gone");
+    assert_eq!(audits[1].seq, Some(202));
+
+    let packages = store.packages().await.unwrap();
+    assert_eq!(packages["esm-only"].inactive, Some(Inactive::Unpublished));
+    // The classification is kept alongside the inactive flag
+    assert!(packages["esm-only"].type_module);
+    assert_eq!(packages["gone"].inactive, Some(Inactive::Unpublished));
+
+    // Republishing makes the package active again
+    std::fs::write(
+        &feed,
+        r#"{"results": [{"seq": 203, "id": "esm-only"}], "last_seq": 203}"#,
+    )
+    .unwrap();
+
+    let summary = follow_changes(&store, &tracked, &options).await.unwrap();
+
+    assert_eq!(summary.audits.len(), 1);
+    assert_eq!(summary.audits[0].change, "unpublished");
+    assert!(!summary.audits[0].new_value);
+    assert_eq!(store.packages().await.unwrap()["esm-only"].inactive, None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,293 @@
+mod support;
+
+use esm_checker::{
+    generate_packages, stream_packages, Cache, CheckOptions, Error, Package, Source,
+};
+use futures::StreamExt;
+use std::time::Duration;
+use support::MockRegistry;
+
+fn options(registry: &MockRegistry, list: &[&str]) -> CheckOptions {
+    CheckOptions::new(list.iter().copied()).source(Source::Unpkg(registry.url().to_owned()))
+}
+
+fn find<'a>(pkgs: &'a [Package], name: &str) -> &'a Package {
+    pkgs.iter()
+        .find(|p| p.name == name)
+        .unwrap_or_else(|| panic!("expected {} to be classified", name))
+}
+
+#[tokio::test]
+async fn classifies_fixture_packages() {
+    let registry = MockRegistry::start().await;
+
+    let pkgs = generate_packages(&options(
+        &registry,
+        &["cjs-only", "dual-mode", "esm-only", "type-module"],
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(pkgs.len(), 4);
+
+    let cjs = find(&pkgs, "cjs-only");
+    assert!(!cjs.has_values());
+
+    let dual = find(&pkgs, "dual-mode");
+    assert!(dual.exports_require);
+    assert!(!dual.exports_no_require);
+    assert!(!dual.type_module);
+
+    let esm = find(&pkgs, "esm-only");
+    assert!(esm.type_module);
+    assert!(esm.exports_no_require);
+    assert!(!esm.exports_require);
+
+    let type_module = find(&pkgs, "type-module");
+    assert!(type_module.type_module);
+    assert!(!type_module.exports_require);
+    assert!(!type_module.exports_no_require);
+}
+
+#[tokio::test]
+async fn skips_missing_and_invalid_packages() {
+    let registry = MockRegistry::start().await;
+
+    let pkgs = generate_packages(&options(
+        &registry,
+        &["dual-mode", "does-not-exist", "malformed", "nameless"],
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(pkgs.len(), 1);
+    assert_eq!(pkgs[0].name, "dual-mode");
+    assert_eq!(registry.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn follows_redirects() {
+    let registry = MockRegistry::builder()
+        .redirect("esm-only-alias", "esm-only")
+        .start()
+        .await;
+
+    let pkgs = generate_packages(&options(&registry, &["esm-only-alias"]))
+        .await
+        .unwrap();
+
+    assert_eq!(pkgs.len(), 1);
+    assert_eq!(pkgs[0].name, "esm-only");
+    assert!(pkgs[0].exports_no_require);
+    assert_eq!(
+        registry.requests(),
+        vec![
+            "/esm-only-alias@latest/package.json",
+            "/esm-only@latest/package.json"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn waits_for_slow_responses() {
+    let registry = MockRegistry::builder()
+        .delay("cjs-only", Duration::from_millis(300))
+        .start()
+        .await;
+
+    let pkgs = generate_packages(&options(&registry, &["cjs-only", "esm-only"]))
+        .await
+        .unwrap();
+
+    assert_eq!(pkgs.len(), 2);
+    // The slow package is still classified, it just finishes last.
+    assert_eq!(pkgs[1].name, "cjs-only");
+}
+
+#[tokio::test]
+async fn handles_more_packages_than_the_concurrency_limit() {
+    let registry = MockRegistry::start().await;
+
+    let package_list: Vec<String> = ["cjs-only", "dual-mode", "esm-only", "type-module"]
+        .iter()
+        .cycle()
+        .take(100)
+        .map(|n| n.to_string())
+        .collect();
+
+    let options = CheckOptions::new(package_list)
+        .concurrency(8)
+        .source(Source::Unpkg(registry.url().to_owned()));
+    let pkgs = generate_packages(&options).await.unwrap();
+
+    assert_eq!(pkgs.len(), 100);
+    assert_eq!(pkgs.iter().filter(|p| p.exports_require).count(), 25);
+    assert_eq!(pkgs.iter().filter(|p| p.exports_no_require).count(), 25);
+    assert_eq!(pkgs.iter().filter(|p| p.type_module).count(), 50);
+    assert_eq!(registry.requests().len(), 100);
+}
+
+#[tokio::test]
+async fn respects_the_limit() {
+    let registry = MockRegistry::start().await;
+
+    let options = options(&registry, &["cjs-only", "dual-mode", "esm-only"]).limit(2);
+    let pkgs = generate_packages(&options).await.unwrap();
+
+    assert_eq!(pkgs.len(), 2);
+    assert_eq!(registry.requests().len(), 2);
+    assert!(pkgs.iter().all(|p| p.name != "esm-only"));
+}
+
+#[tokio::test]
+async fn fetches_from_a_registry_source() {
+    let registry = MockRegistry::start().await;
+
+    let options = CheckOptions::new(["dual-mode", "esm-only@5.0.0"])
+        .source(Source::Registry(registry.url().to_owned()));
+    let pkgs = generate_packages(&options).await.unwrap();
+
+    assert_eq!(pkgs.len(), 2);
+    let mut requests = registry.requests();
+    requests.sort();
+    assert_eq!(requests, vec!["/dual-mode/latest", "/esm-only/5.0.0"]);
+}
+
+#[tokio::test]
+async fn records_the_version_publish_time_and_manifest_fields() {
+    let registry = MockRegistry::start().await;
+
+    let options = CheckOptions::new(["versioned@next", "esm-only"])
+        .source(Source::Replicate(registry.url().to_owned()));
+    let pkgs = generate_packages(&options).await.unwrap();
+
+    let versioned = find(&pkgs, "versioned");
+    assert_eq!(versioned.version.as_deref(), Some("3.0.0-beta.1"));
+    assert_eq!(
+        versioned.published.as_deref(),
+        Some("2022-01-10T18:45:00.000Z")
+    );
+    assert_eq!(
+        versioned.manifest.package_type,
+        Some(serde_json::json!("module"))
+    );
+    assert_eq!(
+        versioned.manifest.exports,
+        Some(serde_json::json!("./index.js"))
+    );
+    assert_eq!(versioned.manifest.main, None);
+
+    // Single version documents don't say when they were published
+    let esm = find(&pkgs, "esm-only");
+    assert_eq!(esm.version.as_deref(), Some("5.0.0"));
+    assert_eq!(esm.published, None);
+}
+
+#[tokio::test]
+async fn records_deprecations_and_unpublished_packages() {
+    let registry = MockRegistry::start().await;
+
+    let results: Vec<_> = stream_packages(
+        &CheckOptions::new(["deprecated", "unpublished", "dual-mode"])
+            .source(Source::Replicate(registry.url().to_owned())),
+    )
+    .collect()
+    .await;
+
+    let mut reports: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    reports.sort_by(|a, b| a.package.name.cmp(&b.package.name));
+    assert_eq!(reports.len(), 2);
+    assert_eq!(
+        reports[0].package.deprecated.as_deref(),
+        Some("This package is no longer maintained, use dual-mode instead")
+    );
+    assert_eq!(reports[1].package.deprecated, None);
+
+    // The package.json on the CDN is the published one, which npm never adds deprecations to
+    let registry_pkgs = generate_packages(
+        &CheckOptions::new(["deprecated"]).source(Source::Registry(registry.url().to_owned())),
+    )
+    .await
+    .unwrap();
+    assert!(registry_pkgs[0].deprecated.is_some());
+    let unpkg_pkgs = generate_packages(&options(&registry, &["deprecated"]))
+        .await
+        .unwrap();
+    assert_eq!(unpkg_pkgs[0].deprecated, None);
+
+    // The document of an unpublished package is still served, but has no versions
+    let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], Error::Unpublished { package } if package == "unpublished"));
+}
+
+#[tokio::test]
+async fn reuses_cached_manifests() {
+    let registry = MockRegistry::start().await;
+    let cache_dir = std::env::temp_dir().join(format!("esm-checker-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let options = options(&registry, &["dual-mode", "does-not-exist"]).cache(Cache::Directory {
+        path: cache_dir.clone(),
+        max_age: None,
+    });
+
+    let first = generate_packages(&options).await.unwrap();
+    let second = generate_packages(&options).await.unwrap();
+
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0].name, "dual-mode");
+    // Failed lookups aren't cached, so only the missing package is requested twice.
+    assert_eq!(registry.requests().len(), 3);
+    assert!(cache_dir.join("dual-mode@latest.json").exists());
+
+    std::fs::remove_dir_all(cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn streams_a_result_per_package() {
+    let registry = MockRegistry::start().await;
+
+    let results: Vec<_> = stream_packages(&options(
+        &registry,
+        &["dual-mode", "does-not-exist", "malformed", "esm-only"],
+    ))
+    .collect()
+    .await;
+
+    assert_eq!(results.len(), 4);
+
+    let mut reports: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    reports.sort_by(|a, b| a.package.name.cmp(&b.package.name));
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].package.name, "dual-mode");
+    assert_eq!(reports[0].version.as_deref(), Some("2.0.0"));
+    assert_eq!(reports[1].spec.name, "esm-only");
+    assert_eq!(reports[1].version.as_deref(), Some("5.0.0"));
+
+    let errors: Vec<_> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+    assert_eq!(errors.len(), 2);
+    assert!(errors
+        .iter()
+        .any(|e| matches!(e, Error::Unpublished { package } if package == "does-not-exist")));
+    assert!(errors
+        .iter()
+        .any(|e| matches!(e, Error::InvalidManifest { package, .. } if package == "malformed")));
+}
+
+#[tokio::test]
+async fn yields_fast_packages_before_slow_ones() {
+    let registry = MockRegistry::builder()
+        .delay("cjs-only", Duration::from_millis(300))
+        .start()
+        .await;
+
+    let mut reports = stream_packages(&options(&registry, &["cjs-only", "esm-only"]));
+
+    let first = reports.next().await.unwrap().unwrap();
+    assert_eq!(first.package.name, "esm-only");
+    let second = reports.next().await.unwrap().unwrap();
+    assert_eq!(second.package.name, "cjs-only");
+    assert!(reports.next().await.is_none());
+}
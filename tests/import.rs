@@ -0,0 +1,130 @@
+mod support;
+
+use esm_checker::{
+    import_snapshot,
+    store::{LocalStore, Store},
+    AuditEntry, Error, FieldChange, Format, ManifestFields, Package, Snapshot, StatsEntry,
+    TableSummary,
+};
+use std::fs;
+use support::temp_dir;
+
+fn snapshot() -> Snapshot {
+    Snapshot {
+        stats: vec![StatsEntry {
+            timestamp: String::from("2022-01-01"),
+            total_packages: 10,
+            dual: 2,
+            cjs: 8,
+            ..StatsEntry::default()
+        }],
+        packages: vec![Package {
+            name: String::from("@scope/dual"),
+            exports_require: true,
+            version: Some(String::from("1.1.0")),
+            manifest: ManifestFields {
+                exports: Some(
+                    serde_json::json!({ "import": "./index.mjs", "require": "./index.js" }),
+                ),
+                ..ManifestFields::default()
+            },
+            ..Package::default()
+        }],
+        audits: vec![AuditEntry {
+            package_name: String::from("@scope/dual"),
+            timestamp: String::from("2022-01-01"),
+            change: String::from("exports_require"),
+            old_value: false,
+            new_value: true,
+            package_name_id: Some(String::from("@scope/dual0123")),
+            old_version: Some(String::from("1.0.0")),
+            new_version: Some(String::from("1.1.0")),
+            manifest_diff: vec![FieldChange {
+                field: String::from("exports"),
+                old: None,
+                new: Some(serde_json::json!({ "import": "./index.mjs", "require": "./index.js" })),
+            }],
+            ..AuditEntry::default()
+        }],
+    }
+}
+
+#[tokio::test]
+async fn restores_an_export_into_an_empty_store() {
+    let dir = temp_dir("import");
+    snapshot()
+        .write(&dir.join("export"), Format::Jsonl)
+        .unwrap();
+
+    let read = Snapshot::read(&dir.join("export")).unwrap();
+    assert_eq!(read, snapshot());
+
+    let store = LocalStore::open(dir.join("store")).unwrap();
+    let summary = import_snapshot(&store, &read, false).await.unwrap();
+    let written = TableSummary {
+        read: 1,
+        written: 1,
+        ..TableSummary::default()
+    };
+    assert_eq!(summary.stats, written);
+    assert_eq!(summary.packages, written);
+    assert_eq!(summary.audits, written);
+    assert_eq!(Snapshot::from_store(&store).await.unwrap(), snapshot());
+
+    // Importing the same snapshot again doesn't write anything
+    let summary = import_snapshot(&store, &read, false).await.unwrap();
+    assert_eq!(summary.audits.existing, 1);
+    assert_eq!(summary.audits.written, 0);
+    assert_eq!(store.audits().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn dedupes_rows_within_a_snapshot() {
+    let dir = temp_dir("import-dedupe");
+    let mut duplicated = snapshot();
+    duplicated.audits.push(duplicated.audits[0].clone());
+    duplicated.stats.push(StatsEntry {
+        dual: 3,
+        cjs: 7,
+        ..duplicated.stats[0].clone()
+    });
+
+    let store = LocalStore::open(dir.join("store")).unwrap();
+    let summary = import_snapshot(&store, &duplicated, false).await.unwrap();
+
+    assert_eq!(summary.stats.duplicates, 1);
+    assert_eq!(summary.audits.duplicates, 1);
+    assert_eq!(store.audits().await.unwrap().len(), 1);
+    // The later of the duplicated rows wins
+    assert_eq!(store.stats_on("2022-01-01").await.unwrap().unwrap().dual, 3);
+}
+
+#[tokio::test]
+async fn dry_runs_leave_the_store_untouched() {
+    let dir = temp_dir("import-dry-run");
+    let store = LocalStore::open(dir.join("store")).unwrap();
+
+    let summary = import_snapshot(&store, &snapshot(), true).await.unwrap();
+
+    assert_eq!(summary.packages.written, 1);
+    assert!(store.packages().await.unwrap().is_empty());
+}
+
+#[test]
+fn rejects_rows_which_dont_match_the_schema() {
+    let dir = temp_dir("import-invalid");
+    fs::write(
+        dir.join("stats.jsonl"),
+        "{\"timestamp\": \"2022-01-01\", \"total_packages\": 10, \"failures\": 0, \"type_module\": 0, \"exports_require\": 0, \"exports_no_require\": 0, \"cjs\": 10, \"dual\": 0, \"esm_only\": 0}\n\
+         {\"timestamp\": \"2022-01-02\", \"total_packages\": \"ten\"}\n",
+    )
+    .unwrap();
+
+    match Snapshot::read(&dir) {
+        Err(Error::InvalidRecord { line, reason, .. }) => {
+            assert_eq!(line, 2);
+            assert!(reason.contains("total_packages"), "{}", reason);
+        }
+        other => panic!("expected an invalid record, got {:?}", other),
+    }
+}
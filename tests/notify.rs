@@ -0,0 +1,318 @@
+mod support;
+
+use esm_checker::{
+    notify::{
+        notify_all, notify_each, Discord, Email, Matrix, Message, Notifier, Slack, SmtpConfig,
+        SmtpTls, Teams, Webhook,
+    },
+    AuditEntry, ChannelTemplates, Error, PackageStatus, StatsEntry, StatsReport,
+};
+use support::{fixtures_dir, MockSmtp, MockWebhook};
+
+fn message() -> Message {
+    Message::new(
+        "ESM Checker",
+        "Stats for 2022-01-20\ntype_module: +3 (+0.15pp)",
+    )
+}
+
+#[tokio::test]
+async fn posts_discord_content() {
+    let webhook = MockWebhook::start().await;
+
+    Discord::new(webhook.url())
+        .notify(&message())
+        .await
+        .unwrap();
+
+    let received = webhook.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].method, "POST");
+    assert_eq!(received[0].header("content-type"), Some("application/json"));
+    assert_eq!(
+        received[0].json(),
+        serde_json::json!({
+            "content": "[ESM Checker]\nStats for 2022-01-20\ntype_module: +3 (+0.15pp)"
+        })
+    );
+}
+
+#[tokio::test]
+async fn posts_discord_embeds_for_a_stats_report() {
+    let webhook = MockWebhook::start().await;
+    let stats = |timestamp: &str, type_module: isize, esm_only: isize| StatsEntry {
+        timestamp: timestamp.to_owned(),
+        total_packages: 200,
+        type_module,
+        exports_no_require: esm_only,
+        esm_only,
+        cjs: 200 - esm_only,
+        ..StatsEntry::default()
+    };
+    let went_esm = AuditEntry {
+        package_name: String::from("chalk"),
+        timestamp: String::from("2022-01-18"),
+        change: String::from("exports_no_require"),
+        old_version: Some(String::from("4.1.2")),
+        new_version: Some(String::from("5.0.0")),
+        old_status: Some(PackageStatus::Cjs),
+        new_status: Some(PackageStatus::EsmOnly),
+        ..AuditEntry::default()
+    };
+    let report = StatsReport::new(stats("2022-01-20", 10, 20), &stats("2022-01-13", 12, 19))
+        .notable(esm_checker::notable_changes(&[went_esm]));
+
+    Discord::new(webhook.url())
+        .notify(&report.message())
+        .await
+        .unwrap();
+
+    let body = webhook.received()[0].json();
+    assert_eq!(body["content"], serde_json::Value::Null);
+    let stats = &body["embeds"][0];
+    assert_eq!(stats["title"], "ESM Checker");
+    assert_eq!(stats["description"], "Stats from 2022-01-13 to 2022-01-20");
+    // Adoption went up, so the embed is green
+    assert_eq!(stats["color"], 0x2ecc71);
+    assert_eq!(stats["fields"][0]["name"], "type_module");
+    assert_eq!(
+        stats["fields"][0]["value"],
+        "10 (5.0%)\n```diff\n- ▼ -2 (-1.00pp)\n```"
+    );
+    assert_eq!(
+        stats["fields"][2]["value"],
+        "20 (10.0%)\n```diff\n+ ▲ +1 (+0.50pp)\n```"
+    );
+    assert_eq!(stats["fields"][4]["name"], "ESM adoption");
+    assert_eq!(
+        body["embeds"][1]["description"],
+        "▲ chalk 4.1.2 -> 5.0.0 (2022-01-18): cjs -> esm-only"
+    );
+}
+
+#[tokio::test]
+async fn posts_slack_blocks() {
+    let webhook = MockWebhook::start().await;
+
+    Slack::new(webhook.url()).notify(&message()).await.unwrap();
+
+    let body = webhook.received()[0].json();
+    assert_eq!(body["blocks"][0]["text"]["text"], "ESM Checker");
+    assert_eq!(
+        body["blocks"][1]["text"]["text"],
+        "Stats for 2022-01-20\ntype_module: +3 (+0.15pp)"
+    );
+    assert!(body["text"].as_str().unwrap().starts_with("ESM Checker\n"));
+}
+
+#[tokio::test]
+async fn escapes_slack_control_characters() {
+    let webhook = MockWebhook::start().await;
+
+    Slack::new(webhook.url())
+        .notify(&Message::new("a & b", "<!channel> chalk -> esm-only"))
+        .await
+        .unwrap();
+
+    let body = webhook.received()[0].json();
+    assert_eq!(
+        body["blocks"][1]["text"]["text"],
+        "&lt;!channel&gt; chalk -&gt; esm-only"
+    );
+    assert_eq!(
+        body["text"],
+        "a &amp; b\n&lt;!channel&gt; chalk -&gt; esm-only"
+    );
+    assert_eq!(body["blocks"][0]["text"]["text"], "a & b");
+}
+
+#[tokio::test]
+async fn truncates_oversized_slack_blocks() {
+    let webhook = MockWebhook::start().await;
+    let changes: Vec<String> = (0..200)
+        .map(|i| format!("▲ package-{:03} 1.0.0 -> 2.0.0: cjs -> esm-only", i))
+        .collect();
+
+    Slack::new(webhook.url())
+        .notify(&Message::new(
+            "Quarterly stats ".repeat(20),
+            changes.join("\n"),
+        ))
+        .await
+        .unwrap();
+
+    let body = webhook.received()[0].json();
+    let header = body["blocks"][0]["text"]["text"].as_str().unwrap();
+    assert_eq!(header.chars().count(), 150);
+    assert!(header.ends_with('…'));
+    let section = body["blocks"][1]["text"]["text"].as_str().unwrap();
+    assert!(section.chars().count() <= 3000);
+    assert!(section.starts_with("▲ package-000"));
+    assert!(section.ends_with(" more"));
+}
+
+#[tokio::test]
+async fn posts_a_teams_message_card() {
+    let webhook = MockWebhook::start().await;
+
+    Teams::new(webhook.url()).notify(&message()).await.unwrap();
+
+    let body = webhook.received()[0].json();
+    assert_eq!(body["@type"], "MessageCard");
+    assert_eq!(body["title"], "ESM Checker");
+    assert_eq!(
+        body["text"],
+        "Stats for 2022-01-20\n\ntype_module: +3 (+0.15pp)"
+    );
+}
+
+#[tokio::test]
+async fn puts_a_matrix_room_message() {
+    let homeserver = MockWebhook::start().await;
+
+    Matrix::new(homeserver.url(), "!room:example.org", "secret")
+        .notify(&message())
+        .await
+        .unwrap();
+
+    let received = &homeserver.received()[0];
+    assert_eq!(received.method, "PUT");
+    assert!(received
+        .path
+        .starts_with("/_matrix/client/v3/rooms/%21room%3Aexample.org/send/m.room.message/"));
+    assert_eq!(received.header("authorization"), Some("Bearer secret"));
+    let body = received.json();
+    assert_eq!(body["msgtype"], "m.text");
+    assert_eq!(
+        body["formatted_body"],
+        "<strong>ESM Checker</strong><br>Stats for 2022-01-20<br>type_module: +3 (+0.15pp)"
+    );
+}
+
+#[tokio::test]
+async fn posts_the_message_to_a_generic_webhook() {
+    let webhook = MockWebhook::start().await;
+
+    Webhook::new(format!("{}/hooks/esm", webhook.url()))
+        .notify(&message())
+        .await
+        .unwrap();
+
+    let received = &webhook.received()[0];
+    assert_eq!(received.path, "/hooks/esm");
+    assert_eq!(
+        received.json(),
+        serde_json::json!({
+            "title": "ESM Checker",
+            "text": "Stats for 2022-01-20\ntype_module: +3 (+0.15pp)"
+        })
+    );
+}
+
+#[tokio::test]
+async fn keeps_notifying_past_a_failing_channel() {
+    let broken = MockWebhook::with_status(500).await;
+    let working = MockWebhook::start().await;
+    let notifiers: Vec<Box<dyn Notifier>> = vec![
+        Box::new(Slack::new(broken.url())),
+        Box::new(Discord::new(working.url())),
+    ];
+
+    let failures = notify_all(&notifiers, &message()).await;
+
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, "slack");
+    assert!(matches!(failures[0].1, Error::Status { status: 500, .. }));
+    assert_eq!(working.received().len(), 1);
+}
+
+#[tokio::test]
+async fn sends_channels_with_a_template_their_own_wording() {
+    let discord = MockWebhook::start().await;
+    let webhook = MockWebhook::start().await;
+    let notifiers: Vec<Box<dyn Notifier>> = vec![
+        Box::new(Discord::new(discord.url())),
+        Box::new(Webhook::new(webhook.url())),
+    ];
+    let template = fixtures_dir().join("templates/weekly.hbs");
+    let templates =
+        ChannelTemplates::from_args(&[format!("webhook={}", template.display())]).unwrap();
+    let stats = |timestamp: &str, esm_only: isize| StatsEntry {
+        timestamp: timestamp.to_owned(),
+        total_packages: 200,
+        type_module: esm_only,
+        esm_only,
+        cjs: 200 - esm_only,
+        ..StatsEntry::default()
+    };
+    let went_esm = AuditEntry {
+        package_name: String::from("chalk"),
+        timestamp: String::from("2022-01-18"),
+        change: String::from("type_module"),
+        new_version: Some(String::from("5.0.0")),
+        old_status: Some(PackageStatus::Cjs),
+        new_status: Some(PackageStatus::EsmOnly),
+        ..AuditEntry::default()
+    };
+    let report = StatsReport::new(stats("2022-01-20", 11), &stats("2022-01-13", 10))
+        .notable(esm_checker::notable_changes(&[went_esm]));
+
+    let messages = templates.messages(&notifiers, &report).unwrap();
+    let failures = notify_each(&notifiers, &messages).await;
+
+    assert!(failures.is_empty());
+    assert!(discord.received()[0].json()["embeds"].is_array());
+    assert_eq!(
+        webhook.received()[0].json()["text"],
+        "ESM adoption is 5.50% ▲ (+0.50pp since 2022-01-13)\n- type_module: 11 (+1)\n- exports_require: 0 (+0)\n- exports_no_require: 0 (+0)\n- cjs: 189 (-1)\n- dual: 0 (+0)\n- esm_only: 11 (+1)\n▲ chalk went esm-only in 5.0.0"
+    );
+}
+
+#[tokio::test]
+async fn emails_plain_text_and_html_versions() {
+    let smtp = MockSmtp::start().await;
+    let config = SmtpConfig {
+        host: String::from("127.0.0.1"),
+        port: Some(smtp.port()),
+        tls: SmtpTls::None,
+        credentials: None,
+        from: String::from("ESM Checker <esm-checker@example.com>"),
+    };
+    let email = Email::new(&config, &["alice@example.com", "bob@example.com"]).unwrap();
+    let message = message()
+        .summary("Stats from 2022-01-13 to 2022-01-20")
+        .events(vec![String::from("▲ <went-esm> cjs -> esm-only")]);
+
+    email.notify(&message).await.unwrap();
+
+    let received = smtp.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].from, "esm-checker@example.com");
+    assert_eq!(received[0].to, vec!["alice@example.com", "bob@example.com"]);
+
+    let data = &received[0].data;
+    assert!(data.contains("Subject: ESM Checker: Stats from 2022-01-13 to 2022-01-20"));
+    assert!(data.contains("Content-Type: multipart/alternative"));
+    assert!(data.contains("Content-Type: text/plain; charset=utf-8"));
+    assert!(data.contains("Content-Type: text/html; charset=utf-8"));
+    assert!(data.contains("type_module: +3 (+0.15pp)"));
+    assert!(data.contains("&lt;went-esm&gt;"));
+}
+
+#[test]
+fn renders_fields_and_events_as_html() {
+    let message = Message::new("ESM Checker", "Stats for 2022-01-20")
+        .field(
+            esm_checker::notify::Field::new("type_module", "10 (5.0%)")
+                .change("-2 (-1.00pp)", esm_checker::notify::Trend::Down),
+        )
+        .events(vec![String::from("▲ chalk 4.1.2 -> 5.0.0")]);
+
+    let html = Email::html_body(&message);
+
+    assert!(html.contains(
+        "<tr><th align=\"left\">type_module</th><td>10 (5.0%)</td><td><span style=\"color: #c0392b\">▼ -2 (-1.00pp)</span></td></tr>"
+    ));
+    assert!(html.contains("<li>▲ chalk 4.1.2 -&gt; 5.0.0</li>"));
+    assert!(!html.contains("<pre>"));
+}
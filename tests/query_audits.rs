@@ -0,0 +1,67 @@
+mod support;
+
+use esm_checker::{
+    store::{LocalStore, Store},
+    AuditEntry, AuditQuery,
+};
+use support::temp_dir;
+
+fn audit(package_name: &str, timestamp: &str, change: &str) -> AuditEntry {
+    AuditEntry {
+        package_name: package_name.to_owned(),
+        timestamp: timestamp.to_owned(),
+        change: change.to_owned(),
+        new_value: true,
+        ..AuditEntry::default()
+    }
+}
+
+#[tokio::test]
+async fn filters_by_package_date_range_and_change() {
+    let dir = temp_dir("query-audits");
+    let store = LocalStore::open(&dir).unwrap();
+    for entry in [
+        audit("chalk", "2021-11-26", "exports_no_require"),
+        audit("chalk", "2021-11-26", "type_module"),
+        audit("got", "2021-12-03", "type_module"),
+        audit("chalk-template", "2022-01-05", "type_module"),
+    ] {
+        store.put_audit(&entry).await.unwrap();
+    }
+
+    let query = |query: AuditQuery| {
+        let store = &store;
+        async move {
+            store
+                .query_audits(&query)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|a| format!("{} {} {}", a.timestamp, a.package_name, a.change))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(
+        query(AuditQuery {
+            package: Some(String::from("chalk")),
+            change: Some(String::from("exports_no_require")),
+            ..AuditQuery::default()
+        })
+        .await,
+        vec!["2021-11-26 chalk exports_no_require"]
+    );
+    assert_eq!(
+        query(AuditQuery {
+            from: Some(String::from("2021-12-01")),
+            to: Some(String::from("2022-01-05")),
+            ..AuditQuery::default()
+        })
+        .await,
+        vec![
+            "2021-12-03 got type_module",
+            "2022-01-05 chalk-template type_module"
+        ]
+    );
+    assert_eq!(query(AuditQuery::default()).await.len(), 4);
+}
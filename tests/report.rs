@@ -0,0 +1,93 @@
+mod support;
+
+use chrono::NaiveDate;
+use esm_checker::{
+    store::{LocalStore, Store},
+    AuditEntry, Error, PackageStatus, Period, StatsEntry, StatsReport,
+};
+use support::temp_dir;
+
+fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%F").unwrap()
+}
+
+fn stats(timestamp: &str, esm_only: isize) -> StatsEntry {
+    StatsEntry {
+        timestamp: timestamp.to_owned(),
+        total_packages: 100,
+        type_module: esm_only,
+        esm_only,
+        cjs: 100 - esm_only,
+        ..StatsEntry::default()
+    }
+}
+
+fn went_esm(package_name: &str, timestamp: &str) -> AuditEntry {
+    AuditEntry {
+        package_name: package_name.to_owned(),
+        timestamp: timestamp.to_owned(),
+        change: String::from("type_module"),
+        new_value: true,
+        old_status: Some(PackageStatus::Cjs),
+        new_status: Some(PackageStatus::EsmOnly),
+        ..AuditEntry::default()
+    }
+}
+
+#[tokio::test]
+async fn compares_the_ends_of_a_period_across_months() {
+    let dir = temp_dir("report");
+    let store = LocalStore::open(&dir).unwrap();
+    // Nothing was recorded on 2021-12-30, the day a week before the end
+    for entry in [
+        stats("2021-12-28", 8),
+        stats("2021-12-31", 10),
+        stats("2022-01-03", 11),
+        stats("2022-01-06", 13),
+    ] {
+        store.put_stats(&entry).await.unwrap();
+    }
+    for audit in [
+        went_esm("before", "2021-12-31"),
+        went_esm("chalk", "2022-01-02"),
+        went_esm("got", "2022-01-05"),
+    ] {
+        store.put_audit(&audit).await.unwrap();
+    }
+
+    let end = date("2022-01-06");
+    let report = StatsReport::between(&store, Period::Week.start(end).unwrap(), end)
+        .await
+        .unwrap()
+        .period(Period::Week);
+
+    assert_eq!(report.diff.since, "2021-12-31");
+    assert_eq!(report.diff.timestamp, "2022-01-06");
+    assert_eq!(report.diff.esm_only, 3);
+    let names: Vec<&str> = report
+        .notable
+        .iter()
+        .map(|c| c.package_name.as_str())
+        .collect();
+    assert_eq!(names, vec!["chalk", "got"]);
+    assert!(report
+        .message()
+        .text
+        .starts_with("Weekly stats from 2021-12-31 to 2022-01-06\n"));
+
+    let month = StatsReport::between(&store, Period::Month.start(end).unwrap(), end)
+        .await
+        .unwrap();
+    assert_eq!(month.diff.since, "2021-12-28");
+}
+
+#[tokio::test]
+async fn needs_stats_from_two_days() {
+    let dir = temp_dir("report-single-day");
+    let store = LocalStore::open(&dir).unwrap();
+    store.put_stats(&stats("2022-01-06", 13)).await.unwrap();
+
+    let report = StatsReport::between(&store, date("2021-12-30"), date("2022-01-06")).await;
+
+    assert!(matches!(report, Err(Error::Store(message)) if message.contains("found 1")));
+}
@@ -0,0 +1,105 @@
+mod support;
+
+use esm_checker::{
+    scan_registry, Error, ListFingerprint, ScanCheckpoint, ScanOptions, ScanStats, Source,
+};
+use std::path::PathBuf;
+use support::{fixtures_dir, MockRegistry};
+
+fn names(list: &[&str]) -> Vec<String> {
+    list.iter().map(|n| n.to_string()).collect()
+}
+
+fn checkpoint_path(test: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("esm-checker-{}-{}.json", test, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn scans_a_local_mirror_in_batches() {
+    let checkpoint = checkpoint_path("mirror-scan");
+    let options = ScanOptions::new(Source::Mirror(fixtures_dir().join("packages")), &checkpoint)
+        .batch_size(2);
+
+    let mut batches = vec![];
+    let result = scan_registry(
+        &names(&["cjs-only", "dual-mode", "esm-only", "missing", "versioned"]),
+        &options,
+        |c| batches.push(c.position),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(batches, vec![2, 4, 5]);
+    assert_eq!(
+        result.stats,
+        ScanStats {
+            scanned: 4,
+            failed: 1,
+            type_module: 1,
+            exports_require: 2,
+            exports_no_require: 1,
+        }
+    );
+    assert_eq!(ScanCheckpoint::load(&checkpoint).unwrap(), Some(result));
+
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[tokio::test]
+async fn resumes_from_a_checkpoint() {
+    let registry = MockRegistry::start().await;
+    let checkpoint = checkpoint_path("resume-scan");
+    let list = names(&["cjs-only", "dual-mode", "esm-only", "versioned"]);
+
+    ScanCheckpoint {
+        position: 2,
+        stats: ScanStats {
+            scanned: 2,
+            exports_require: 1,
+            ..ScanStats::default()
+        },
+        list: Some(ListFingerprint::of(&list)),
+    }
+    .save(&checkpoint)
+    .unwrap();
+
+    let options = ScanOptions::new(Source::Replicate(registry.url().to_owned()), &checkpoint);
+    let result = scan_registry(&list, &options, |_| {}).await.unwrap();
+
+    let mut requests = registry.requests();
+    requests.sort();
+    assert_eq!(requests, vec!["/esm-only", "/versioned"]);
+    assert_eq!(result.position, 4);
+    assert_eq!(result.stats.scanned, 4);
+    assert_eq!(result.stats.exports_require, 2);
+    assert_eq!(result.stats.exports_no_require, 1);
+
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[tokio::test]
+async fn refuses_to_resume_with_a_different_list() {
+    let checkpoint = checkpoint_path("changed-list-scan");
+    let options = ScanOptions::new(Source::Mirror(fixtures_dir().join("packages")), &checkpoint);
+
+    let saved = scan_registry(&names(&["cjs-only", "dual-mode"]), &options, |_| {})
+        .await
+        .unwrap();
+
+    // The same names in another order would resume at the wrong package just the same
+    let regenerated = names(&["dual-mode", "cjs-only"]);
+    assert_ne!(
+        ListFingerprint::of(&regenerated),
+        saved.list.clone().unwrap()
+    );
+    match scan_registry(&regenerated, &options, |_| {}).await {
+        Err(Error::Config(message)) => assert!(message.contains("different list of 2 packages")),
+        other => panic!("expected a configuration error, got {:?}", other),
+    }
+    assert_eq!(ScanCheckpoint::load(&checkpoint).unwrap(), Some(saved));
+
+    std::fs::remove_file(checkpoint).unwrap();
+}
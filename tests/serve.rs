@@ -0,0 +1,174 @@
+mod support;
+
+use esm_checker::{
+    store::{LocalStore, Store},
+    AuditEntry, Badge, Package, PackageStatus, Server, Source, StatsEntry,
+};
+use serde_json::Value;
+use std::{net::TcpListener, sync::Arc};
+use support::{temp_dir, MockRegistry};
+use tokio::sync::oneshot;
+
+async fn start(store: LocalStore) -> (String, oneshot::Sender<()>) {
+    start_server(Server::new(Arc::new(store))).await
+}
+
+async fn start_server(server: Server) -> (String, oneshot::Sender<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (stop, stopped) = oneshot::channel::<()>();
+
+    tokio::spawn(server.run(listener, async {
+        stopped.await.ok();
+    }));
+    (url, stop)
+}
+
+async fn get_json(url: &str) -> (u16, Value) {
+    let res = reqwest::get(url).await.unwrap();
+    let status = res.status().as_u16();
+    assert_eq!(res.headers()["access-control-allow-origin"], "*");
+    (
+        status,
+        serde_json::from_str(&res.text().await.unwrap()).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn serves_badges_from_the_store() {
+    let store = LocalStore::open(temp_dir("serve-badges")).unwrap();
+    let nanoid = Package {
+        name: String::from("@scope/nanoid"),
+        exports_require: true,
+        ..Package::default()
+    };
+    store.put_package(&nanoid).await.unwrap();
+    let (url, _stop) = start(store).await;
+
+    for path in ["/badge/@scope/nanoid.svg", "/badge/%40scope%2Fnanoid.svg"] {
+        let res = reqwest::get(format!("{}{}", url, path)).await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "image/svg+xml");
+        assert_eq!(res.text().await.unwrap(), Badge::for_package(&nanoid).svg());
+    }
+
+    let unknown = reqwest::get(format!("{}/badge/left-pad.svg", url))
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), 200);
+    assert!(unknown.text().await.unwrap().contains("ESM: unknown"));
+
+    let missing = reqwest::get(format!("{}/nothing", url)).await.unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn serves_stats_packages_and_audits_as_json() {
+    let store = LocalStore::open(temp_dir("serve-api")).unwrap();
+    for (timestamp, esm_only) in [("2022-01-01", 10), ("2022-01-02", 11), ("2022-01-03", 12)] {
+        let entry = StatsEntry {
+            timestamp: timestamp.to_owned(),
+            esm_only,
+            ..StatsEntry::default()
+        };
+        store.put_stats(&entry).await.unwrap();
+    }
+    for (name, exports_require, exports_no_require) in [
+        ("chalk", false, true),
+        ("chalk-template", false, true),
+        ("nanoid", true, false),
+    ] {
+        let pkg = Package {
+            name: name.to_owned(),
+            exports_require,
+            exports_no_require,
+            ..Package::default()
+        };
+        store.put_package(&pkg).await.unwrap();
+    }
+    store
+        .put_audit(&AuditEntry {
+            package_name: String::from("chalk"),
+            timestamp: String::from("2022-01-02"),
+            change: String::from("exports_no_require"),
+            old_status: Some(PackageStatus::Cjs),
+            new_status: Some(PackageStatus::EsmOnly),
+            ..AuditEntry::default()
+        })
+        .await
+        .unwrap();
+    let (url, _stop) = start(store).await;
+
+    let (status, stats) = get_json(&format!("{}/api/stats?from=2022-01-02", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(stats.as_array().unwrap().len(), 2);
+    let (_, latest) = get_json(&format!("{}/api/stats/latest", url)).await;
+    assert_eq!(latest["esm_only"], 12);
+
+    let (_, page) = get_json(&format!("{}/api/packages?q=CHALK&limit=1", url)).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["packages"][0]["name"], "chalk");
+    let (_, dual) = get_json(&format!("{}/api/packages?status=dual", url)).await;
+    assert_eq!(dual["packages"][0]["name"], "nanoid");
+    let (status, nanoid) = get_json(&format!("{}/api/packages/nanoid", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(nanoid["exports_require"], true);
+
+    let (_, audits) = get_json(&format!(
+        "{}/api/audits?package=chalk&transition=adoption",
+        url
+    ))
+    .await;
+    assert_eq!(audits["total"], 1);
+    assert_eq!(audits["audits"][0]["change"], "exports_no_require");
+    // Without a package or first day, the 30 days up to `to` are looked up
+    let (_, recent) = get_json(&format!("{}/api/audits?to=2022-01-31", url)).await;
+    assert_eq!(recent["total"], 1);
+    let (_, older) = get_json(&format!("{}/api/audits?to=2022-03-01", url)).await;
+    assert_eq!(older["total"], 0);
+    let (status, _) = get_json(&format!("{}/api/audits?limit=5000", url)).await;
+    assert_eq!(status, 400);
+
+    // `+` is only a space in the query string
+    let (status, build) = get_json(&format!("{}/api/packages/nanoid+build", url)).await;
+    assert_eq!(status, 404);
+    assert_eq!(build["error"], "nanoid+build isn't tracked");
+    let (_, spaced) = get_json(&format!("{}/api/packages?q=chalk+", url)).await;
+    assert_eq!(spaced["total"], 0);
+
+    let (status, missing) = get_json(&format!("{}/api/packages/left-pad", url)).await;
+    assert_eq!(status, 404);
+    assert_eq!(missing["error"], "left-pad isn't tracked");
+    let (status, invalid) = get_json(&format!("{}/api/stats?from=yesterday", url)).await;
+    assert_eq!(status, 400);
+    assert!(invalid["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid from"));
+}
+
+#[tokio::test]
+async fn checks_packages_live_from_the_registry() {
+    let registry = MockRegistry::start().await;
+    let store = LocalStore::open(temp_dir("serve-check")).unwrap();
+    let server = Server::new(Arc::new(store)).source(Source::Registry(registry.url().to_owned()));
+    let (url, _stop) = start_server(server).await;
+
+    let (status, dual) = get_json(&format!("{}/api/check/dual-mode", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(dual["status"], "dual");
+    assert_eq!(dual["package"]["version"], "2.0.0");
+    assert_eq!(dual["subpaths"][0]["conditions"][1], "require");
+    assert_eq!(dual["reasons"].as_array().unwrap().len(), 3);
+
+    let (status, pinned) = get_json(&format!("{}/api/check/versioned@next", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(pinned["requested"], "versioned@next");
+    assert_eq!(pinned["status"], "esm-only");
+
+    let (status, missing) = get_json(&format!("{}/api/check/does-not-exist", url)).await;
+    assert_eq!(status, 404);
+    assert_eq!(missing["error"], "does-not-exist has been unpublished");
+    let (status, _) = get_json(&format!("{}/api/check/malformed", url)).await;
+    assert_eq!(status, 502);
+}
@@ -0,0 +1,281 @@
+//! A small stand-in for the unpkg CDN and the npm registry so `generate_packages` can be exercised
+//! offline.
+//!
+//! Requests are accepted in the unpkg, npm registry and CouchDB replica layouts. Each is answered
+//! with the matching file in `tests/fixtures/packages`, or a 404 when there is no fixture.
+//! Registry and replica requests are answered from `tests/fixtures/registry` first, which holds
+//! registry documents with what only the registry knows, such as publish times and deprecations.
+//! Like npm, a registry request for a single version gets just that version's manifest.
+//! Redirects and slow responses can be configured per package through [`MockRegistryBuilder`].
+//!
+//! [`MockWebhook`] and [`MockSmtp`] stand in for the endpoints and mail server notifications are
+//! sent to.
+
+#![allow(dead_code)]
+
+mod smtp;
+mod webhook;
+
+#[allow(unused_imports)]
+pub use smtp::{MockSmtp, ReceivedEmail};
+#[allow(unused_imports)]
+pub use webhook::{MockWebhook, Received};
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+pub struct MockRegistry {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+#[derive(Default, Clone)]
+pub struct MockRegistryBuilder {
+    redirects: HashMap<String, String>,
+    delays: HashMap<String, Duration>,
+    failures: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl MockRegistryBuilder {
+    /// Answer requests for `from` with a `302` pointing at the `package.json` of `to`.
+    pub fn redirect(mut self, from: &str, to: &str) -> Self {
+        self.redirects.insert(from.to_owned(), to.to_owned());
+        self
+    }
+
+    /// Wait for `delay` before answering requests for `package`.
+    pub fn delay(mut self, package: &str, delay: Duration) -> Self {
+        self.delays.insert(package.to_owned(), delay);
+        self
+    }
+
+    /// Answer the first `times` requests for `package` with a `500`.
+    pub fn fail(self, package: &str, times: usize) -> Self {
+        self.failures
+            .lock()
+            .unwrap()
+            .insert(package.to_owned(), times);
+        self
+    }
+
+    pub async fn start(self) -> MockRegistry {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let routes = Arc::new(self);
+        let log = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+                tokio::spawn(handle(stream, routes.clone(), log.clone()));
+            }
+        });
+
+        MockRegistry { url, requests }
+    }
+}
+
+impl MockRegistry {
+    pub fn builder() -> MockRegistryBuilder {
+        MockRegistryBuilder::default()
+    }
+
+    pub async fn start() -> MockRegistry {
+        Self::builder().start().await
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The paths of every request received so far, in arrival order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// A fresh, empty directory unique to `name` and this test run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("esm-checker-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    routes: Arc<MockRegistryBuilder>,
+    log: Arc<Mutex<Vec<String>>>,
+) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&buf).to_string();
+    let path = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/")
+        .to_owned();
+    log.lock().unwrap().push(path.clone());
+
+    if let Some(response) = changes_feed(&path) {
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+        return;
+    }
+
+    let package = package_from_path(&path);
+
+    let response = match package {
+        Some(package) => {
+            if let Some(delay) = routes.delays.get(&package) {
+                tokio::time::sleep(*delay).await;
+            }
+
+            let failing = match routes.failures.lock().unwrap().get_mut(&package) {
+                Some(times) if *times > 0 => {
+                    *times -= 1;
+                    true
+                }
+                _ => false,
+            };
+
+            if failing {
+                respond(500, "Internal Server Error", "")
+            } else if let Some(target) = routes.redirects.get(&package) {
+                format!(
+                    "HTTP/1.1 302 Found\r\nLocation: /{}@latest/package.json\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    target
+                )
+            } else {
+                match fixture(&path, &package) {
+                    Some(body) => respond(200, "OK", &body),
+                    None => respond(404, "Not Found", &format!("Not found: {}", path)),
+                }
+            }
+        }
+        None => respond(404, "Not Found", &format!("Not found: {}", path)),
+    };
+
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Answers requests for the database info (`/`) and `_changes` feed of a CouchDB replica from the
+/// changes recorded in `tests/fixtures/changes.json`.
+fn changes_feed(path: &str) -> Option<String> {
+    let recorded: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(fixtures_dir().join("changes.json")).unwrap(),
+    )
+    .unwrap();
+
+    if path == "/" {
+        let info = serde_json::json!({ "db_name": "registry", "update_seq": recorded["last_seq"] });
+        return Some(respond(200, "OK", &info.to_string()));
+    }
+
+    let query = path.strip_prefix("/_changes?")?;
+    let param = |name: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    let since = param("since").unwrap_or_default();
+    let limit = param("limit").unwrap_or(u64::MAX) as usize;
+
+    let results: Vec<&serde_json::Value> = recorded["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|c| c["seq"].as_u64().unwrap() > since)
+        .take(limit)
+        .collect();
+    let last_seq = results.last().map_or(since, |c| c["seq"].as_u64().unwrap());
+    let body = serde_json::json!({ "results": results, "last_seq": last_seq });
+    Some(respond(200, "OK", &body.to_string()))
+}
+
+/// The body to answer `path` for `package` with, if there's a fixture for it.
+fn fixture(path: &str, package: &str) -> Option<String> {
+    let read = |dir: &str| {
+        std::fs::read_to_string(fixtures_dir().join(dir).join(format!("{}.json", package))).ok()
+    };
+    if path.ends_with("/package.json") {
+        return read("packages");
+    }
+
+    let body = read("registry").or_else(|| read("packages"))?;
+    // Whatever follows the name in `/{name}/{version}` picks a single version of a document
+    let version = path
+        .trim_start_matches('/')
+        .replace("%2f", "/")
+        .strip_prefix(package)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .filter(|v| !v.is_empty())
+        .map(str::to_owned);
+    let document: serde_json::Value = match (&version, serde_json::from_str(&body)) {
+        (Some(_), Ok(document)) => document,
+        _ => return Some(body),
+    };
+    let versions = match document.get("versions") {
+        Some(versions) => versions,
+        None => return Some(body),
+    };
+
+    let version = version.unwrap();
+    let version = document["dist-tags"][&version].as_str().unwrap_or(&version);
+    versions.get(version).map(|manifest| manifest.to_string())
+}
+
+/// Extracts the package name from an unpkg (`/{name}@{version}/package.json`), registry
+/// (`/{name}/{version}`) or CouchDB replica (`/{name}`) style path.
+fn package_from_path(path: &str) -> Option<String> {
+    let path = path.trim_start_matches('/').replace("%2f", "/");
+
+    if let Some(spec) = path.strip_suffix("/package.json") {
+        let at = spec.char_indices().skip(1).find(|&(_, c)| c == '@')?.0;
+        return Some(spec[..at].to_owned());
+    }
+
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let first = segments.next()?;
+    if first.starts_with('@') {
+        Some(format!("{}/{}", first, segments.next()?))
+    } else {
+        Some(first.to_owned())
+    }
+}
+
+fn respond(status: u16, reason: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )
+}
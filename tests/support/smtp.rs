@@ -0,0 +1,97 @@
+//! A stand-in for an SMTP server, like MailHog, which accepts every email without encryption or
+//! authentication and keeps it for inspection.
+
+use std::sync::{Arc, Mutex};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// An email received by [`MockSmtp`].
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    /// The raw message, headers and body, as sent after `DATA`.
+    pub data: String,
+}
+
+pub struct MockSmtp {
+    port: u16,
+    received: Arc<Mutex<Vec<ReceivedEmail>>>,
+}
+
+impl MockSmtp {
+    pub async fn start() -> MockSmtp {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+                tokio::spawn(handle(stream, log.clone()));
+            }
+        });
+
+        MockSmtp { port, received }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Every email received so far, in arrival order.
+    pub fn received(&self) -> Vec<ReceivedEmail> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn handle(stream: TcpStream, log: Arc<Mutex<Vec<ReceivedEmail>>>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let mut email = ReceivedEmail::default();
+
+    let _ = write.write_all(b"220 localhost mock SMTP\r\n").await;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 localhost\r\n"
+        } else if command.starts_with("MAIL FROM:") {
+            email.from = address(&line);
+            b"250 OK\r\n"
+        } else if command.starts_with("RCPT TO:") {
+            email.to.push(address(&line));
+            b"250 OK\r\n"
+        } else if command == "DATA" {
+            let _ = write
+                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                .await;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                email.data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                email.data.push('\n');
+            }
+            log.lock().unwrap().push(std::mem::take(&mut email));
+            b"250 OK: queued\r\n"
+        } else if command == "QUIT" {
+            let _ = write.write_all(b"221 Bye\r\n").await;
+            return;
+        } else {
+            b"250 OK\r\n"
+        };
+        let _ = write.write_all(reply).await;
+    }
+}
+
+fn address(line: &str) -> String {
+    let start = line.find('<').map_or(0, |i| i + 1);
+    let end = line.rfind('>').unwrap_or(line.len());
+    line[start..end].to_owned()
+}